@@ -0,0 +1,446 @@
+// Stable codes for every diagnostic. Message wording may change between releases, codes
+// never do: retire a code rather than reuse it, and only ever append to `CODES`.
+// L00xx come from the scanner, L01xx from the parser, L02xx from the resolver and L03xx from
+// the evaluator.
+
+pub const UNTERMINATED_STRING: &str = "L0001";
+pub const UNTERMINATED_BLOCK_COMMENT: &str = "L0002";
+pub const INVALID_NUMBER: &str = "L0003";
+pub const RESERVED_WORD: &str = "L0004";
+pub const UNEXPECTED_CHARACTER: &str = "L0005";
+pub const UNEXPECTED_END_OF_FILE: &str = "L0006";
+pub const UNREADABLE_SOURCE: &str = "L0007";
+
+pub const EXPECTED_EXPRESSION: &str = "L0100";
+pub const UNCLOSED_GROUPING: &str = "L0101";
+pub const MISSPELLED_KEYWORD: &str = "L0102";
+pub const MISSING_LEFT_OPERAND: &str = "L0103";
+pub const UNFINISHED_CONDITIONAL: &str = "L0104";
+pub const DUPLICATE_PARAMETER: &str = "L0105";
+pub const LOOP_CONTROL_OUTSIDE_LOOP: &str = "L0106";
+pub const UNCLOSED_BRACKET: &str = "L0107";
+pub const MALFORMED_MAP: &str = "L0108";
+pub const INVALID_MAP_KEY: &str = "L0109";
+pub const INVALID_ASSIGNMENT_TARGET: &str = "L0110";
+pub const MISSING_SEMICOLON: &str = "L0111";
+pub const EXPECTED_NAME: &str = "L0112";
+pub const UNCLOSED_BLOCK: &str = "L0113";
+pub const EXPECTED_PARENTHESIS: &str = "L0114";
+pub const RETURN_OUTSIDE_FUNCTION: &str = "L0115";
+
+pub const UNDECLARED_VARIABLE: &str = "L0200";
+
+pub const OPERAND_TYPE: &str = "L0300";
+pub const UNDEFINED_VARIABLE: &str = "L0301";
+pub const DIVISION_BY_ZERO: &str = "L0302";
+pub const NOT_CALLABLE: &str = "L0303";
+pub const WRONG_ARGUMENT_COUNT: &str = "L0304";
+pub const STACK_OVERFLOW: &str = "L0305";
+pub const INDEX_OUT_OF_BOUNDS: &str = "L0306";
+pub const INVALID_INDEX: &str = "L0307";
+pub const NOT_INDEXABLE: &str = "L0308";
+pub const NOT_ITERABLE: &str = "L0309";
+pub const ARGUMENT_TYPE: &str = "L0310";
+pub const MAP_KEY_TYPE: &str = "L0311";
+pub const MISSING_KEY: &str = "L0312";
+
+pub struct Code {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+pub const CODES: &[Code] = &[
+    Code {
+        code: UNTERMINATED_STRING,
+        title: "unterminated string",
+        explanation: "A string literal was opened with `\"` but the file ended before the closing quote.
+
+Strings may span several lines, so the missing quote is often far above where the
+error is reported.
+
+    print \"hello;      // error: the string runs to the end of the file
+    print \"hello\";     // ok",
+    },
+    Code {
+        code: UNTERMINATED_BLOCK_COMMENT,
+        title: "unterminated block comment",
+        explanation: "A `/*` comment was never closed with `*/`.
+
+    /* the rest of the file is a comment
+    var x = 1;
+
+Close the comment, or use `//` for a single-line comment.",
+    },
+    Code {
+        code: INVALID_NUMBER,
+        title: "invalid number",
+        explanation: "A number literal could not be read as a number.
+
+Numbers are digits with an optional fractional part: `12`, `3.5`. A trailing dot is not
+part of the number, so `1.` is the number `1` followed by `.`.",
+    },
+    Code {
+        code: RESERVED_WORD,
+        title: "reserved word",
+        explanation: "The word is reserved by the active dialect and cannot be used as a name.
+
+Dialects reserve words they plan to give a meaning later. Pick a different name:
+
+    var async = 1;     // error when the dialect reserves `async`
+    var isAsync = 1;   // ok",
+    },
+    Code {
+        code: UNEXPECTED_CHARACTER,
+        title: "unexpected character",
+        explanation: "The scanner found a character that does not start any token, such as `@` or `#`
+outside a string or comment. The character is skipped and scanning carries on.
+
+    var total = 1 @ 2;   // error
+    var total = 1 + 2;   // ok",
+    },
+    Code {
+        code: UNEXPECTED_END_OF_FILE,
+        title: "unexpected end of file",
+        explanation: "The scanner was asked for a character after the end of the input. This is an internal
+error: please report it along with the program that triggered it.",
+    },
+    Code {
+        code: UNREADABLE_SOURCE,
+        title: "could not read source",
+        explanation: "Reading the program failed partway through, for example because the file is not valid
+UTF-8 or the stream was closed. Scanning stops at the point of failure.",
+    },
+    Code {
+        code: EXPECTED_EXPRESSION,
+        title: "expected expression",
+        explanation: "The parser needed an expression, such as a number, a name or a parenthesized
+expression, and found something else.
+
+    1 + ;      // error: nothing after `+`
+    1 + 2;     // ok",
+    },
+    Code {
+        code: UNCLOSED_GROUPING,
+        title: "unclosed grouping",
+        explanation: "A `(` was opened but the expression inside it was not followed by `)`.
+
+    (1 + 2 * 3;     // error
+    (1 + 2) * 3;    // ok",
+    },
+    Code {
+        code: MISSPELLED_KEYWORD,
+        title: "misspelled keyword",
+        explanation: "A name was followed directly by another operand, which is never valid, and the name is
+one or two edits away from a keyword. It is almost certainly a typo for that keyword.
+
+    retrun x;    // error: did you mean `return`?
+    return x;    // ok",
+    },
+    Code {
+        code: MISSING_LEFT_OPERAND,
+        title: "missing left operand",
+        explanation: "A binary operator appeared where an expression should start, so it has nothing on its
+left. The operator is skipped and the operand after it takes the place of the whole
+operation, so `+ 1 == 2` goes on parsing as `1 == 2` and later errors are still found.
+
+    == 2         // error
+    x == 2       // ok
+
+`-` is not reported here: a leading `-` is negation.",
+    },
+    Code {
+        code: UNFINISHED_CONDITIONAL,
+        title: "unfinished conditional",
+        explanation: "A conditional expression `condition ? then : else` has its `?` and then branch but no
+`:` and else branch. Both branches are required.
+
+    ready ? start()          // error
+    ready ? start() : wait() // ok",
+    },
+    Code {
+        code: DUPLICATE_PARAMETER,
+        title: "duplicate parameter",
+        explanation: "A function lists the same parameter name twice, so the second would hide the first.
+
+    (a, a) => a * 2    // error
+    (a, b) => a * b    // ok",
+    },
+    Code {
+        code: LOOP_CONTROL_OUTSIDE_LOOP,
+        title: "break or continue outside of a loop",
+        explanation: "`break` and `continue` only make sense inside the body of a `while` or `for` loop.
+A function body starts afresh: a loop around the function does not count.
+
+    break;                        // error
+    while (true) { break; }       // ok
+
+In a `for` loop, `continue` still runs the increment clause before the next check.",
+    },
+    Code {
+        code: UNCLOSED_BRACKET,
+        title: "unclosed bracket",
+        explanation: "A list literal or an index was opened with `[` but not closed with `]`.
+
+    [1, 2, 3      // error
+    xs[0          // error
+    [1, 2, 3]     // ok",
+    },
+    Code {
+        code: MALFORMED_MAP,
+        title: "malformed map literal",
+        explanation: "A map literal is a `{`, then `key: value` entries separated by commas, then `}`. Either
+a key was not followed by `:` or the closing `}` is missing.
+
+    {\"host\" \"x\"}          // error: missing ':'
+    {\"host\": \"x\"          // error: missing '}'
+    {\"host\": \"x\", \"port\": 80}   // ok
+
+At the start of a statement `{` opens a block, so a map used there must be wrapped in
+parentheses.
+
+    {\"k\": 1}[\"k\"];     // error
+    ({\"k\": 1})[\"k\"];   // ok",
+    },
+    Code {
+        code: INVALID_MAP_KEY,
+        title: "invalid map key",
+        explanation: "Map keys must be strings, numbers or booleans. `nil`, lists, maps and functions can
+never be keys, so a literal of one of those in key position is rejected before the
+program runs.
+
+    {nil: 1}       // error
+    {(a, []): 1}   // error: the comma operator yields the list
+    {\"none\": 1}    // ok
+
+Keys that are only sometimes invalid, like `(c ? nil : 1)`, are checked when the map is built
+and reported as L0311.",
+    },
+    Code {
+        code: INVALID_ASSIGNMENT_TARGET,
+        title: "invalid assignment target",
+        explanation: "The left side of `=` is not something a value can be stored in. Only variables
+and list and map elements can be assigned to.
+
+    x = 1          // ok
+    xs[0] = 1      // ok
+    xs[0] + 1 = 2  // error
+    f() = 3        // error",
+    },
+    Code {
+        code: MISSING_SEMICOLON,
+        title: "missing semicolon",
+        explanation: "Expression, `print`, `var` and `return` statements end with `;`.
+
+    print 1        // error
+    print 1;       // ok",
+    },
+    Code {
+        code: EXPECTED_NAME,
+        title: "expected a name",
+        explanation: "`var` and `fun` must be followed by the name being declared.
+
+    var = 1;       // error
+    var x = 1;     // ok",
+    },
+    Code {
+        code: UNCLOSED_BLOCK,
+        title: "unclosed block",
+        explanation: "A block or function body was opened with `{` but not closed with `}`, or a function
+declaration has no body at all.
+
+    { print 1;         // error
+    fun f() print 1;   // error
+    fun f() { print 1; }   // ok",
+    },
+    Code {
+        code: EXPECTED_PARENTHESIS,
+        title: "expected a parenthesis",
+        explanation: "The condition of an `if` and the parameters of a function are written in parentheses.
+
+    if x print 1;        // error
+    if (x) print 1;      // ok",
+    },
+    Code {
+        code: RETURN_OUTSIDE_FUNCTION,
+        title: "return outside of a function",
+        explanation: "`return` only makes sense inside a function body; top-level code has nothing to
+return to.
+
+    return 1;                  // error
+    fun f() { return 1; }      // ok",
+    },
+    Code {
+        code: UNDECLARED_VARIABLE,
+        title: "undeclared variable",
+        explanation: "A variable is read or assigned, but no scope around it declares that name. This is
+a warning: the program still runs, and fails with L0301 only if it reaches the name. When a
+declared name is close in spelling, it is suggested.
+
+    var counter = 0;
+    print countr;              // warning: did you mean `counter`?
+
+A function body may use a name that its enclosing scope declares after it, since the body
+only runs when the function is called:
+
+    fun isEven(n) { return n == 0 or isOdd(n - 1); }
+    fun isOdd(n) { return n != 0 and isEven(n - 1); }   // ok",
+    },
+    Code {
+        code: OPERAND_TYPE,
+        title: "wrong operand type",
+        explanation: "An operator was applied to a value it does not work on. Arithmetic and comparisons
+need numbers; `+` also joins two strings. Nothing is converted implicitly.
+
+    1 + \"2\"      // error
+    -\"x\"         // error
+    1 + 2        // ok
+    \"a\" + \"b\"    // ok",
+    },
+    Code {
+        code: UNDEFINED_VARIABLE,
+        title: "undefined variable",
+        explanation: "A variable was read or assigned before any `var` declared it in an enclosing scope.
+
+    print x;          // error
+    var x = 1;
+    print x;          // ok",
+    },
+    Code {
+        code: DIVISION_BY_ZERO,
+        title: "integer division by zero",
+        explanation: "`~/` divides and rounds down to a whole number, which has no answer for a zero
+divisor. `/` follows floating point and gives infinity or NaN instead.
+
+    1 ~/ 0        // error
+    1 / 0         // infinity",
+    },
+    Code {
+        code: NOT_CALLABLE,
+        title: "value is not callable",
+        explanation: "Something other than a function was called. Only functions, whether declared with
+`fun name(...)`, written as `fun (...) { ... }` or as `(...) => ...`, can be called.
+
+    var x = 1;
+    x();                       // error
+    var f = (a) => a + 1;
+    f(x);                      // ok",
+    },
+    Code {
+        code: WRONG_ARGUMENT_COUNT,
+        title: "wrong number of arguments",
+        explanation: "A function was called with more or fewer arguments than it has parameters. Lox has
+no default or variadic parameters, so the counts must match exactly.
+
+    fun add(a, b) { return a + b; }
+    add(1);                    // error
+    add(1, 2);                 // ok",
+    },
+    Code {
+        code: STACK_OVERFLOW,
+        title: "calls nested too deeply",
+        explanation: "Function calls nested deeper than the interpreter allows, almost always because a
+recursive function never reaches its base case.
+
+    fun loop(n) { return loop(n + 1); }
+    loop(0);                   // error",
+    },
+    Code {
+        code: INDEX_OUT_OF_BOUNDS,
+        title: "list index out of bounds",
+        explanation: "A list was indexed, or changed by `insert`, `remove`, `pop` or `slice`, at a position
+it does not have. Positions start at 0 and end one before `len(xs)`; there are no negative
+positions counting from the end.
+
+    var xs = [1, 2, 3];
+    xs[3];                     // error
+    pop([]);                   // error
+    xs[len(xs) - 1];           // ok",
+    },
+    Code {
+        code: INVALID_INDEX,
+        title: "list index is not a whole number",
+        explanation: "A list was indexed with something other than a whole number.
+
+    var xs = [1, 2, 3];
+    xs[\"0\"];                   // error
+    xs[1.5];                   // error
+    xs[1];                     // ok",
+    },
+    Code {
+        code: NOT_INDEXABLE,
+        title: "value cannot be indexed",
+        explanation: "`[...]` was applied to a value that is not a list or a map.
+
+    var n = 1;
+    n[0];                      // error
+    [n][0];                    // ok
+    {\"n\": n}[\"n\"];             // ok",
+    },
+    Code {
+        code: NOT_ITERABLE,
+        title: "value cannot be looped over",
+        explanation: "`for (var x : ...)` was given a value that is not a list or a map. A loop over a map
+sees its keys, in the order they were first inserted.
+
+    for (var x : 3) print x;          // error
+    for (var x : [1, 2, 3]) print x;  // ok
+    for (var k : {\"a\": 1}) print k;  // ok",
+    },
+    Code {
+        code: ARGUMENT_TYPE,
+        title: "wrong argument type",
+        explanation: "A built-in function was called with an argument of a type it does not accept.
+
+    len(3);                    // error
+    push(\"ab\", \"c\");           // error
+    len(\"abc\");                // ok",
+    },
+    Code {
+        code: MAP_KEY_TYPE,
+        title: "invalid map key",
+        explanation: "A map was built, indexed or asked about with a key that is not a string, number or
+boolean. Literal keys that can never be valid are caught before the program runs (L0109);
+this is the same rule for keys only known when the program runs.
+
+    var k = nil;
+    var m = {k: 1};            // error
+    m = {\"k\": 1};
+    m[[]];                     // error
+    has(m, \"k\");               // ok",
+    },
+    Code {
+        code: MISSING_KEY,
+        title: "key not in map",
+        explanation: "A map was indexed, or `remove` was called, with a key the map does not hold. Check
+with `has` first, or assign to the key to add it.
+
+    var m = {\"host\": \"x\"};
+    m[\"port\"];                 // error
+    m[\"port\"] = 80;            // ok: adds the key
+    has(m, \"user\");            // ok: false",
+    },
+];
+
+pub fn lookup(code: &str) -> Option<&'static Code> {
+    return CODES.iter().find(|entry| entry.code == code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_unique() {
+        for (index, entry) in CODES.iter().enumerate() {
+            assert!(CODES[index + 1..].iter().all(|other| other.code != entry.code), "{}", entry.code);
+            assert!(entry.code.len() == 5 && entry.code.starts_with('L'));
+        }
+        assert_eq!(lookup("L0001").unwrap().title, "unterminated string");
+        // every code falls in the range of one of the four stages
+        for entry in CODES {
+            assert!(["L00", "L01", "L02", "L03"].contains(&&entry.code[..3]), "{}", entry.code);
+        }
+        assert!(lookup("L9999").is_none());
+    }
+}
@@ -0,0 +1,414 @@
+#![allow(clippy::needless_return)]
+pub mod codes;
+pub mod suggest;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// Identifies a file registered in a source map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+impl FileId {
+    // for source maps and deserializers; anything else should get ids from a source map
+    pub fn from_index(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        return self.0 as usize;
+    }
+}
+
+// Byte offsets of a piece of source in its file, end exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self::in_file(FileId::default(), start, end)
+    }
+
+    pub fn in_file(file: FileId, start: usize, end: usize) -> Self {
+        Self { file, start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.end - self.start;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.start == self.end;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Help,
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Help => "help",
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+const HELP: &str = "help: ";
+
+// `note: ...`, or `help: ...` for notes added with `with_help`
+fn note_line(note: &str) -> String {
+    if note.starts_with(HELP) {
+        return note.to_string();
+    }
+    return format!("note: {}", note);
+}
+
+// A secondary span with its own explanation, e.g. where an unclosed paren was opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub primary_span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: &str, primary_span: Span) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.to_string(),
+            primary_span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: &str, primary_span: Span) -> Self {
+        return Self::new(Severity::Error, message, primary_span);
+    }
+
+    pub fn warning(message: &str, primary_span: Span) -> Self {
+        return Self::new(Severity::Warning, message, primary_span);
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    // a note telling the user what to do about it, shown as `help:` rather than `note:`
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.notes.push(format!("{}{}", HELP, help));
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        return self.severity == Severity::Error;
+    }
+}
+
+// Where the scanner and parser send what they find. Libraries only ever emit into a sink;
+// deciding whether anything reaches stderr is up to the embedder.
+pub trait DiagnosticSink {
+    fn emit(&mut self, diagnostic: Diagnostic);
+}
+
+// the simplest sink: keep everything, in order
+impl DiagnosticSink for Vec<Diagnostic> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        self.push(diagnostic);
+    }
+}
+
+// Shared by every stage of one run, so messages come out in the order they were found.
+pub type SharedSink = Rc<RefCell<dyn DiagnosticSink>>;
+
+pub fn collector() -> Rc<RefCell<Vec<Diagnostic>>> {
+    return Rc::new(RefCell::new(Vec::new()));
+}
+
+// What rendering needs to know about the files spans point into.
+pub trait Files {
+    fn path(&self, file: FileId) -> &str;
+    // 1-based line and column of a byte offset
+    fn line_column(&self, file: FileId, offset: usize) -> (usize, usize);
+    // text of a 1-based line, without its line ending
+    fn line_text(&self, file: FileId, line: usize) -> &str;
+}
+
+// one line per diagnostic, plus its labels and notes:
+// `a.lox:1:3: error[E0001]: Expect expression`
+pub fn render(diagnostic: &Diagnostic, files: &dyn Files) -> String {
+    let location = |span: Span| {
+        let (line, column) = files.line_column(span.file, span.start);
+        return format!("{}:{}:{}", files.path(span.file), line, column);
+    };
+    let code = match diagnostic.code {
+        Some(code) => format!("[{}]", code),
+        None => String::new(),
+    };
+    let mut out = format!(
+        "{}: {}{}: {}",
+        location(diagnostic.primary_span),
+        diagnostic.severity,
+        code,
+        diagnostic.message
+    );
+    for label in diagnostic.labels.iter() {
+        out.push_str(&format!("\n{}: note: {}", location(label.span), label.message));
+    }
+    for note in diagnostic.notes.iter() {
+        out.push_str(&format!("\n  = {}", note_line(note)));
+    }
+    return out;
+}
+
+// Renders diagnostics the way rustc does: a header, the source lines involved, and
+// underlines beneath the exact spans (`^` for the primary span, `-` for labels).
+pub struct Renderer {
+    color: bool,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+impl Renderer {
+    pub fn plain() -> Self {
+        Self { color: false }
+    }
+
+    pub fn colored() -> Self {
+        Self { color: true }
+    }
+
+    // colors only when stderr is a terminal and NO_COLOR is unset
+    pub fn for_stderr() -> Self {
+        use std::io::IsTerminal;
+        Self {
+            color: std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            return format!("{}{}{}", style, text, RESET);
+        }
+        return text.to_string();
+    }
+
+    fn severity_style(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;32m",
+            Severity::Help => "\x1b[1;36m",
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic, files: &dyn Files) -> String {
+        let style = Self::severity_style(diagnostic.severity);
+        let code = match diagnostic.code {
+            Some(code) => format!("[{}]", code),
+            None => String::new(),
+        };
+        let mut out = format!(
+            "{}{}",
+            self.paint(style, &format!("{}{}", diagnostic.severity, code)),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+
+        // the primary span first, so it wins the top underline row on a shared line
+        let primary = diagnostic.primary_span;
+        let mut marks = vec![(primary, '^', style, "")];
+        for label in diagnostic.labels.iter() {
+            marks.push((label.span, '-', BLUE, label.message.as_str()));
+        }
+        let mut lines: Vec<(FileId, usize)> = marks
+            .iter()
+            .map(|(span, ..)| (span.file, files.line_column(span.file, span.start).0))
+            .collect();
+        lines.sort();
+        lines.dedup();
+        let width = lines.iter().map(|(_, line)| line.to_string().len()).max().unwrap_or(1);
+        let gutter = " ".repeat(width);
+
+        let (line, column) = files.line_column(primary.file, primary.start);
+        out.push_str(&format!(
+            "\n{}{} {}:{}:{}",
+            gutter,
+            self.paint(BLUE, "-->"),
+            files.path(primary.file),
+            line,
+            column
+        ));
+        out.push_str(&format!("\n{} {}", gutter, self.paint(BLUE, "|")));
+        let mut previous: Option<(FileId, usize)> = None;
+        for &(file, line) in lines.iter() {
+            if let Some((previous_file, previous_line)) = previous {
+                if previous_file != file {
+                    out.push_str(&format!("\n{}{} {}", gutter, self.paint(BLUE, "::>"), files.path(file)));
+                } else if line > previous_line + 1 {
+                    out.push_str(&format!("\n{}", self.paint(BLUE, "...")));
+                }
+            }
+            previous = Some((file, line));
+
+            let text = files.line_text(file, line);
+            let number = format!("{:>width$} |", line, width = width);
+            out.push_str(&format!("\n{} {}", self.paint(BLUE, &number), text));
+            for (span, mark, mark_style, message) in marks.iter() {
+                let (start_line, start_column) = files.line_column(span.file, span.start);
+                if span.file != file || start_line != line {
+                    continue;
+                }
+                // spans running past the end of the line are cut off there
+                let (end_line, end_column) = files.line_column(span.file, span.end);
+                let end_column = if end_line == line { end_column } else { text.chars().count() + 1 };
+                let length = end_column.saturating_sub(start_column).max(1);
+                let mut underline = mark.to_string().repeat(length);
+                if !message.is_empty() {
+                    underline.push(' ');
+                    underline.push_str(message);
+                }
+                out.push_str(&format!(
+                    "\n{} {} {}{}",
+                    gutter,
+                    self.paint(BLUE, "|"),
+                    " ".repeat(start_column - 1),
+                    self.paint(mark_style, &underline)
+                ));
+            }
+        }
+        for note in diagnostic.notes.iter() {
+            out.push_str(&format!("\n{} {} {}", gutter, self.paint(BLUE, "="), note_line(note)));
+        }
+        return out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct OneFile(&'static str);
+
+    impl Files for OneFile {
+        fn path(&self, _file: FileId) -> &str {
+            return "a.lox";
+        }
+        fn line_column(&self, _file: FileId, offset: usize) -> (usize, usize) {
+            let before = &self.0[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+            return (line, column);
+        }
+        fn line_text(&self, _file: FileId, line: usize) -> &str {
+            return self.0.lines().nth(line - 1).unwrap_or("");
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let diagnostic = Diagnostic::error("Expect ')' after expression", Span::new(9, 9))
+            .with_code("E0001")
+            .with_label(Span::new(0, 1), "to match this '('")
+            .with_note("groupings must be closed on the same expression");
+        let rendered = render(&diagnostic, &OneFile("(1 +\n  2 *"));
+
+        assert_eq!(
+            rendered,
+            "a.lox:2:5: error[E0001]: Expect ')' after expression\n\
+             a.lox:1:1: note: to match this '('\n  \
+             = note: groupings must be closed on the same expression"
+        );
+    }
+
+    #[test]
+    fn test_sink() {
+        let diagnostics = collector();
+        let sink: SharedSink = diagnostics.clone();
+        sink.borrow_mut().emit(Diagnostic::warning("unused", Span::new(0, 1)));
+        sink.borrow_mut().emit(Diagnostic::error("bad", Span::new(1, 2)));
+
+        let errors: Vec<_> = diagnostics.borrow().iter().filter(|d| d.is_error()).map(|d| d.message.clone()).collect();
+        assert_eq!(errors, vec!["bad"]);
+        assert!(Severity::Warning < Severity::Error);
+    }
+
+    #[test]
+    fn test_snippet() {
+        let diagnostic = Diagnostic::error("Expect ')' after expression", Span::new(14, 15))
+            .with_code("E0001")
+            .with_label(Span::new(0, 1), "to match this '('")
+            .with_note("groupings must be closed")
+            .with_help("add ')' after the 3");
+        let source = "(1 +\n  2\n\n  * 3;";
+        let rendered = Renderer::plain().render(&diagnostic, &OneFile(source));
+
+        assert_eq!(
+            rendered,
+            [
+                "error[E0001]: Expect ')' after expression",
+                " --> a.lox:4:5",
+                "  |",
+                "1 | (1 +",
+                "  | - to match this '('",
+                "...",
+                "4 |   * 3;",
+                "  |     ^",
+                "  = note: groupings must be closed",
+                "  = help: add ')' after the 3",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_snippet_same_line_and_color() {
+        let diagnostic = Diagnostic::warning("Unused value", Span::new(4, 9)).with_label(Span::new(0, 3), "declared here");
+        let files = OneFile("var xyzzy");
+        let rendered = Renderer::plain().render(&diagnostic, &files);
+        assert!(rendered.ends_with("1 | var xyzzy\n  |     ^^^^^\n  | --- declared here"));
+
+        let colored = Renderer::colored().render(&diagnostic, &files);
+        assert!(colored.starts_with("\x1b[1;33mwarning\x1b[0m"));
+        assert!(colored.contains("\x1b[1;33m^^^^^\x1b[0m"));
+    }
+}
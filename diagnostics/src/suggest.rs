@@ -0,0 +1,68 @@
+// "Did you mean ...?" support: the candidate nearest to a misspelled word, if any is near
+// enough to be a plausible typo.
+
+// Optimal string alignment distance: insertions, deletions, substitutions and swaps of two
+// neighbouring characters each cost one, so `retrun` is a single edit from `return`.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j] is the distance between the first i characters of a and the first j of b
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    return rows[a.len()][b.len()];
+}
+
+// the closest candidate within a third of the word's length (at least one edit); ties go
+// to whichever candidate comes first
+pub fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (word.chars().count() / 3).max(1);
+    let mut best: Option<(usize, &'a str)> = None;
+    for candidate in candidates {
+        let distance = distance(word, candidate);
+        if distance == 0 || distance > limit {
+            continue;
+        }
+        if best.is_none_or(|(best_distance, _)| distance < best_distance) {
+            best = Some((distance, candidate));
+        }
+    }
+    return best.map(|(_, candidate)| candidate);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("retrun", "return"), 1);
+        assert_eq!(distance("fucn", "fun"), 1);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let names = ["count", "counter", "total"];
+        assert_eq!(closest("coutn", names), Some("count"));
+        assert_eq!(closest("totl", names), Some("total"));
+        // a word that is already a candidate needs no suggestion
+        assert_eq!(closest("count", names), None);
+        assert_eq!(closest("xyz", names), None);
+    }
+}
@@ -0,0 +1,620 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::statement::{Statement, StmtId};
+use crate::tokens::{Token, TokenType};
+
+// Builds an AST without a source file. Tokens are spelled the way the scanner would spell
+// them and all sit on line 1, so compare against parsed trees with `AST::same_shape`.
+#[derive(Default)]
+pub struct Builder {
+    ast: AST,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    // `lexeme` is the number as written, e.g. "1.50"
+    pub fn number(&mut self, lexeme: &str) -> ExprId {
+        let value: f64 = lexeme.parse().expect("not a number");
+        let token = Token::new(TokenType::Number, lexeme.to_string(), Some(value.to_string()), 1);
+        return self.ast.add(Expression::Literal(Literal::NUMBER(token)));
+    }
+
+    pub fn string(&mut self, text: &str) -> ExprId {
+        let token = Token::new(TokenType::String, format!("\"{}\"", text), Some(text.to_string()), 1);
+        return self.ast.add(Expression::Literal(Literal::STRING(token)));
+    }
+
+    pub fn boolean(&mut self, value: bool) -> ExprId {
+        let (token_type, lexeme) = if value { (TokenType::True, "true") } else { (TokenType::False, "false") };
+        return self.keyword(token_type, lexeme);
+    }
+
+    pub fn nil(&mut self) -> ExprId {
+        return self.keyword(TokenType::Nil, "nil");
+    }
+
+    fn keyword(&mut self, token_type: TokenType, lexeme: &str) -> ExprId {
+        let token = Token::new(token_type, lexeme.to_string(), None, 1);
+        return self.ast.add(Expression::Literal(Literal::KEYWORD(token)));
+    }
+
+    pub fn variable(&mut self, name: &str) -> ExprId {
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 1);
+        return self.ast.add(Expression::Variable { name });
+    }
+
+    pub fn assign(&mut self, name: &str, value: ExprId) -> ExprId {
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 1);
+        return self.ast.add(Expression::Assign { name, value });
+    }
+
+    pub fn unary(&mut self, operator: UnaryOperator, value: ExprId) -> ExprId {
+        let operator = operator.token();
+        return self.ast.add(Expression::Unary { operator, value });
+    }
+
+    pub fn binary(&mut self, left: ExprId, operator: BinaryOperator, right: ExprId) -> ExprId {
+        let operator = operator.token();
+        return self.ast.add(Expression::Binary { left, operator, right });
+    }
+
+    pub fn logical(&mut self, left: ExprId, operator: LogicalOperator, right: ExprId) -> ExprId {
+        let operator = operator.token();
+        return self.ast.add(Expression::Logical { left, operator, right });
+    }
+
+    pub fn conditional(&mut self, condition: ExprId, then_branch: ExprId, else_branch: ExprId) -> ExprId {
+        let question = Token::new(TokenType::Question, "?".to_string(), None, 1);
+        return self.ast.add(Expression::Conditional {
+            condition,
+            question,
+            then_branch,
+            else_branch,
+        });
+    }
+
+    pub fn lambda(&mut self, params: &[&str], body: ExprId) -> ExprId {
+        let params = params
+            .iter()
+            .map(|name| Token::new(TokenType::Identifier, name.to_string(), None, 1))
+            .collect();
+        let arrow = Token::new(TokenType::Arrow, "=>".to_string(), None, 1);
+        return self.ast.add(Expression::Lambda { params, arrow, body });
+    }
+
+    pub fn function_expression(&mut self, params: &[&str], body: &[StmtId]) -> ExprId {
+        let keyword = Token::new(TokenType::Fun, "fun".to_string(), None, 1);
+        let params = params
+            .iter()
+            .map(|name| Token::new(TokenType::Identifier, name.to_string(), None, 1))
+            .collect();
+        return self.ast.add(Expression::Function {
+            keyword,
+            params,
+            body: body.to_vec(),
+        });
+    }
+
+    pub fn call(&mut self, callee: ExprId, arguments: &[ExprId]) -> ExprId {
+        let paren = Token::new(TokenType::RightParen, ")".to_string(), None, 1);
+        return self.ast.add(Expression::Call {
+            callee,
+            paren,
+            arguments: arguments.to_vec(),
+        });
+    }
+
+    pub fn list(&mut self, elements: &[ExprId]) -> ExprId {
+        return self.ast.add(Expression::List {
+            bracket: bracket(),
+            elements: elements.to_vec(),
+        });
+    }
+
+    pub fn map(&mut self, entries: &[(ExprId, ExprId)]) -> ExprId {
+        let brace = Token::new(TokenType::LeftBrace, "{".to_string(), None, 1);
+        return self.ast.add(Expression::Map {
+            brace,
+            entries: entries.to_vec(),
+        });
+    }
+
+    pub fn index(&mut self, object: ExprId, index: ExprId) -> ExprId {
+        return self.ast.add(Expression::Index {
+            object,
+            bracket: bracket(),
+            index,
+        });
+    }
+
+    pub fn set_index(&mut self, object: ExprId, index: ExprId, value: ExprId) -> ExprId {
+        return self.ast.add(Expression::SetIndex {
+            object,
+            bracket: bracket(),
+            index,
+            value,
+        });
+    }
+
+    pub fn grouping(&mut self, interior: ExprId) -> ExprId {
+        return self.ast.add(Expression::Grouping { interior });
+    }
+
+    pub fn finish(mut self, root: ExprId) -> AST {
+        self.ast.add_root(root);
+        return self.ast;
+    }
+
+    pub fn expression_statement(&mut self, expression: ExprId) -> StmtId {
+        return self.ast.add_statement(Statement::Expression { expression });
+    }
+
+    pub fn print(&mut self, value: ExprId) -> StmtId {
+        let keyword = Token::new(TokenType::Print, "print".to_string(), None, 1);
+        return self.ast.add_statement(Statement::Print { keyword, value });
+    }
+
+    pub fn var(&mut self, name: &str, initializer: Option<ExprId>) -> StmtId {
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 1);
+        return self.ast.add_statement(Statement::Var { name, initializer });
+    }
+
+    pub fn block(&mut self, statements: &[StmtId]) -> StmtId {
+        let brace = Token::new(TokenType::LeftBrace, "{".to_string(), None, 1);
+        return self.ast.add_statement(Statement::Block {
+            brace,
+            statements: statements.to_vec(),
+        });
+    }
+
+    pub fn if_statement(&mut self, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> StmtId {
+        let keyword = Token::new(TokenType::If, "if".to_string(), None, 1);
+        return self.ast.add_statement(Statement::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        });
+    }
+
+    pub fn function(&mut self, name: &str, params: &[&str], body: &[StmtId]) -> StmtId {
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 1);
+        let params = params
+            .iter()
+            .map(|name| Token::new(TokenType::Identifier, name.to_string(), None, 1))
+            .collect();
+        return self.ast.add_statement(Statement::Function {
+            name,
+            params,
+            body: body.to_vec(),
+        });
+    }
+
+    pub fn return_statement(&mut self, value: Option<ExprId>) -> StmtId {
+        let keyword = Token::new(TokenType::Return, "return".to_string(), None, 1);
+        return self.ast.add_statement(Statement::Return { keyword, value });
+    }
+
+    // with an increment, the loop is the one a `for` without an initializer becomes
+    pub fn while_statement(&mut self, condition: ExprId, body: StmtId, increment: Option<ExprId>) -> StmtId {
+        let keyword = match increment {
+            Some(_) => Token::new(TokenType::For, "for".to_string(), None, 1),
+            None => Token::new(TokenType::While, "while".to_string(), None, 1),
+        };
+        return self.ast.add_statement(Statement::While {
+            keyword,
+            condition,
+            body,
+            increment,
+        });
+    }
+
+    pub fn for_each(&mut self, name: &str, iterable: ExprId, body: StmtId) -> StmtId {
+        let keyword = Token::new(TokenType::For, "for".to_string(), None, 1);
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 1);
+        return self.ast.add_statement(Statement::ForEach {
+            keyword,
+            name,
+            iterable,
+            body,
+        });
+    }
+
+    pub fn break_statement(&mut self) -> StmtId {
+        let keyword = Token::new(TokenType::Break, "break".to_string(), None, 1);
+        return self.ast.add_statement(Statement::Break { keyword });
+    }
+
+    pub fn continue_statement(&mut self) -> StmtId {
+        let keyword = Token::new(TokenType::Continue, "continue".to_string(), None, 1);
+        return self.ast.add_statement(Statement::Continue { keyword });
+    }
+
+    // the statements become the program, in order
+    pub fn finish_program(mut self, program: &[StmtId]) -> AST {
+        for statement in program {
+            self.ast.add_to_program(*statement);
+        }
+        return self.ast;
+    }
+}
+
+fn bracket() -> Token {
+    return Token::new(TokenType::LeftBracket, "[".to_string(), None, 1);
+}
+
+// The operators a unary node can have; any other token type would not be an expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Minus,
+    Bang,
+}
+
+impl UnaryOperator {
+    fn token(self) -> Token {
+        let (token_type, lexeme) = match self {
+            UnaryOperator::Minus => (TokenType::Minus, "-"),
+            UnaryOperator::Bang => (TokenType::Bang, "!"),
+        };
+        return Token::new(token_type, lexeme.to_string(), None, 1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Comma,
+    Minus,
+    Plus,
+    Slash,
+    Star,
+    Percent,
+    TildeSlash,
+    StarStar,
+    BangEqual,
+    EqualEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+impl BinaryOperator {
+    fn token(self) -> Token {
+        let (token_type, lexeme) = match self {
+            BinaryOperator::Comma => (TokenType::Comma, ","),
+            BinaryOperator::Minus => (TokenType::Minus, "-"),
+            BinaryOperator::Plus => (TokenType::Plus, "+"),
+            BinaryOperator::Slash => (TokenType::Slash, "/"),
+            BinaryOperator::Star => (TokenType::Star, "*"),
+            BinaryOperator::Percent => (TokenType::Percent, "%"),
+            BinaryOperator::TildeSlash => (TokenType::TildeSlash, "~/"),
+            BinaryOperator::StarStar => (TokenType::StarStar, "**"),
+            BinaryOperator::BangEqual => (TokenType::BangEqual, "!="),
+            BinaryOperator::EqualEqual => (TokenType::EqualEqual, "=="),
+            BinaryOperator::Greater => (TokenType::Greater, ">"),
+            BinaryOperator::GreaterEqual => (TokenType::GreaterEqual, ">="),
+            BinaryOperator::Less => (TokenType::Less, "<"),
+            BinaryOperator::LessEqual => (TokenType::LessEqual, "<="),
+        };
+        return Token::new(token_type, lexeme.to_string(), None, 1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOperator {
+    And,
+    Or,
+}
+
+impl LogicalOperator {
+    fn token(self) -> Token {
+        let (token_type, lexeme) = match self {
+            LogicalOperator::And => (TokenType::And, "and"),
+            LogicalOperator::Or => (TokenType::Or, "or"),
+        };
+        return Token::new(token_type, lexeme.to_string(), None, 1);
+    }
+}
+
+// How `lox_expr!` turns a Rust literal into a Lox one; not meant to be called directly.
+pub trait MacroLiteral {
+    fn add_to(self, builder: &mut Builder, lexeme: &str) -> ExprId;
+}
+
+impl MacroLiteral for i32 {
+    fn add_to(self, builder: &mut Builder, lexeme: &str) -> ExprId {
+        return builder.number(lexeme);
+    }
+}
+
+impl MacroLiteral for f64 {
+    fn add_to(self, builder: &mut Builder, lexeme: &str) -> ExprId {
+        return builder.number(lexeme);
+    }
+}
+
+impl MacroLiteral for &str {
+    fn add_to(self, builder: &mut Builder, lexeme: &str) -> ExprId {
+        return builder.string(self);
+    }
+}
+
+impl MacroLiteral for bool {
+    fn add_to(self, builder: &mut Builder, lexeme: &str) -> ExprId {
+        return builder.boolean(self);
+    }
+}
+
+// Builds an AST from the same prefix notation `AST::print` uses:
+// `lox_expr!((+ 1 (* x (group (- 2)))))`. `nil` is nil, other identifiers are variables,
+// `(? c a b)` is `c ? a : b`, `(and a b)` and `(or a b)` are the logical operators,
+// `(, a b)` is the comma operator and `(=> (a b) body)` is
+// the lambda `(a, b) => body`. `(fun (a b) statements...)` is `fun (a, b) { statements }`,
+// its statements written as for `lox_stmt!`, and `(call f a b)` is `f(a, b)`. Lists, indexing and index assignment are `(list a b)`,
+// `(index xs i)` and `(setindex xs i v)`; `(map k1 v1 k2 v2)` is a map literal and
+// `(= x v)` assigns to a variable.
+#[macro_export]
+macro_rules! lox_expr {
+    (@node $b:ident, $value:literal) => {
+        $crate::builder::MacroLiteral::add_to($value, &mut $b, stringify!($value))
+    };
+    (@node $b:ident, nil) => { $b.nil() };
+    (@node $b:ident, $name:ident) => { $b.variable(stringify!($name)) };
+    (@node $b:ident, (group $interior:tt)) => {{
+        let interior = $crate::lox_expr!(@node $b, $interior);
+        $b.grouping(interior)
+    }};
+    (@node $b:ident, (? $condition:tt $then:tt $else:tt)) => {{
+        let condition = $crate::lox_expr!(@node $b, $condition);
+        let then_branch = $crate::lox_expr!(@node $b, $then);
+        let else_branch = $crate::lox_expr!(@node $b, $else);
+        $b.conditional(condition, then_branch, else_branch)
+    }};
+    (@node $b:ident, (=> ($($param:ident)*) $body:tt)) => {{
+        let body = $crate::lox_expr!(@node $b, $body);
+        $b.lambda(&[$(stringify!($param)),*], body)
+    }};
+    (@node $b:ident, (fun ($($param:ident)*) $($statement:tt)*)) => {{
+        let body = [$($crate::lox_stmt!(@stmt $b, $statement)),*];
+        $b.function_expression(&[$(stringify!($param)),*], &body)
+    }};
+    (@node $b:ident, (call $callee:tt $($argument:tt)*)) => {{
+        let callee = $crate::lox_expr!(@node $b, $callee);
+        let arguments = [$($crate::lox_expr!(@node $b, $argument)),*];
+        $b.call(callee, &arguments)
+    }};
+    (@node $b:ident, (list $($element:tt)*)) => {{
+        let elements = [$($crate::lox_expr!(@node $b, $element)),*];
+        $b.list(&elements)
+    }};
+    (@node $b:ident, (map $($key:tt $value:tt)*)) => {{
+        let entries = [$(($crate::lox_expr!(@node $b, $key), $crate::lox_expr!(@node $b, $value))),*];
+        $b.map(&entries)
+    }};
+    (@node $b:ident, (= $name:ident $value:tt)) => {{
+        let value = $crate::lox_expr!(@node $b, $value);
+        $b.assign(stringify!($name), value)
+    }};
+    (@node $b:ident, (index $object:tt $index:tt)) => {{
+        let object = $crate::lox_expr!(@node $b, $object);
+        let index = $crate::lox_expr!(@node $b, $index);
+        $b.index(object, index)
+    }};
+    (@node $b:ident, (setindex $object:tt $index:tt $value:tt)) => {{
+        let object = $crate::lox_expr!(@node $b, $object);
+        let index = $crate::lox_expr!(@node $b, $index);
+        let value = $crate::lox_expr!(@node $b, $value);
+        $b.set_index(object, index, value)
+    }};
+    (@node $b:ident, (and $left:tt $right:tt)) => {{
+        let left = $crate::lox_expr!(@node $b, $left);
+        let right = $crate::lox_expr!(@node $b, $right);
+        $b.logical(left, $crate::builder::LogicalOperator::And, right)
+    }};
+    (@node $b:ident, (or $left:tt $right:tt)) => {{
+        let left = $crate::lox_expr!(@node $b, $left);
+        let right = $crate::lox_expr!(@node $b, $right);
+        $b.logical(left, $crate::builder::LogicalOperator::Or, right)
+    }};
+    (@node $b:ident, (, $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Comma, $right) };
+    (@node $b:ident, (- $value:tt)) => { $crate::lox_expr!(@unary $b, Minus, $value) };
+    (@node $b:ident, (! $value:tt)) => { $crate::lox_expr!(@unary $b, Bang, $value) };
+    (@node $b:ident, (- $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Minus, $right) };
+    (@node $b:ident, (+ $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Plus, $right) };
+    (@node $b:ident, (/ $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Slash, $right) };
+    (@node $b:ident, (** $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, StarStar, $right) };
+    (@node $b:ident, (* $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Star, $right) };
+    (@node $b:ident, (% $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Percent, $right) };
+    (@node $b:ident, (~/ $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, TildeSlash, $right) };
+    (@node $b:ident, (!= $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, BangEqual, $right) };
+    (@node $b:ident, (== $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, EqualEqual, $right) };
+    (@node $b:ident, (> $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Greater, $right) };
+    (@node $b:ident, (>= $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, GreaterEqual, $right) };
+    (@node $b:ident, (< $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, Less, $right) };
+    (@node $b:ident, (<= $left:tt $right:tt)) => { $crate::lox_expr!(@binary $b, $left, LessEqual, $right) };
+    (@unary $b:ident, $operator:ident, $value:tt) => {{
+        let value = $crate::lox_expr!(@node $b, $value);
+        $b.unary($crate::builder::UnaryOperator::$operator, value)
+    }};
+    (@binary $b:ident, $left:tt, $operator:ident, $right:tt) => {{
+        // children first, so the arena ends up in the same order the parser would use
+        let left = $crate::lox_expr!(@node $b, $left);
+        let right = $crate::lox_expr!(@node $b, $right);
+        $b.binary(left, $crate::builder::BinaryOperator::$operator, right)
+    }};
+    ($expression:tt) => {{
+        let mut builder = $crate::builder::Builder::new();
+        let root = $crate::lox_expr!(@node builder, $expression);
+        builder.finish(root)
+    }};
+}
+
+// Builds a program from the notation `AST::print` uses for statements, one statement per
+// token tree: `lox_stmt!((var x 1) (if x (print x) (block)))`. `(; e)` is an expression
+// statement, `(var x)` has no initializer, `(fun f (a b) body...)` declares a function,
+// `(while c body i)` is `for (; c; i) body` and `(for x xs body)` is `for (var x : xs) body`.
+// Expressions inside are written as for `lox_expr!`.
+#[macro_export]
+macro_rules! lox_stmt {
+    (@stmt $b:ident, (; $expression:tt)) => {{
+        let expression = $crate::lox_expr!(@node $b, $expression);
+        $b.expression_statement(expression)
+    }};
+    (@stmt $b:ident, (print $value:tt)) => {{
+        let value = $crate::lox_expr!(@node $b, $value);
+        $b.print(value)
+    }};
+    (@stmt $b:ident, (var $name:ident)) => { $b.var(stringify!($name), None) };
+    (@stmt $b:ident, (var $name:ident $initializer:tt)) => {{
+        let initializer = $crate::lox_expr!(@node $b, $initializer);
+        $b.var(stringify!($name), Some(initializer))
+    }};
+    (@stmt $b:ident, (block $($statement:tt)*)) => {{
+        let statements = [$($crate::lox_stmt!(@stmt $b, $statement)),*];
+        $b.block(&statements)
+    }};
+    (@stmt $b:ident, (if $condition:tt $then:tt)) => {{
+        let condition = $crate::lox_expr!(@node $b, $condition);
+        let then_branch = $crate::lox_stmt!(@stmt $b, $then);
+        $b.if_statement(condition, then_branch, None)
+    }};
+    (@stmt $b:ident, (if $condition:tt $then:tt $else:tt)) => {{
+        let condition = $crate::lox_expr!(@node $b, $condition);
+        let then_branch = $crate::lox_stmt!(@stmt $b, $then);
+        let else_branch = $crate::lox_stmt!(@stmt $b, $else);
+        $b.if_statement(condition, then_branch, Some(else_branch))
+    }};
+    (@stmt $b:ident, (fun $name:ident ($($param:ident)*) $($statement:tt)*)) => {{
+        let body = [$($crate::lox_stmt!(@stmt $b, $statement)),*];
+        $b.function(stringify!($name), &[$(stringify!($param)),*], &body)
+    }};
+    (@stmt $b:ident, (return)) => { $b.return_statement(None) };
+    (@stmt $b:ident, (return $value:tt)) => {{
+        let value = $crate::lox_expr!(@node $b, $value);
+        $b.return_statement(Some(value))
+    }};
+    (@stmt $b:ident, (while $condition:tt $body:tt)) => {{
+        let condition = $crate::lox_expr!(@node $b, $condition);
+        let body = $crate::lox_stmt!(@stmt $b, $body);
+        $b.while_statement(condition, body, None)
+    }};
+    (@stmt $b:ident, (while $condition:tt $body:tt $increment:tt)) => {{
+        let condition = $crate::lox_expr!(@node $b, $condition);
+        let body = $crate::lox_stmt!(@stmt $b, $body);
+        let increment = $crate::lox_expr!(@node $b, $increment);
+        $b.while_statement(condition, body, Some(increment))
+    }};
+    (@stmt $b:ident, (for $name:ident $iterable:tt $body:tt)) => {{
+        let iterable = $crate::lox_expr!(@node $b, $iterable);
+        let body = $crate::lox_stmt!(@stmt $b, $body);
+        $b.for_each(stringify!($name), iterable, body)
+    }};
+    (@stmt $b:ident, (break)) => { $b.break_statement() };
+    (@stmt $b:ident, (continue)) => { $b.continue_statement() };
+    ($($statement:tt)*) => {{
+        let mut builder = $crate::builder::Builder::new();
+        let program = [$($crate::lox_stmt!(@stmt builder, $statement)),*];
+        builder.finish_program(&program)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, Scanner};
+
+    fn parse(input: &str) -> AST {
+        let mut parser = Parser::new(Scanner::new(input));
+        parser.parse();
+        return parser.into_ast();
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut builder = Builder::new();
+        let x = builder.variable("x");
+        let two = builder.number("2.50");
+        let product = builder.binary(x, BinaryOperator::Star, two);
+        let group = builder.grouping(product);
+        let negated = builder.unary(UnaryOperator::Minus, group);
+        let ast = builder.finish(negated);
+
+        assert_eq!(ast.print(negated), "(- (group (* x 2.50)))");
+        assert!(ast.same_shape(&parse("-(x * 2.50)")));
+        assert!(!ast.same_shape(&parse("-x * 2.50")));
+        assert!(!ast.same_shape(&parse("-(x * 2.5)")));
+    }
+
+    #[test]
+    fn test_lox_expr() {
+        let ast = lox_expr!((== (+ 1 (* x (group (- 2.5)))) (! "a b")));
+        assert!(ast.same_shape(&parse("1 + x * (-2.5) == !\"a b\"")));
+
+        let ast = lox_expr!((? a (, b c) (? d e f)));
+        assert!(ast.same_shape(&parse("a ? b, c : d ? e : f")));
+
+        let ast = lox_expr!((=> (a b) (+ a (=> () 1))));
+        assert!(ast.same_shape(&parse("(a, b) => a + () => 1")));
+
+        let ast = lox_expr!((setindex (index xs 0) i (list 1 (list) x)));
+        assert_eq!(ast, parse("xs[0][i] = [1, [], x]"));
+
+        let ast = lox_expr!((map "host" "x" "port" 80 true (map)));
+        assert_eq!(ast, parse("{\"host\": \"x\", \"port\": 80, true: {}}"));
+
+        let ast = lox_expr!((% (- (** 2 (** x 3))) (group (~/ a b))));
+        assert_eq!(ast, parse("-2 ** x ** 3 % (a ~/ b)"));
+
+        let ast = lox_expr!((or a (and b (! c))));
+        assert_eq!(ast, parse("a or b and !c"));
+
+        let ast = lox_expr!((= a (= b (+ c 1))));
+        assert_eq!(ast, parse("a = b = c + 1"));
+
+        let ast = lox_expr!((<= nil (- (- a b) c)));
+        assert!(ast.same_shape(&parse("nil\n<=\na - b - c")));
+        assert!(!ast.same_shape(&parse("nil <= a - (b - c)")));
+
+        // the macro adds nodes in parse order, so even the arena layout matches
+        let ast = lox_expr!((> true false));
+        let parsed = parse("true > false");
+        assert_eq!(ast, parsed);
+    }
+
+    fn parse_program(input: &str) -> AST {
+        let mut parser = Parser::new(Scanner::new(input));
+        parser.parse_program();
+        return parser.into_ast();
+    }
+
+    #[test]
+    fn test_lox_stmt() {
+        let ast = lox_stmt!(
+            (var x 1)
+            (var y)
+            (fun add (a b) (print (+ a b)) (return))
+            (if (> x 0) (block (; (= y x))) (if x (return y)))
+        );
+        let source = "var x = 1; var y; fun add(a, b) { print a + b; return; } if (x > 0) { y = x; } else if (x) return y;";
+        let mut parser = Parser::new(Scanner::new(source)).with_sink(diagnostics::collector());
+        parser.parse_program();
+        // the top-level `return` is reported, but it is still in the tree
+        assert_eq!(ast, parser.into_ast());
+
+        let ast = lox_stmt!((print 1) (block));
+        assert!(ast.same_shape(&parse_program("print\n1;\n{\n}")));
+        assert!(!ast.same_shape(&parse_program("print 1; {} x;")));
+        assert!(!ast.same_shape(&parse_program("print 1;")));
+
+        let ast = lox_stmt!((var f (fun (a) (return (call g a 1)))) (; (call (call f) (=> () nil))));
+        assert!(ast.same_shape(&parse_program("var f = fun (a) { return g(a, 1); }; f()(() => nil);")));
+
+        let ast = lox_stmt!((while x (block (break))) (block (var i 0) (while (< i 3) (continue) (= i (+ i 1)))));
+        assert_eq!(ast, parse_program("while (x) { break; } for (var i = 0; i < 3; i = i + 1) continue;"));
+
+        let ast = lox_stmt!((for x (list 1 2) (print x)));
+        assert_eq!(ast, parse_program("for (var x : [1, 2]) print x;"));
+    }
+}
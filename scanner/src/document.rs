@@ -0,0 +1,597 @@
+use crate::{
+    expression::AST,
+    interner::SymbolTable,
+    parser::Parser,
+    source_map::{FileId, SharedSourceMap},
+    statement::StmtId,
+    tokens::{Span, Token, TokenType},
+    visitor::Fold,
+    Scanner,
+};
+use diagnostics::{collector, Diagnostic, SharedSink};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Cursor;
+use std::ops::Range;
+use std::rc::Rc;
+
+// Replace the bytes in `range` with `replacement`, the way an editor reports a change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, replacement: &str) -> Self {
+        Self {
+            range,
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+// An edit whose range does not fit the document: past its end, backwards, or splitting a
+// character.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditError {
+    pub message: String,
+}
+
+impl EditError {
+    fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not apply edit: {}", self.message)
+    }
+}
+
+// What an edit cost: how many tokens were scanned again, which old tokens they replaced, and
+// how many tokens were parsed again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relex {
+    pub scanned: usize,
+    pub replaced: Range<usize>,
+    pub reparsed: usize,
+}
+
+// The tokens of one top-level declaration, from `start` up to the next chunk's start, and the
+// statement they parsed to; None if they did not parse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Chunk {
+    start: usize,
+    statement: Option<StmtId>,
+}
+
+// Rebuilds a program into a fresh arena, leaving behind the statements edits replaced.
+struct Compact;
+
+impl Fold for Compact {}
+
+// An arena this much bigger than the last compacted one is compacted again, so a long run of
+// edits costs memory in proportion to the program rather than to the number of edits.
+const GROWTH_BEFORE_COMPACTING: usize = 2;
+const MIN_COMPACTED_SIZE: usize = 1024;
+
+type Collector = Rc<RefCell<Vec<Diagnostic>>>;
+
+// A scanned and parsed program that can be kept up to date edit by edit. An edit rescans the
+// tokens around it and reparses only the top-level declarations those tokens belong to.
+pub struct Document {
+    source: String,
+    tokens: Vec<Token>,
+    ast: AST,
+    chunks: Vec<Chunk>,
+    // nodes and statements in the arena after the last compaction
+    compacted_size: usize,
+    // what the scanner and the parser currently report, each kept apart because an edit
+    // rescans and reparses different stretches of the source
+    scan_diagnostics: Vec<Diagnostic>,
+    parse_diagnostics: Vec<Diagnostic>,
+    sink: SharedSink,
+    interner: SymbolTable,
+    source_map: SharedSourceMap,
+    file: FileId,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        let scanned = collector();
+        let mut scanner = Scanner::new(source).with_sink(scanned.clone());
+        let tokens = scanner.scan_tokens();
+        let mut document = Self {
+            source: source.to_string(),
+            tokens,
+            ast: AST::new(),
+            chunks: Vec::new(),
+            compacted_size: 0,
+            scan_diagnostics: scanned.take(),
+            parse_diagnostics: Vec::new(),
+            sink: collector(),
+            interner: scanner.interner(),
+            source_map: scanner.source_map(),
+            file: scanner.file(),
+        };
+        let parsed = collector();
+        let (chunks, _) = document.parse_chunks(0, &[], &parsed);
+        document.chunks = chunks;
+        document.parse_diagnostics = parsed.take();
+        document.finish_program();
+        document.compacted_size = document.arena_size();
+        return document;
+    }
+
+    // Also reports to `sink`: first what the document already has, then what each edit finds.
+    pub fn with_sink(mut self, sink: SharedSink) -> Self {
+        for diagnostic in self.diagnostics() {
+            sink.borrow_mut().emit(diagnostic);
+        }
+        self.sink = sink;
+        self
+    }
+
+    pub fn source(&self) -> &str {
+        return &self.source;
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        return &self.tokens;
+    }
+
+    pub fn ast(&self) -> &AST {
+        return &self.ast;
+    }
+
+    pub fn program(&self) -> &[StmtId] {
+        return self.ast.program();
+    }
+
+    pub fn interner(&self) -> SymbolTable {
+        return self.interner.clone();
+    }
+
+    // what the current source has wrong with it, in source order
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = self.scan_diagnostics.iter().chain(&self.parse_diagnostics).cloned().collect();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.primary_span.start);
+        return diagnostics;
+    }
+
+    // An invalid edit leaves the document as it was.
+    pub fn apply(&mut self, edit: TextEdit) -> Result<Relex, EditError> {
+        self.check(&edit)?;
+        let before = self.tokens.len();
+        let scanned = collector();
+        let (mut relex, rescanned) = self.relex(&edit, &scanned);
+        // the retokenized stretch, in new token indices
+        let fresh = self.tokens.len() + relex.replaced.len() - before;
+        let edited_end = relex.replaced.start + fresh;
+
+        // Reparse from the chunk holding the first retokenized token. Old chunks that start
+        // after the retokenized tokens are where the new parse can rejoin the old one.
+        let first = self.chunks.partition_point(|chunk| chunk.start <= relex.replaced.start).saturating_sub(1);
+        let start = self.chunks.get(first).map_or(0, |chunk| chunk.start);
+        let later = self.chunks.partition_point(|chunk| chunk.start < relex.replaced.end);
+        let moved: Vec<usize> = self.chunks[later..]
+            .iter()
+            .map(|chunk| chunk.start - relex.replaced.end + edited_end)
+            .collect();
+        let parsed = collector();
+        let (chunks, rejoined) = self.parse_chunks(start, &moved, &parsed);
+        let end = rejoined.map_or(self.tokens.len(), |index| moved[index]);
+        relex.reparsed = end - start;
+
+        let kept = rejoined.map_or(Vec::new(), |index| {
+            self.chunks[later + index..]
+                .iter()
+                .zip(&moved[index..])
+                .map(|(chunk, start)| Chunk { start: *start, statement: chunk.statement })
+                .collect()
+        });
+        self.chunks.truncate(first);
+        self.chunks.extend(chunks);
+        self.chunks.extend(kept);
+        self.finish_program();
+
+        // what the rescanned and reparsed stretches report replaces what they used to
+        let (scanned, parsed) = (scanned.take(), parsed.take());
+        for diagnostic in scanned.iter().chain(&parsed) {
+            self.sink.borrow_mut().emit(diagnostic.clone());
+        }
+        let reparsed = self.offset(start)..rejoined.map_or(usize::MAX, |_| self.offset(end));
+        update(&mut self.scan_diagnostics, &edit, &rescanned, scanned);
+        update(&mut self.parse_diagnostics, &edit, &reparsed, parsed);
+
+        if self.arena_size() > GROWTH_BEFORE_COMPACTING * self.compacted_size.max(MIN_COMPACTED_SIZE) {
+            self.compact();
+        }
+        return Ok(relex);
+    }
+
+    // Parses declarations from token `start` to the end, or until one ends where a token in
+    // `rejoin` starts, and returns the chunks parsed and which of `rejoin` it stopped at.
+    fn parse_chunks(&mut self, start: usize, rejoin: &[usize], sink: &Collector) -> (Vec<Chunk>, Option<usize>) {
+        let mut parser = Parser::new(self.tokens[start..].iter().cloned())
+            .with_sink(sink.clone())
+            .with_ast(std::mem::take(&mut self.ast));
+        let mut chunks = Vec::new();
+        let mut position = start;
+        let mut rejoined = None;
+        loop {
+            // the first chunk always parses, so the edited tokens are never skipped
+            if !chunks.is_empty() {
+                if let Ok(index) = rejoin.binary_search(&position) {
+                    rejoined = Some(index);
+                    break;
+                }
+            }
+            if parser.at_end() {
+                break;
+            }
+            let statement = parser.parse_declaration();
+            chunks.push(Chunk { start: position, statement });
+            position = self.chunk_end(position, start + parser.consumed());
+        }
+        self.ast = parser.into_ast();
+        return (chunks, rejoined);
+    }
+
+    // Doc comments the parser read ahead belong to the declaration after them.
+    fn chunk_end(&self, start: usize, mut end: usize) -> usize {
+        while end > start + 1 && self.tokens[end - 1].token_type == TokenType::DocComment {
+            end -= 1;
+        }
+        return end;
+    }
+
+    fn finish_program(&mut self) {
+        let program = self.chunks.iter().filter_map(|chunk| chunk.statement).collect();
+        self.ast.set_program(program);
+    }
+
+    // the byte offset where token `index` starts, or the whole source's end past the last
+    fn offset(&self, index: usize) -> usize {
+        if index == 0 {
+            return 0;
+        }
+        return self.tokens.get(index).map_or(self.source.len(), |token| token.span.start);
+    }
+
+    fn arena_size(&self) -> usize {
+        return self.ast.len() + self.ast.statement_count();
+    }
+
+    fn compact(&mut self) {
+        let mut compacted = AST::new();
+        for chunk in self.chunks.iter_mut() {
+            if let Some(statement) = chunk.statement {
+                chunk.statement = Some(Compact.fold_statement(&self.ast, statement, &mut compacted));
+            }
+        }
+        self.ast = compacted;
+        self.finish_program();
+        self.compacted_size = self.arena_size();
+    }
+
+    fn check(&self, edit: &TextEdit) -> Result<(), EditError> {
+        let Range { start, end } = edit.range;
+        if start > end {
+            return Err(EditError::new(&format!("range {}..{} ends before it starts", start, end)));
+        }
+        if end > self.source.len() {
+            return Err(EditError::new(&format!(
+                "range {}..{} is past the end of the source ({} bytes)",
+                start,
+                end,
+                self.source.len()
+            )));
+        }
+        for offset in [start, end] {
+            if !self.source.is_char_boundary(offset) {
+                return Err(EditError::new(&format!("offset {} is inside a character", offset)));
+            }
+        }
+        return Ok(());
+    }
+
+    // Rescans from the token before the edit until the new tokens line up with the old ones
+    // again, then splices the new tokens in and shifts the untouched tail. Also returns the
+    // bytes that were rescanned, in the new source.
+    fn relex(&mut self, edit: &TextEdit, sink: &Collector) -> (Relex, Range<usize>) {
+        let removed = edit.range.len();
+        let inserted = edit.replacement.len();
+        self.source.replace_range(edit.range.clone(), &edit.replacement);
+        self.source_map.borrow_mut().update(self.file, &self.source);
+
+        // an edit touching the end of a token can change that token, so step back one more
+        let first = self
+            .tokens
+            .iter()
+            .position(|token| token.span.end >= edit.range.start)
+            .unwrap_or(self.tokens.len() - 1)
+            .saturating_sub(1);
+        let (restart, line, column) = if self.tokens[first].span.start <= edit.range.start {
+            let token = &self.tokens[first];
+            (token.span.start, token.line, token.column)
+        } else {
+            (0, 1, 1)
+        };
+
+        let shift = |offset: usize| offset + inserted - removed;
+        let scanner = Scanner::from_reader(Cursor::new(&self.source.as_bytes()[restart..]))
+            .resume_at(restart, line, column)
+            .with_interner(self.interner.clone())
+            .with_sink(sink.clone())
+            .in_file(self.source_map.clone(), self.file);
+
+        let mut old = first;
+        let mut fresh: Vec<Token> = Vec::new();
+        let mut sync: Option<(Token, Token)> = None;
+        for token in scanner {
+            // old tokens entirely after the edit are candidates to resynchronise with
+            while old < self.tokens.len()
+                && (self.tokens[old].span.start < edit.range.end
+                    || shift(self.tokens[old].span.start) < token.span.start)
+            {
+                old += 1;
+            }
+            if let Some(candidate) = self.tokens.get(old) {
+                if shift(candidate.span.start) == token.span.start
+                    && candidate.token_type == token.token_type
+                    && candidate.lexeme == token.lexeme
+                {
+                    sync = Some((candidate.clone(), token));
+                    break;
+                }
+            }
+            fresh.push(token);
+        }
+        let scanned = fresh.len() + usize::from(sync.is_some());
+        if sync.is_none() {
+            old = self.tokens.len();
+        }
+
+        let rescanned = restart..sync.as_ref().map_or(usize::MAX, |(_, new_sync)| new_sync.span.start);
+        if let Some((old_sync, new_sync)) = sync {
+            for token in self.tokens[old..].iter_mut() {
+                // only tokens on the edited line move sideways
+                if token.line == old_sync.line {
+                    token.column = token.column + new_sync.column - old_sync.column;
+                }
+                token.span.start = shift(token.span.start);
+                token.span.end = shift(token.span.end);
+                token.line = token.line + new_sync.line - old_sync.line;
+            }
+        }
+        self.tokens.splice(first..old, fresh);
+        let relex = Relex {
+            scanned,
+            replaced: first..old,
+            reparsed: 0,
+        };
+        return (relex, rescanned);
+    }
+}
+
+// Carries `diagnostics` across `edit`: the ones in `stretch`, which was scanned or parsed
+// again, give way to `found`, and the rest move with the text around them. One pointing into
+// the replaced text is dropped, since what it pointed at is gone.
+fn update(diagnostics: &mut Vec<Diagnostic>, edit: &TextEdit, stretch: &Range<usize>, found: Vec<Diagnostic>) {
+    let moved = |offset: usize| {
+        if offset >= edit.range.end {
+            return offset - edit.range.len() + edit.replacement.len();
+        }
+        return offset.min(edit.range.start + edit.replacement.len());
+    };
+    diagnostics.retain_mut(|diagnostic| {
+        let labels = diagnostic.labels.iter_mut().map(|label| &mut label.span);
+        for span in std::iter::once(&mut diagnostic.primary_span).chain(labels) {
+            if (edit.range.start..edit.range.end).contains(&span.start) {
+                return false;
+            }
+            *span = Span::in_file(span.file, moved(span.start), moved(span.end));
+        }
+        return !stretch.contains(&diagnostic.primary_span.start);
+    });
+    diagnostics.extend(found);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.primary_span.start);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matches_full_scan(document: &Document) {
+        let expected = Scanner::new(document.source()).scan_tokens();
+        assert_eq!(document.tokens(), expected.as_slice());
+        let locations: Vec<(Span, usize)> =
+            document.tokens().iter().map(|token| (token.span, token.column)).collect();
+        let expected_locations: Vec<(Span, usize)> =
+            expected.iter().map(|token| (token.span, token.column)).collect();
+        assert_eq!(locations, expected_locations);
+    }
+
+    // the edited document has the program and diagnostics of one made from its source afresh
+    fn assert_matches_full_parse(document: &Document) {
+        let expected = Document::new(document.source());
+        assert!(document.ast().same_shape(expected.ast()));
+        let starts = |document: &Document| -> Vec<usize> { document.chunks.iter().map(|chunk| chunk.start).collect() };
+        assert_eq!(starts(document), starts(&expected));
+        let report = |document: &Document| -> Vec<(String, Span)> {
+            document.diagnostics().into_iter().map(|diagnostic| (diagnostic.message, diagnostic.primary_span)).collect()
+        };
+        assert_eq!(report(document), report(&expected));
+    }
+
+    #[test]
+    fn test_one_character_edit_is_bounded() {
+        let source = "print 1;\n".repeat(10_000);
+        let mut document = Document::new(&source);
+        let line_5000 = 4999 * 9 + 6;
+
+        let relex = document.apply(TextEdit::new(line_5000..line_5000 + 1, "7")).unwrap();
+
+        assert!(relex.scanned <= 3, "scanned {} tokens", relex.scanned);
+        assert_eq!(document.tokens()[relex.replaced.start + 1].lexeme, "7");
+        // the statement before the edit's first token at most, and the edited one
+        assert!(relex.reparsed <= 6, "reparsed {} tokens", relex.reparsed);
+        assert_eq!(document.ast().print(document.program()[4999]), "(print 7)");
+        assert_matches_full_scan(&document);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_insert_line_shifts_tail() {
+        let mut document = Document::new("1 +\n2 +\n3;");
+
+        let relex = document.apply(TextEdit::new(4..4, "\n")).unwrap();
+
+        assert!(relex.scanned <= 3, "scanned {} tokens", relex.scanned);
+        assert_eq!(document.source(), "1 +\n\n2 +\n3;");
+        assert_eq!(document.tokens()[4].line, 4);
+        assert_matches_full_scan(&document);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_edit_shifts_columns() {
+        let mut document = Document::new("1 + 2 * 3;\n4;");
+
+        document.apply(TextEdit::new(0..1, "100")).unwrap();
+
+        assert_eq!(document.tokens()[4].column, 11);
+        assert_matches_full_scan(&document);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_edit_joins_tokens() {
+        let mut document = Document::new("12 + 34;");
+
+        document.apply(TextEdit::new(2..5, "")).unwrap();
+
+        assert_eq!(document.source(), "1234;");
+        assert_eq!(document.tokens()[0].lexeme, "1234");
+        assert_eq!(document.ast().print(document.program()[0]), "(; 1234)");
+        assert_matches_full_scan(&document);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_edit_opening_comment() {
+        let mut document = Document::new("1 + 2\n* 3;");
+
+        document.apply(TextEdit::new(4..4, "/* ")).unwrap();
+
+        assert_eq!(document.source(), "1 + /* 2\n* 3;");
+        assert_eq!(document.tokens().len(), 3);
+        assert_matches_full_scan(&document);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_symbols_stable_across_edits() {
+        let mut document = Document::new("width * 2;");
+
+        document.apply(TextEdit::new(8..9, "width")).unwrap();
+
+        assert_eq!(document.source(), "width * width;");
+        assert_eq!(document.tokens()[0].symbol, document.tokens()[2].symbol);
+        assert_eq!(document.interner().borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_reparse_after_edit() {
+        let mut document = Document::new("var a = 1 + 2;\nprint a;");
+
+        document.apply(TextEdit::new(10..11, "*")).unwrap();
+
+        let mut parser = Parser::new(Scanner::new("var a = 1 * 2;\nprint a;"));
+        parser.parse_program();
+        assert!(document.ast().same_shape(parser.ast()));
+    }
+
+    #[test]
+    fn test_reparse_runs_on_until_it_lines_up() {
+        // without its `;` the first statement runs into the second, and with it back they part
+        let mut document = Document::new("print 1;\nprint 2;\nprint 3;\nprint 4;");
+        document.apply(TextEdit::new(16..17, "")).unwrap();
+        assert_eq!(document.source(), "print 1;\nprint 2\nprint 3;\nprint 4;");
+        assert_matches_full_parse(&document);
+
+        document.apply(TextEdit::new(16..16, ";")).unwrap();
+        assert_eq!(document.program().len(), 4);
+        assert_matches_full_parse(&document);
+
+        // a new `else` joins the statement before it
+        let mut document = Document::new("if (a) print 1;\nprint 2;");
+        document.apply(TextEdit::new(16..16, "else ")).unwrap();
+        assert_eq!(document.program().len(), 1);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_doc_comments_stay_with_their_declaration() {
+        let mut document = Document::new("var a = 1;\n/// b\nvar b = 2;");
+
+        document.apply(TextEdit::new(8..9, "3")).unwrap();
+        document.apply(TextEdit::new(25..26, "4")).unwrap();
+
+        assert_eq!(document.ast().docs(document.program()[1]).len(), 1);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_diagnostics_follow_edits() {
+        let sink = diagnostics::collector();
+        let mut document = Document::new("print 1;\nprint @;\nprint 3").with_sink(sink.clone());
+        let messages = |document: &Document| -> Vec<String> {
+            document.diagnostics().into_iter().map(|diagnostic| diagnostic.message).collect()
+        };
+        let before = messages(&document);
+        assert_eq!(before.len(), 3, "{:?}", before);
+        assert_eq!(sink.borrow().len(), 3);
+
+        // fixing the `@` clears its errors; the missing `;` at the end stays, moved along
+        document.apply(TextEdit::new(15..16, "22")).unwrap();
+        assert_eq!(messages(&document), before[2..]);
+        assert_matches_full_parse(&document);
+
+        // new errors are reported to the sink as well
+        document.apply(TextEdit::new(0..5, "prin")).unwrap();
+        let reported: Vec<String> = sink.borrow()[3..].iter().map(|diagnostic| diagnostic.message.clone()).collect();
+        assert_eq!(reported, ["'prin' is not a keyword", "Expect ';' after expression"]);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_many_edits_keep_the_arena_bounded() {
+        let mut document = Document::new("var x = 0;\nprint x;");
+        for digit in (0..5_000).map(|n| (n % 10).to_string()) {
+            document.apply(TextEdit::new(8..9, &digit)).unwrap();
+        }
+        assert!(document.arena_size() <= GROWTH_BEFORE_COMPACTING * MIN_COMPACTED_SIZE);
+        assert_matches_full_parse(&document);
+    }
+
+    #[test]
+    fn test_invalid_edits_are_rejected() {
+        let mut document = Document::new("\"héllo\" + 1;");
+
+        for range in [3..4, Range { start: 4, end: 2 }, 0..100] {
+            let error = document.apply(TextEdit::new(range.clone(), "x")).unwrap_err();
+            assert!(error.to_string().starts_with("could not apply edit: "), "{}", error);
+        }
+        assert_eq!(document.source(), "\"héllo\" + 1;");
+        assert!(document.apply(TextEdit::new(2..4, "e")).is_ok());
+        assert_eq!(document.source(), "\"hello\" + 1;");
+        assert_matches_full_scan(&document);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::statement::{Statement, StmtId};
+use crate::visitor::{children, statement_children};
+
+// Renders the tree under `root` as a Graphviz digraph, one node per expression.
+// Node names are arena indices, so the same id always draws as the same node.
+pub fn to_dot(ast: &AST, root: ExprId) -> String {
+    let mut out = String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n");
+    write_node(ast, root, &mut out);
+    out.push_str("}\n");
+    return out;
+}
+
+// The whole program as one digraph: a `program` node with an edge to each top-level
+// statement, numbered from 0. Statements are `s<index>`, expressions `n<index>` as above.
+pub fn program_to_dot(ast: &AST) -> String {
+    let mut out = String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n");
+    out.push_str("    program [label=\"program\"];\n");
+    for (position, statement) in ast.program().iter().enumerate() {
+        write_statement(ast, *statement, &mut out);
+        out.push_str(&format!("    program -> s{} [label=\"{}\"];\n", statement.index(), position));
+    }
+    out.push_str("}\n");
+    return out;
+}
+
+fn write_statement(ast: &AST, id: StmtId, out: &mut String) {
+    let label = format!("{}\nline {}", statement_label(&ast[id]), statement_line(ast, id));
+    out.push_str(&format!("    s{} [label=\"{}\"];\n", id.index(), escape(&label)));
+    let (expressions, statements) = statement_children(&ast[id]);
+    let (expression_edges, statement_edges): (Vec<String>, Vec<String>) = match &ast[id] {
+        Statement::Expression { .. } => (vec!["expression".to_string()], vec![]),
+        Statement::Print { .. } | Statement::Return { .. } => (vec!["value".to_string()], vec![]),
+        Statement::Var { .. } => (vec!["initializer".to_string()], vec![]),
+        Statement::While { .. } => (vec!["condition".to_string(), "increment".to_string()], vec!["body".to_string()]),
+        Statement::ForEach { .. } => (vec!["iterable".to_string()], vec!["body".to_string()]),
+        Statement::Break { .. } | Statement::Continue { .. } => (vec![], vec![]),
+        Statement::If { .. } => (vec!["condition".to_string()], vec!["then".to_string(), "else".to_string()]),
+        // the statements of a block or body are labelled by position
+        Statement::Block { statements, .. } | Statement::Function { body: statements, .. } => {
+            (vec![], (0..statements.len()).map(|position| position.to_string()).collect())
+        }
+    };
+    for (child, edge) in expressions.into_iter().zip(expression_edges) {
+        write_node(ast, child, out);
+        out.push_str(&format!("    s{} -> n{} [label=\"{}\"];\n", id.index(), child.index(), edge));
+    }
+    for (child, edge) in statements.into_iter().zip(statement_edges) {
+        write_statement(ast, child, out);
+        out.push_str(&format!("    s{} -> s{} [label=\"{}\"];\n", id.index(), child.index(), edge));
+    }
+}
+
+fn statement_label(statement: &Statement) -> String {
+    match statement {
+        Statement::Expression { .. } => ";".to_string(),
+        Statement::Print { .. } => "print".to_string(),
+        Statement::Var { name, .. } => format!("var {}", name.lexeme),
+        Statement::Block { .. } => "block".to_string(),
+        Statement::If { .. } => "if".to_string(),
+        Statement::Function { name, params, .. } => {
+            let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+            format!("fun {}({})", name.lexeme, params.join(", "))
+        }
+        Statement::Return { .. } => "return".to_string(),
+        Statement::While { .. } => "while".to_string(),
+        Statement::ForEach { name, .. } => format!("for {}", name.lexeme),
+        Statement::Break { .. } => "break".to_string(),
+        Statement::Continue { .. } => "continue".to_string(),
+    }
+}
+
+// an expression statement has no token of its own, so it reports the line of its expression
+fn statement_line(ast: &AST, id: StmtId) -> usize {
+    match &ast[id] {
+        Statement::Expression { expression } => line(ast, *expression),
+        Statement::Print { keyword, .. }
+        | Statement::If { keyword, .. }
+        | Statement::Return { keyword, .. }
+        | Statement::While { keyword, .. }
+        | Statement::ForEach { keyword, .. }
+        | Statement::Break { keyword }
+        | Statement::Continue { keyword } => keyword.line,
+        Statement::Var { name, .. } | Statement::Function { name, .. } => name.line,
+        Statement::Block { brace, .. } => brace.line,
+    }
+}
+
+fn write_node(ast: &AST, id: ExprId, out: &mut String) {
+    let label = format!("{}\nline {}", label(&ast[id]), line(ast, id));
+    out.push_str(&format!("    n{} [label=\"{}\"];\n", id.index(), escape(&label)));
+    let edges: Vec<String> = match &ast[id] {
+        Expression::Binary { .. } | Expression::Logical { .. } => vec!["left".to_string(), "right".to_string()],
+        Expression::Unary { .. } | Expression::Assign { .. } => vec!["value".to_string()],
+        Expression::Grouping { .. } => vec!["interior".to_string()],
+        Expression::Conditional { .. } => vec!["condition".to_string(), "then".to_string(), "else".to_string()],
+        Expression::Lambda { .. } => vec!["body".to_string()],
+        Expression::Call { arguments, .. } => {
+            let mut edges = vec!["callee".to_string()];
+            edges.extend((0..arguments.len()).map(|position| format!("argument {}", position)));
+            edges
+        }
+        Expression::Function { .. } => vec![],
+        // elements are labelled by position
+        Expression::List { elements, .. } => (0..elements.len()).map(|position| position.to_string()).collect(),
+        Expression::Map { entries, .. } => (0..entries.len())
+            .flat_map(|position| [format!("key {}", position), format!("value {}", position)])
+            .collect(),
+        Expression::Index { .. } => vec!["object".to_string(), "index".to_string()],
+        Expression::SetIndex { .. } => vec!["object".to_string(), "index".to_string(), "value".to_string()],
+        Expression::Literal(_) | Expression::Variable { .. } => vec![],
+    };
+    for (child, edge) in children(&ast[id]).into_iter().zip(edges) {
+        write_node(ast, child, out);
+        out.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", id.index(), child.index(), edge));
+    }
+    // the body of a `fun` expression is statements, labelled by position
+    if let Expression::Function { body, .. } = &ast[id] {
+        for (position, statement) in body.iter().enumerate() {
+            write_statement(ast, *statement, out);
+            out.push_str(&format!("    n{} -> s{} [label=\"{}\"];\n", id.index(), statement.index(), position));
+        }
+    }
+}
+
+fn label(expression: &Expression) -> String {
+    match expression {
+        Expression::Unary { operator, .. } | Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => {
+            operator.lexeme.clone()
+        }
+        Expression::Grouping { .. } => "group".to_string(),
+        Expression::Conditional { .. } => "?:".to_string(),
+        Expression::List { .. } => "[]".to_string(),
+        Expression::Map { .. } => "{}".to_string(),
+        Expression::Index { .. } => "[index]".to_string(),
+        Expression::SetIndex { .. } => "[index] =".to_string(),
+        Expression::Lambda { params, .. } => {
+            let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+            format!("({}) =>", params.join(", "))
+        }
+        Expression::Function { params, .. } => {
+            let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+            format!("fun ({})", params.join(", "))
+        }
+        Expression::Call { .. } => "call".to_string(),
+        Expression::Literal(Literal::NUMBER(token) | Literal::STRING(token) | Literal::KEYWORD(token)) => {
+            token.lexeme.clone()
+        }
+        Expression::Variable { name } => name.lexeme.clone(),
+        Expression::Assign { name, .. } => format!("{} =", name.lexeme),
+    }
+}
+
+// groupings carry no token of their own, so they report where their contents start
+fn line(ast: &AST, id: ExprId) -> usize {
+    match &ast[id] {
+        Expression::Unary { operator, .. } | Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => {
+            operator.line
+        }
+        Expression::Grouping { interior } => line(ast, *interior),
+        Expression::Conditional { question, .. } => question.line,
+        Expression::Lambda { arrow, .. } => arrow.line,
+        Expression::Function { keyword, .. } => keyword.line,
+        Expression::Call { paren, .. } => paren.line,
+        Expression::Map { brace, .. } => brace.line,
+        Expression::List { bracket, .. } | Expression::Index { bracket, .. } | Expression::SetIndex { bracket, .. } => bracket.line,
+        Expression::Literal(Literal::NUMBER(token) | Literal::STRING(token) | Literal::KEYWORD(token)) => token.line,
+        Expression::Variable { name } | Expression::Assign { name, .. } => name.line,
+    }
+}
+
+fn escape(label: &str) -> String {
+    let mut escaped = String::new();
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, Scanner};
+
+    #[test]
+    fn test_dot_graph() {
+        let mut parser = Parser::new(Scanner::new("-x *\n(\"a\" + 2)"));
+        let root = parser.parse();
+        let dot = to_dot(parser.ast(), root);
+
+        assert!(dot.starts_with("digraph ast {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains(&format!("    n{} [label=\"*\\nline 1\"];\n", root.index())));
+        assert!(dot.contains("[label=\"\\\"a\\\"\\nline 2\"];"));
+        assert!(dot.contains("[label=\"group\\nline 2\"];"));
+        assert_eq!(dot.matches(" -> ").count(), 6);
+        assert_eq!(dot.matches("[label=\"left\"]").count(), 2);
+        assert_eq!(dot.matches("[label=\"value\"]").count(), 1);
+    }
+
+    #[test]
+    fn test_program_graph() {
+        let mut parser = Parser::new(Scanner::new("var x = 1;\nif (x)\n  print x;\nelse {}"));
+        parser.parse_program();
+        let dot = program_to_dot(parser.ast());
+
+        assert!(dot.contains("    program [label=\"program\"];\n"));
+        assert_eq!(dot.matches("    program -> s").count(), 2);
+        assert!(dot.contains("[label=\"var x\\nline 1\"];"));
+        assert!(dot.contains("[label=\"print\\nline 3\"];"));
+        assert!(dot.contains("[label=\"block\\nline 4\"];"));
+        for edge in ["initializer", "condition", "then", "else", "value"] {
+            assert_eq!(dot.matches(&format!("[label=\"{}\"]", edge)).count(), 1, "{}", edge);
+        }
+        // program edges, var -> 1, if -> x, then, else, print -> x
+        assert_eq!(dot.matches(" -> ").count(), 7);
+    }
+
+    #[test]
+    fn test_function_graph() {
+        let mut parser = Parser::new(Scanner::new("var f = fun (a) {\n  return a;\n};\nf(1, 2);"));
+        parser.parse_program();
+        let dot = program_to_dot(parser.ast());
+
+        assert!(dot.contains("[label=\"fun (a)\\nline 1\"];"));
+        assert!(dot.contains("[label=\"return\\nline 2\"];"));
+        assert!(dot.contains("[label=\"call\\nline 4\"];"));
+        assert_eq!(dot.matches("[label=\"callee\"]").count(), 1);
+        assert_eq!(dot.matches("[label=\"argument ").count(), 2);
+        // the body hangs off the expression node
+        assert_eq!(dot.matches(" -> s").count() - dot.matches("program -> s").count(), 1);
+    }
+}
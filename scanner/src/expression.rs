@@ -1,5 +1,8 @@
+use crate::statement::{Statement, StmtId};
 use crate::tokens::{Token, TokenType};
-use std::rc::Rc;
+use crate::visitor::{StatementVisitor, Visitor};
+use std::collections::BTreeMap;
+use std::ops::{Index, IndexMut};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Literal {
@@ -8,212 +11,682 @@ pub enum Literal {
     KEYWORD(Token),
 }
 
-trait Print {
-    fn print(&self) -> String;
+// Index of an expression in its AST; stable for the life of the tree, so passes can key
+// side tables by it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+impl ExprId {
+    pub(crate) fn from_index(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        return self.0 as usize;
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
     Unary {
         operator: Token,
-        value: Rc<Expression>,
+        value: ExprId,
     },
     Binary {
-        left: Rc<Expression>,
+        left: ExprId,
         operator: Token,
-        right: Rc<Expression>,
+        right: ExprId,
     },
     Grouping {
-        interior: Rc<Expression>,
+        interior: ExprId,
+    },
+    // `left and right`, `left or right`; kept apart from Binary because `right` is only
+    // evaluated when `left` does not already decide the result
+    Logical {
+        left: ExprId,
+        operator: Token,
+        right: ExprId,
+    },
+    // `condition ? then_branch : else_branch`
+    Conditional {
+        condition: ExprId,
+        question: Token,
+        then_branch: ExprId,
+        else_branch: ExprId,
     },
     Literal(Literal),
+    Variable {
+        name: Token,
+    },
+    // `name = value`, right-associative
+    Assign {
+        name: Token,
+        value: ExprId,
+    },
+    // `[a, b, c]`; `bracket` is the `[`
+    List {
+        bracket: Token,
+        elements: Vec<ExprId>,
+    },
+    // `object[index]`; out-of-bounds errors point at `bracket`
+    Index {
+        object: ExprId,
+        bracket: Token,
+        index: ExprId,
+    },
+    // `object[index] = value`
+    SetIndex {
+        object: ExprId,
+        bracket: Token,
+        index: ExprId,
+        value: ExprId,
+    },
+    // `{key: value, ...}`; entries keep their source order, which is also iteration order
+    Map {
+        brace: Token,
+        entries: Vec<(ExprId, ExprId)>,
+    },
+    // `(a, b) => body`, an anonymous function
+    Lambda {
+        params: Vec<Token>,
+        arrow: Token,
+        body: ExprId,
+    },
+    // `fun (a, b) { ... }`, an anonymous function with a block body
+    Function {
+        keyword: Token,
+        params: Vec<Token>,
+        body: Vec<StmtId>,
+    },
+    // `callee(arguments)`; arity errors point at `paren`, the `)`
+    Call {
+        callee: ExprId,
+        paren: Token,
+        arguments: Vec<ExprId>,
+    },
+}
+
+// Arena owning every node of a parse; children refer to each other by ExprId or StmtId and
+// the whole tree is dropped at once. `roots` are the expressions parsed on their own and
+// `program` the top-level statements, both in source order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AST {
+    exprs: Vec<Expression>,
+    roots: Vec<ExprId>,
+    stmts: Vec<Statement>,
+    program: Vec<StmtId>,
+    // the doc comments written above a `var` or `fun` declaration, in source order
+    docs: BTreeMap<StmtId, Vec<Token>>,
 }
 
-impl Print for Expression {
-    fn print(&self) -> String {
-        let print_val: String = match self {
-            Expression::Unary { operator, value } => {
-                parenthesize(operator.lexeme.as_str(), vec![value.clone()])
-            }
-            Expression::Binary {
-                left,
-                operator,
-                right,
-            } => {
-                let exprs = vec![left.clone(), right.clone()];
-                parenthesize(operator.lexeme.as_str(), exprs)
-            }
-            Expression::Grouping { interior } => parenthesize("group", vec![interior.clone()]),
-            Expression::Literal(c) => match c {
-                Literal::NUMBER(t) => t.clone().lexeme,
-                Literal::STRING(t) => t.clone().lexeme,
-                Literal::KEYWORD(t) => {
-                    if t.token_type == TokenType::Nil {
-                        return String::from("nil");
+impl AST {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, expression: Expression) -> ExprId {
+        let id = ExprId(self.exprs.len() as u32);
+        self.exprs.push(expression);
+        return id;
+    }
+
+    // Removes `id` so a parser can rebuild it as something else, e.g. an index as the target
+    // of an assignment. Only the newest node can go: nothing refers to it yet.
+    pub(crate) fn pop(&mut self, id: ExprId) -> Expression {
+        assert_eq!(id.index() + 1, self.exprs.len(), "only the newest node can be popped");
+        return self.exprs.pop().unwrap();
+    }
+
+    pub fn add_root(&mut self, id: ExprId) {
+        self.roots.push(id);
+    }
+
+    pub fn roots(&self) -> &[ExprId] {
+        return &self.roots;
+    }
+
+    pub fn get(&self, id: ExprId) -> Option<&Expression> {
+        return self.exprs.get(id.index());
+    }
+
+    pub fn len(&self) -> usize {
+        return self.exprs.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.exprs.is_empty();
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ExprId> {
+        return (0..self.exprs.len() as u32).map(ExprId);
+    }
+
+    pub fn add_statement(&mut self, statement: Statement) -> StmtId {
+        let id = StmtId::from_index(self.stmts.len());
+        self.stmts.push(statement);
+        return id;
+    }
+
+    pub fn add_to_program(&mut self, id: StmtId) {
+        self.program.push(id);
+    }
+
+    pub fn program(&self) -> &[StmtId] {
+        return &self.program;
+    }
+
+    // for a document that reparses part of its program and keeps the rest
+    pub(crate) fn set_program(&mut self, program: Vec<StmtId>) {
+        self.program = program;
+    }
+
+    pub fn statement_count(&self) -> usize {
+        return self.stmts.len();
+    }
+
+    pub fn statement_ids(&self) -> impl Iterator<Item = StmtId> {
+        return (0..self.stmts.len()).map(StmtId::from_index);
+    }
+
+    pub fn add_docs(&mut self, id: StmtId, docs: Vec<Token>) {
+        if !docs.is_empty() {
+            self.docs.insert(id, docs);
+        }
+    }
+
+    // the DocComment tokens attached to a declaration; empty for any other statement
+    pub fn docs(&self, id: StmtId) -> &[Token] {
+        return self.docs.get(&id).map_or(&[], Vec::as_slice);
+    }
+
+    // every documented statement with its docs, in StmtId order
+    pub fn documented(&self) -> impl Iterator<Item = (StmtId, &[Token])> {
+        return self.docs.iter().map(|(id, docs)| (*id, docs.as_slice()));
+    }
+
+    pub fn print<N: Print>(&self, node: N) -> String {
+        return node.print(self);
+    }
+
+    // Compares the trees under each pair of roots by token type, lexeme and literal, ignoring
+    // lines, spans and where nodes sit in the arena.
+    pub fn same_shape(&self, other: &AST) -> bool {
+        return self.roots.len() == other.roots.len()
+            && self
+                .roots
+                .iter()
+                .zip(other.roots.iter())
+                .all(|(&id, &other_id)| self.same_tree(id, other, other_id))
+            && self.program.len() == other.program.len()
+            && self
+                .program
+                .iter()
+                .zip(other.program.iter())
+                .all(|(&id, &other_id)| self.same_statement(id, other, other_id));
+    }
+
+    // `same_tree` for statements
+    pub fn same_statement(&self, id: StmtId, other: &AST, other_id: StmtId) -> bool {
+        let same_token = |a: &Token, b: &Token| {
+            a.token_type == b.token_type && a.lexeme == b.lexeme && a.literal == b.literal
+        };
+        let same_expression = |a: &Option<ExprId>, b: &Option<ExprId>| match (a, b) {
+            (Some(a), Some(b)) => self.same_tree(*a, other, *b),
+            (None, None) => true,
+            _ => false,
+        };
+        let same_statements = |a: &[StmtId], b: &[StmtId]| {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| self.same_statement(*a, other, *b))
+        };
+        match (&self[id], &other[other_id]) {
+            (Statement::Expression { expression }, Statement::Expression { expression: e }) => {
+                self.same_tree(*expression, other, *e)
+            }
+            (Statement::Print { keyword, value }, Statement::Print { keyword: k, value: v }) => {
+                same_token(keyword, k) && self.same_tree(*value, other, *v)
+            }
+            (Statement::Var { name, initializer }, Statement::Var { name: n, initializer: i }) => {
+                same_token(name, n) && same_expression(initializer, i)
+            }
+            (Statement::Block { brace, statements }, Statement::Block { brace: b, statements: s }) => {
+                same_token(brace, b) && same_statements(statements, s)
+            }
+            (
+                Statement::If { keyword, condition, then_branch, else_branch },
+                Statement::If { keyword: k, condition: c, then_branch: t, else_branch: e },
+            ) => {
+                same_token(keyword, k)
+                    && self.same_tree(*condition, other, *c)
+                    && self.same_statement(*then_branch, other, *t)
+                    && match (else_branch, e) {
+                        (Some(a), Some(b)) => self.same_statement(*a, other, *b),
+                        (None, None) => true,
+                        _ => false,
                     }
-                    t.clone().into_string()
-                }
-            },
+            }
+            (Statement::Function { name, params, body }, Statement::Function { name: n, params: p, body: b }) => {
+                same_token(name, n)
+                    && params.len() == p.len()
+                    && params.iter().zip(p.iter()).all(|(param, other)| same_token(param, other))
+                    && same_statements(body, b)
+            }
+            (Statement::Return { keyword, value }, Statement::Return { keyword: k, value: v }) => {
+                same_token(keyword, k) && same_expression(value, v)
+            }
+            (
+                Statement::While { keyword, condition, body, increment },
+                Statement::While { keyword: k, condition: c, body: b, increment: i },
+            ) => {
+                same_token(keyword, k)
+                    && self.same_tree(*condition, other, *c)
+                    && self.same_statement(*body, other, *b)
+                    && same_expression(increment, i)
+            }
+            (
+                Statement::ForEach { keyword, name, iterable, body },
+                Statement::ForEach { keyword: k, name: n, iterable: i, body: b },
+            ) => {
+                same_token(keyword, k)
+                    && same_token(name, n)
+                    && self.same_tree(*iterable, other, *i)
+                    && self.same_statement(*body, other, *b)
+            }
+            (Statement::Break { keyword }, Statement::Break { keyword: k })
+            | (Statement::Continue { keyword }, Statement::Continue { keyword: k }) => same_token(keyword, k),
+            _ => false,
+        }
+    }
+
+    pub fn same_tree(&self, id: ExprId, other: &AST, other_id: ExprId) -> bool {
+        let same_token = |a: &Token, b: &Token| {
+            a.token_type == b.token_type && a.lexeme == b.lexeme && a.literal == b.literal
         };
-        return print_val;
+        match (&self[id], &other[other_id]) {
+            (Expression::Unary { operator, value }, Expression::Unary { operator: o, value: v }) => {
+                same_token(operator, o) && self.same_tree(*value, other, *v)
+            }
+            (
+                Expression::Binary { left, operator, right },
+                Expression::Binary { left: l, operator: o, right: r },
+            )
+            | (
+                Expression::Logical { left, operator, right },
+                Expression::Logical { left: l, operator: o, right: r },
+            ) => {
+                same_token(operator, o) && self.same_tree(*left, other, *l) && self.same_tree(*right, other, *r)
+            }
+            (Expression::Grouping { interior }, Expression::Grouping { interior: i }) => {
+                self.same_tree(*interior, other, *i)
+            }
+            (
+                Expression::Conditional { condition, question, then_branch, else_branch },
+                Expression::Conditional { condition: c, question: q, then_branch: t, else_branch: e },
+            ) => {
+                same_token(question, q)
+                    && self.same_tree(*condition, other, *c)
+                    && self.same_tree(*then_branch, other, *t)
+                    && self.same_tree(*else_branch, other, *e)
+            }
+            (Expression::Literal(literal), Expression::Literal(l)) => match (literal, l) {
+                (Literal::NUMBER(a), Literal::NUMBER(b))
+                | (Literal::STRING(a), Literal::STRING(b))
+                | (Literal::KEYWORD(a), Literal::KEYWORD(b)) => same_token(a, b),
+                _ => false,
+            },
+            (Expression::Variable { name }, Expression::Variable { name: n }) => same_token(name, n),
+            (Expression::Assign { name, value }, Expression::Assign { name: n, value: v }) => {
+                same_token(name, n) && self.same_tree(*value, other, *v)
+            }
+            (Expression::List { bracket, elements }, Expression::List { bracket: b, elements: e }) => {
+                same_token(bracket, b)
+                    && elements.len() == e.len()
+                    && elements.iter().zip(e.iter()).all(|(element, other_element)| self.same_tree(*element, other, *other_element))
+            }
+            (Expression::Map { brace, entries }, Expression::Map { brace: b, entries: e }) => {
+                same_token(brace, b)
+                    && entries.len() == e.len()
+                    && entries.iter().zip(e.iter()).all(|((key, value), (k, v))| {
+                        self.same_tree(*key, other, *k) && self.same_tree(*value, other, *v)
+                    })
+            }
+            (Expression::Index { object, bracket, index }, Expression::Index { object: o, bracket: b, index: i }) => {
+                same_token(bracket, b) && self.same_tree(*object, other, *o) && self.same_tree(*index, other, *i)
+            }
+            (
+                Expression::SetIndex { object, bracket, index, value },
+                Expression::SetIndex { object: o, bracket: b, index: i, value: v },
+            ) => {
+                same_token(bracket, b)
+                    && self.same_tree(*object, other, *o)
+                    && self.same_tree(*index, other, *i)
+                    && self.same_tree(*value, other, *v)
+            }
+            (Expression::Lambda { params, arrow, body }, Expression::Lambda { params: p, arrow: a, body: b }) => {
+                params.len() == p.len()
+                    && params.iter().zip(p.iter()).all(|(param, other)| same_token(param, other))
+                    && same_token(arrow, a)
+                    && self.same_tree(*body, other, *b)
+            }
+            (Expression::Function { keyword, params, body }, Expression::Function { keyword: k, params: p, body: b }) => {
+                same_token(keyword, k)
+                    && params.len() == p.len()
+                    && params.iter().zip(p.iter()).all(|(param, other)| same_token(param, other))
+                    && body.len() == b.len()
+                    && body.iter().zip(b.iter()).all(|(statement, other_statement)| self.same_statement(*statement, other, *other_statement))
+            }
+            (Expression::Call { callee, paren, arguments }, Expression::Call { callee: c, paren: p, arguments: a }) => {
+                same_token(paren, p)
+                    && self.same_tree(*callee, other, *c)
+                    && arguments.len() == a.len()
+                    && arguments.iter().zip(a.iter()).all(|(argument, other_argument)| self.same_tree(*argument, other, *other_argument))
+            }
+            _ => false,
+        }
     }
 }
 
-fn parenthesize(name: &str, exprs: Vec<Rc<Expression>>) -> String {
-    let mut string: String = String::from("(");
-    string.push_str(name);
-    for expr in exprs {
-        string.push_str(" ");
-        string.push_str(expr.print().as_str());
+impl Index<ExprId> for AST {
+    type Output = Expression;
+
+    fn index(&self, id: ExprId) -> &Expression {
+        return &self.exprs[id.index()];
     }
-    string.push(')');
+}
 
-    return string;
+impl IndexMut<ExprId> for AST {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expression {
+        return &mut self.exprs[id.index()];
+    }
 }
 
-pub struct AST {
-    ast: Vec<Rc<Expression>>,
+impl Index<StmtId> for AST {
+    type Output = Statement;
+
+    fn index(&self, id: StmtId) -> &Statement {
+        return &self.stmts[id.index()];
+    }
 }
 
-impl AST {
-    pub fn print(self) {
-        for exp in self.ast {
-            print!("{}", exp.print());
+impl IndexMut<StmtId> for AST {
+    fn index_mut(&mut self, id: StmtId) -> &mut Statement {
+        return &mut self.stmts[id.index()];
+    }
+}
+
+// Prints a node of `ast` in prefix notation: `(+ 1 (* 2 3))`, `(var x 1)`.
+pub trait Print {
+    fn print(&self, ast: &AST) -> String;
+}
+
+impl Print for ExprId {
+    fn print(&self, ast: &AST) -> String {
+        return Printer.visit_expression(ast, *self);
+    }
+}
+
+impl Print for StmtId {
+    fn print(&self, ast: &AST) -> String {
+        return Printer.visit_statement(ast, *self);
+    }
+}
+
+struct Printer;
+
+impl Visitor<String> for Printer {
+    fn combine(&mut self, results: Vec<String>) -> String {
+        unreachable!("the printer handles every node itself")
+    }
+    fn visit_unary(&mut self, ast: &AST, id: ExprId, operator: &Token, value: ExprId) -> String {
+        return self.parenthesize(ast, operator.lexeme.as_str(), &[value]);
+    }
+    fn visit_binary(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> String {
+        return self.parenthesize(ast, operator.lexeme.as_str(), &[left, right]);
+    }
+    fn visit_logical(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> String {
+        return self.parenthesize(ast, operator.lexeme.as_str(), &[left, right]);
+    }
+    fn visit_grouping(&mut self, ast: &AST, id: ExprId, interior: ExprId) -> String {
+        return self.parenthesize(ast, "group", &[interior]);
+    }
+    fn visit_conditional(&mut self, ast: &AST, id: ExprId, condition: ExprId, question: &Token, then_branch: ExprId, else_branch: ExprId) -> String {
+        return self.parenthesize(ast, "?:", &[condition, then_branch, else_branch]);
+    }
+    fn visit_lambda(&mut self, ast: &AST, id: ExprId, params: &[Token], arrow: &Token, body: ExprId) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        return format!("(=> ({}) {})", params.join(" "), self.visit_expression(ast, body));
+    }
+    fn visit_function_expression(&mut self, ast: &AST, id: ExprId, keyword: &Token, params: &[Token], body: &[StmtId]) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        let mut parts = vec![format!("({})", params.join(" "))];
+        parts.extend(body.iter().map(|statement| self.visit_statement(ast, *statement)));
+        return parenthesize_parts("fun", parts);
+    }
+    fn visit_call(&mut self, ast: &AST, id: ExprId, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> String {
+        let mut children = vec![callee];
+        children.extend_from_slice(arguments);
+        return self.parenthesize(ast, "call", &children);
+    }
+    fn visit_list(&mut self, ast: &AST, id: ExprId, bracket: &Token, elements: &[ExprId]) -> String {
+        return self.parenthesize(ast, "list", elements);
+    }
+    fn visit_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId) -> String {
+        return self.parenthesize(ast, "index", &[object, index]);
+    }
+    fn visit_map(&mut self, ast: &AST, id: ExprId, brace: &Token, entries: &[(ExprId, ExprId)]) -> String {
+        let children: Vec<ExprId> = entries.iter().flat_map(|(key, value)| [*key, *value]).collect();
+        return self.parenthesize(ast, "map", &children);
+    }
+    fn visit_set_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId, value: ExprId) -> String {
+        return self.parenthesize(ast, "setindex", &[object, index, value]);
+    }
+    fn visit_literal(&mut self, ast: &AST, id: ExprId, literal: &Literal) -> String {
+        match literal {
+            Literal::NUMBER(t) => t.lexeme.clone(),
+            Literal::STRING(t) => t.lexeme.clone(),
+            Literal::KEYWORD(t) => {
+                if t.token_type == TokenType::Nil {
+                    return String::from("nil");
+                }
+                t.clone().into_string()
+            }
+        }
+    }
+    fn visit_variable(&mut self, ast: &AST, id: ExprId, name: &Token) -> String {
+        return name.lexeme.clone();
+    }
+    fn visit_assign(&mut self, ast: &AST, id: ExprId, name: &Token, value: ExprId) -> String {
+        return format!("(= {} {})", name.lexeme, self.visit_expression(ast, value));
+    }
+}
+
+impl StatementVisitor<String> for Printer {
+    fn visit_expression_statement(&mut self, ast: &AST, id: StmtId, expression: ExprId) -> String {
+        return self.parenthesize(ast, ";", &[expression]);
+    }
+    fn visit_print(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: ExprId) -> String {
+        return self.parenthesize(ast, "print", &[value]);
+    }
+    fn visit_var(&mut self, ast: &AST, id: StmtId, name: &Token, initializer: Option<ExprId>) -> String {
+        let name = format!("var {}", name.lexeme);
+        return self.parenthesize(ast, &name, initializer.as_slice());
+    }
+    fn visit_block(&mut self, ast: &AST, id: StmtId, brace: &Token, statements: &[StmtId]) -> String {
+        let statements: Vec<String> = statements.iter().map(|statement| self.visit_statement(ast, *statement)).collect();
+        return parenthesize_parts("block", statements);
+    }
+    fn visit_if(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> String {
+        let mut parts = vec![self.visit_expression(ast, condition), self.visit_statement(ast, then_branch)];
+        parts.extend(else_branch.map(|else_branch| self.visit_statement(ast, else_branch)));
+        return parenthesize_parts("if", parts);
+    }
+    fn visit_function(&mut self, ast: &AST, id: StmtId, name: &Token, params: &[Token], body: &[StmtId]) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        let name = format!("fun {} ({})", name.lexeme, params.join(" "));
+        let body: Vec<String> = body.iter().map(|statement| self.visit_statement(ast, *statement)).collect();
+        return parenthesize_parts(&name, body);
+    }
+    fn visit_return(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: Option<ExprId>) -> String {
+        return self.parenthesize(ast, "return", value.as_slice());
+    }
+    fn visit_while(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, body: StmtId, increment: Option<ExprId>) -> String {
+        let mut parts = vec![self.visit_expression(ast, condition), self.visit_statement(ast, body)];
+        parts.extend(increment.map(|increment| self.visit_expression(ast, increment)));
+        return parenthesize_parts("while", parts);
+    }
+    fn visit_for_each(&mut self, ast: &AST, id: StmtId, keyword: &Token, name: &Token, iterable: ExprId, body: StmtId) -> String {
+        let parts = vec![name.lexeme.clone(), self.visit_expression(ast, iterable), self.visit_statement(ast, body)];
+        return parenthesize_parts("for", parts);
+    }
+    fn visit_break(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> String {
+        return String::from("(break)");
+    }
+    fn visit_continue(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> String {
+        return String::from("(continue)");
+    }
+}
+
+fn parenthesize_parts(name: &str, parts: Vec<String>) -> String {
+    let mut string = format!("({}", name);
+    for part in parts {
+        string.push(' ');
+        string.push_str(&part);
+    }
+    string.push(')');
+    return string;
+}
+
+impl Printer {
+    fn parenthesize(&mut self, ast: &AST, name: &str, exprs: &[ExprId]) -> String {
+        let mut string: String = String::from("(");
+        string.push_str(name);
+        for expr in exprs {
+            string.push(' ');
+            string.push_str(self.visit_expression(ast, *expr).as_str());
         }
+        string.push(')');
+
+        return string;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::rc::Rc;
+    use crate::tokens::Span;
 
-    #[test]
-    fn print_number_literal() {
-        let expr = Expression::Literal(Literal::NUMBER(Token {
+    fn number(lexeme: &str) -> Expression {
+        Expression::Literal(Literal::NUMBER(Token {
             token_type: TokenType::Number,
-            lexeme: String::from("42"),
+            lexeme: String::from(lexeme),
             literal: None,
             line: 1,
-        }));
+            column: 0,
+            span: Span::default(),
+            symbol: None,
+        }))
+    }
 
-        assert_eq!(Rc::new(expr).print(), "42");
+    fn operator(token_type: TokenType, lexeme: &str) -> Token {
+        Token {
+            token_type,
+            lexeme: String::from(lexeme),
+            literal: None,
+            line: 1,
+            column: 0,
+            span: Span::default(),
+            symbol: None,
+        }
+    }
+
+    #[test]
+    fn print_number_literal() {
+        let mut ast = AST::new();
+        let expr = ast.add(number("42"));
+
+        assert_eq!(ast.print(expr), "42");
     }
 
     #[test]
     fn print_unary_expression() {
-        let expr = Expression::Unary {
-            operator: Token {
-                token_type: TokenType::Minus,
-                lexeme: String::from("-"),
-                literal: None,
-                line: 1,
-            },
-            value: Rc::new(Expression::Literal(Literal::NUMBER(Token {
-                token_type: TokenType::Number,
-                lexeme: String::from("42"),
-                literal: None,
-                line: 1,
-            }))),
-        };
+        let mut ast = AST::new();
+        let value = ast.add(number("42"));
+        let expr = ast.add(Expression::Unary {
+            operator: operator(TokenType::Minus, "-"),
+            value,
+        });
 
-        assert_eq!(Rc::new(expr).print(), "(- 42)");
+        assert_eq!(ast.print(expr), "(- 42)");
     }
 
     #[test]
     fn print_binary_expression() {
-        let left = Rc::new(Expression::Literal(Literal::NUMBER(Token {
-            token_type: TokenType::Number,
-            lexeme: String::from("1"),
-            literal: None,
-            line: 1,
-        })));
+        let mut ast = AST::new();
+        let left = ast.add(number("1"));
+        let right = ast.add(number("2"));
 
-        let right = Rc::new(Expression::Literal(Literal::NUMBER(Token {
-            token_type: TokenType::Number,
-            lexeme: String::from("2"),
-            literal: None,
-            line: 1,
-        })));
-
-        let expr = Expression::Binary {
+        let expr = ast.add(Expression::Binary {
             left,
-            operator: Token {
-                token_type: TokenType::Plus,
-                lexeme: String::from("+"),
-                literal: None,
-                line: 1,
-            },
+            operator: operator(TokenType::Plus, "+"),
             right,
-        };
+        });
 
-        assert_eq!(Rc::new(expr).print(), "(+ 1 2)");
+        assert_eq!(ast.print(expr), "(+ 1 2)");
     }
 
     #[test]
     fn print_grouping_expression() {
-        let expr = Expression::Grouping {
-            interior: Rc::new(Expression::Literal(Literal::NUMBER(Token {
-                token_type: TokenType::Number,
-                lexeme: String::from("42"),
-                literal: None,
-                line: 1,
-            })),
-            ),
-        };
+        let mut ast = AST::new();
+        let interior = ast.add(number("42"));
+        let expr = ast.add(Expression::Grouping { interior });
 
-        assert_eq!(Rc::new(expr).print(), "(group 42)");
+        assert_eq!(ast.print(expr), "(group 42)");
     }
 
     #[test]
     fn print_nested_expression() {
-        let left = Rc::new(Expression::Literal(Literal::NUMBER(Token {
-            token_type: TokenType::Number,
-            lexeme: String::from("1"),
-            literal: None,
-            line: 1,
-        })));
-
-        let right = Rc::new(Expression::Literal(Literal::NUMBER(Token {
-            token_type: TokenType::Number,
-            lexeme: String::from("2"),
-            literal: None,
-            line: 1,
-        })));
+        let mut ast = AST::new();
+        let left = ast.add(number("1"));
+        let right = ast.add(number("2"));
 
-        let expr = Expression::Binary {
+        let expr = ast.add(Expression::Binary {
             left,
-            operator: Token {
-                token_type: TokenType::Plus,
-                lexeme: String::from("+"),
-                literal: None,
-                line: 1,
-            },
+            operator: operator(TokenType::Plus, "+"),
             right,
-        };
+        });
 
-        let outer_expr = Expression::Unary {
-            operator: Token {
-                token_type: TokenType::Minus,
-                lexeme: String::from("-"),
-                literal: None,
-                line: 1,
-            },
-            value: Rc::new(expr),
-        };
+        let outer_expr = ast.add(Expression::Unary {
+            operator: operator(TokenType::Minus, "-"),
+            value: expr,
+        });
 
-        assert_eq!(Rc::new(outer_expr).print(), "(- (+ 1 2))");
+        assert_eq!(ast.print(outer_expr), "(- (+ 1 2))");
     }
 
     #[test]
     fn print_literal_nil() {
-        let expr = Expression::Literal(Literal::KEYWORD(Token {
+        let mut ast = AST::new();
+        let expr = ast.add(Expression::Literal(Literal::KEYWORD(Token {
             token_type: TokenType::Nil,
             lexeme: "nil".to_string(),
             literal: None,
             line: 1,
-        }));
+            column: 0,
+            span: Span::default(),
+            symbol: None,
+        })));
+
+        assert_eq!(ast.print(expr), "nil");
+    }
+
+    #[test]
+    fn node_ids_are_stable() {
+        let mut ast = AST::new();
+        let one = ast.add(number("1"));
+        let two = ast.add(number("2"));
 
-        assert_eq!(Rc::new(expr).print(), "nil");
+        assert_ne!(one, two);
+        assert_eq!(ast.len(), 2);
+        assert_eq!(ast[two], number("2"));
+        assert_eq!(ast.ids().collect::<Vec<ExprId>>(), vec![one, two]);
     }
 }
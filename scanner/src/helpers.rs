@@ -1,9 +1,9 @@
 pub fn is_digit(c: char) -> bool {
-    return c >= '0' && c <= '9';
+    return c.is_ascii_digit();
 }
 
 pub fn is_alpha(c: char) -> bool {
-    return c >= 'a' && c <= 'z' || c >= 'A' && c <= 'Z' || c == '_';
+    return c.is_ascii_alphabetic() || c == '_';
 }
 
 pub fn is_alphanumeric(c: char) -> bool {
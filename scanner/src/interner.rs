@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// A handle to an interned string; equal names get equal symbols from the same interner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(self) -> usize {
+        return self.0 as usize;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+// The scanner, parser and later passes share one table so their symbols agree.
+pub type SymbolTable = Rc<RefCell<Interner>>;
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shared() -> SymbolTable {
+        return Rc::new(RefCell::new(Self::new()));
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.strings.push(name.clone());
+        self.symbols.insert(name, symbol);
+        return symbol;
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Symbol> {
+        return self.symbols.get(name).copied();
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        return &self.strings[symbol.index()];
+    }
+
+    pub fn len(&self) -> usize {
+        return self.strings.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.strings.is_empty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_same_name() {
+        let mut interner = Interner::new();
+        let a = interner.intern("count");
+        let b = interner.intern("other");
+        let c = interner.intern("count");
+
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.resolve(b), "other");
+        assert_eq!(interner.lookup("missing"), None);
+    }
+}
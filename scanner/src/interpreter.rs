@@ -0,0 +1,843 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::interner::{Interner, Symbol, SymbolTable};
+use crate::native::{list_index, map_key, missing_key, NativeError, NATIVES};
+use crate::statement::StmtId;
+use crate::tokens::{Token, TokenType};
+use crate::value::{Map, Value};
+use crate::visitor::{StatementVisitor, Visitor};
+use diagnostics::{codes, Diagnostic};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+// One scope's variables, chained to the scope around it. Names are symbols, so looking one up
+// compares integers rather than strings.
+#[derive(Debug, Default)]
+struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Env>,
+}
+
+type Env = Rc<RefCell<Environment>>;
+
+impl Environment {
+    fn inside(enclosing: &Env) -> Env {
+        return Rc::new(RefCell::new(Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing.clone()),
+        }));
+    }
+
+    fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    fn get(&self, name: Symbol) -> Option<Value> {
+        if let Some(value) = self.values.get(&name) {
+            return Some(value.clone());
+        }
+        return self.enclosing.as_ref()?.borrow().get(name);
+    }
+
+    // false if no scope declares `name`
+    fn assign(&mut self, name: Symbol, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(&name) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => return enclosing.borrow_mut().assign(name, value),
+            None => return false,
+        }
+    }
+}
+
+// A function value: its parameters, its body and the scope it was created in. The body is ids
+// into the AST the function was parsed into, so it can only be called while that AST, or one
+// that grew from it, is being interpreted.
+pub struct Function {
+    name: Option<String>,
+    params: Vec<Symbol>,
+    body: Body,
+    closure: Env,
+}
+
+enum Body {
+    Block(Vec<StmtId>),
+    // the arrow form, `(a) => a * 2`
+    Expression(ExprId),
+}
+
+// Functions are only equal to themselves.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        return std::ptr::eq(self, other);
+    }
+}
+
+// The closure can hold the function itself, so it is not printed.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "<fun {}>", name),
+            None => write!(f, "<fun>"),
+        }
+    }
+}
+
+// Calls nested deeper than this are reported rather than left to overflow the native stack.
+const MAX_CALL_DEPTH: usize = 200;
+
+// Why evaluation stopped before reaching a value.
+#[derive(Debug)]
+enum Unwind {
+    Error(Box<Diagnostic>),
+    // a `return` on its way out to the call it ends
+    Return(Value),
+    // a `break` or `continue` on its way out to the loop it belongs to
+    Break,
+    Continue,
+}
+
+type Evaluation = Result<Value, Unwind>;
+
+fn error(token: &Token, code: &'static str, message: &str) -> Unwind {
+    return Unwind::Error(Box::new(Diagnostic::error(message, token.span).with_code(code)));
+}
+
+fn native_error(token: &Token, (code, message): NativeError) -> Unwind {
+    return error(token, code, &message);
+}
+
+// A tree-walking evaluator. Globals persist between calls to `interpret`, so a REPL can feed it
+// one line at a time.
+//
+// Variables are keyed by the symbols the scanner gave their names, so the program must be
+// scanned with the interpreter's `interner()`. Names without a symbol, as in a built or loaded
+// tree, are interned when they are used.
+pub struct Interpreter {
+    globals: Env,
+    interner: SymbolTable,
+    output: Box<dyn Write>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        return Self {
+            globals: Rc::default(),
+            interner: Interner::shared(),
+            output: Box::new(std::io::stdout()),
+        }
+        .with_natives();
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    // Shares `interner` with the scanner. Set it before running anything: the globals defined
+    // so far are dropped, as their symbols belong to the old table.
+    pub fn with_interner(mut self, interner: SymbolTable) -> Self {
+        self.interner = interner;
+        self.globals = Rc::default();
+        self.with_natives()
+    }
+
+    pub fn interner(&self) -> SymbolTable {
+        return self.interner.clone();
+    }
+
+    fn with_natives(self) -> Self {
+        for native in NATIVES {
+            let name = self.interner.borrow_mut().intern(native.name);
+            self.globals.borrow_mut().define(name, Value::Native(native));
+        }
+        self
+    }
+
+    // where `print` writes; stdout by default
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    // Runs `statements` of `ast` in order and stops at the first runtime error.
+    pub fn interpret(&mut self, ast: &AST, statements: &[StmtId]) -> Result<(), Box<Diagnostic>> {
+        let mut evaluator = self.evaluator();
+        for statement in statements {
+            match evaluator.visit_statement(ast, *statement) {
+                Ok(_) => {}
+                // the parser reports a `return` outside a function, and a `break` or `continue`
+                // outside a loop; if one runs anyway it ends the program
+                Err(Unwind::Return(_) | Unwind::Break | Unwind::Continue) => return Ok(()),
+                Err(Unwind::Error(diagnostic)) => return Err(diagnostic),
+            }
+        }
+        return Ok(());
+    }
+
+    // the globals defined so far, built-in functions included
+    pub fn globals(&self) -> Vec<Symbol> {
+        let mut names: Vec<Symbol> = self.globals.borrow().values.keys().copied().collect();
+        names.sort();
+        return names;
+    }
+
+    pub fn evaluate(&mut self, ast: &AST, id: ExprId) -> Result<Value, Box<Diagnostic>> {
+        return self.evaluator().visit_expression(ast, id).map_err(Unwind::into_diagnostic);
+    }
+
+    fn evaluator(&mut self) -> Evaluator<'_> {
+        return Evaluator {
+            environment: self.globals.clone(),
+            interner: self.interner.clone(),
+            output: &mut self.output,
+            calls: 0,
+        };
+    }
+}
+
+impl Unwind {
+    fn into_diagnostic(self) -> Box<Diagnostic> {
+        match self {
+            Unwind::Error(diagnostic) => return diagnostic,
+            Unwind::Return(_) => unreachable!("a return outside any function"),
+            Unwind::Break | Unwind::Continue => unreachable!("a break or continue outside any loop"),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    environment: Env,
+    interner: SymbolTable,
+    output: &'a mut Box<dyn Write>,
+    // how many calls are in progress
+    calls: usize,
+}
+
+impl Evaluator<'_> {
+    fn symbol(&self, name: &Token) -> Symbol {
+        match name.symbol {
+            Some(symbol) => {
+                debug_assert_eq!(self.interner.borrow().resolve(symbol), name.lexeme, "scanned with another interner");
+                return symbol;
+            }
+            None => return self.interner.borrow_mut().intern(&name.lexeme),
+        }
+    }
+
+    fn number(&self, operator: &Token, value: &Value) -> Result<f64, Unwind> {
+        match value {
+            Value::Number(number) => return Ok(*number),
+            value => {
+                let message = format!("operands of '{}' must be numbers, not {}", operator.lexeme, value.type_name());
+                return Err(error(operator, codes::OPERAND_TYPE, &message));
+            }
+        }
+    }
+
+    // runs `statements` in a new scope inside the current one
+    fn block(&mut self, ast: &AST, statements: &[StmtId]) -> Evaluation {
+        let scope = Environment::inside(&self.environment);
+        let enclosing = std::mem::replace(&mut self.environment, scope);
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.visit_statement(ast, *statement).map(|_| ()));
+        self.environment = enclosing;
+        return result.map(|_| Value::Nil);
+    }
+
+    fn function(&self, name: Option<&Token>, params: &[Token], body: Body) -> Value {
+        return Value::Function(Rc::new(Function {
+            name: name.map(|name| name.lexeme.clone()),
+            params: params.iter().map(|param| self.symbol(param)).collect(),
+            body,
+            closure: self.environment.clone(),
+        }));
+    }
+
+    // runs `function` in a new scope inside its closure, with the parameters bound
+    fn call(&mut self, ast: &AST, function: &Function, paren: &Token, arguments: Vec<Value>) -> Evaluation {
+        if arguments.len() != function.params.len() {
+            let message = format!("expected {} arguments but got {}", function.params.len(), arguments.len());
+            return Err(error(paren, codes::WRONG_ARGUMENT_COUNT, &message));
+        }
+        if self.calls == MAX_CALL_DEPTH {
+            let message = format!("calls nested more than {} deep", MAX_CALL_DEPTH);
+            return Err(error(paren, codes::STACK_OVERFLOW, &message));
+        }
+        let scope = Environment::inside(&function.closure);
+        for (param, argument) in function.params.iter().zip(arguments) {
+            scope.borrow_mut().define(*param, argument);
+        }
+        let enclosing = std::mem::replace(&mut self.environment, scope);
+        self.calls += 1;
+        let result = match &function.body {
+            Body::Expression(body) => self.visit_expression(ast, *body),
+            Body::Block(body) => body
+                .iter()
+                .try_for_each(|statement| self.visit_statement(ast, *statement).map(|_| ()))
+                .map(|_| Value::Nil),
+        };
+        self.calls -= 1;
+        self.environment = enclosing;
+        match result {
+            Err(Unwind::Return(value)) => return Ok(value),
+            // a loop does not reach into the functions it calls; the parser reports these
+            Err(Unwind::Break | Unwind::Continue) => return Ok(Value::Nil),
+            result => return result,
+        }
+    }
+
+    fn not_indexable(&self, bracket: &Token, object: &Value) -> Unwind {
+        let message = format!("can only index lists and maps, not {}", object.type_name());
+        return error(bracket, codes::NOT_INDEXABLE, &message);
+    }
+}
+
+impl Visitor<Evaluation> for Evaluator<'_> {
+    fn combine(&mut self, results: Vec<Evaluation>) -> Evaluation {
+        unreachable!("the evaluator handles every node itself")
+    }
+    fn visit_unary(&mut self, ast: &AST, id: ExprId, operator: &Token, value: ExprId) -> Evaluation {
+        let value = self.visit_expression(ast, value)?;
+        match operator.token_type {
+            TokenType::Bang => return Ok(Value::Bool(!value.is_truthy())),
+            _ => return Ok(Value::Number(-self.number(operator, &value)?)),
+        }
+    }
+    fn visit_binary(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> Evaluation {
+        let left = self.visit_expression(ast, left)?;
+        let right = self.visit_expression(ast, right)?;
+        let value = match (&operator.token_type, &left, &right) {
+            (TokenType::Comma, _, _) => right,
+            (TokenType::EqualEqual, _, _) => Value::Bool(left == right),
+            (TokenType::BangEqual, _, _) => Value::Bool(left != right),
+            (TokenType::Plus, Value::String(left), Value::String(right)) => Value::String(format!("{}{}", left, right).into()),
+            (TokenType::Plus, Value::String(_), _) | (TokenType::Plus, _, Value::String(_)) => {
+                let message = format!("cannot add {} and {}", left.type_name(), right.type_name());
+                return Err(error(operator, codes::OPERAND_TYPE, &message));
+            }
+            (token_type, _, _) => {
+                let (left, right) = (self.number(operator, &left)?, self.number(operator, &right)?);
+                match token_type {
+                    TokenType::Plus => Value::Number(left + right),
+                    TokenType::Minus => Value::Number(left - right),
+                    TokenType::Star => Value::Number(left * right),
+                    TokenType::Slash => Value::Number(left / right),
+                    TokenType::Percent => Value::Number(left % right),
+                    TokenType::StarStar => Value::Number(left.powf(right)),
+                    TokenType::TildeSlash if right == 0.0 => {
+                        return Err(error(operator, codes::DIVISION_BY_ZERO, "integer division by zero"));
+                    }
+                    TokenType::TildeSlash => Value::Number((left / right).floor()),
+                    TokenType::Greater => Value::Bool(left > right),
+                    TokenType::GreaterEqual => Value::Bool(left >= right),
+                    TokenType::Less => Value::Bool(left < right),
+                    TokenType::LessEqual => Value::Bool(left <= right),
+                    token_type => unreachable!("{:?} is not a binary operator", token_type),
+                }
+            }
+        };
+        return Ok(value);
+    }
+    // the right operand is only evaluated when the left one does not decide the result
+    fn visit_logical(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> Evaluation {
+        let left = self.visit_expression(ast, left)?;
+        let decided = match operator.token_type {
+            TokenType::Or => left.is_truthy(),
+            _ => !left.is_truthy(),
+        };
+        if decided {
+            return Ok(left);
+        }
+        return self.visit_expression(ast, right);
+    }
+    fn visit_grouping(&mut self, ast: &AST, id: ExprId, interior: ExprId) -> Evaluation {
+        return self.visit_expression(ast, interior);
+    }
+    // only the chosen branch is evaluated
+    fn visit_conditional(&mut self, ast: &AST, id: ExprId, condition: ExprId, question: &Token, then_branch: ExprId, else_branch: ExprId) -> Evaluation {
+        if self.visit_expression(ast, condition)?.is_truthy() {
+            return self.visit_expression(ast, then_branch);
+        }
+        return self.visit_expression(ast, else_branch);
+    }
+    fn visit_literal(&mut self, ast: &AST, id: ExprId, literal: &Literal) -> Evaluation {
+        let value = match literal {
+            Literal::NUMBER(token) => Value::Number(token.lexeme.parse().unwrap_or(f64::NAN)),
+            Literal::STRING(token) => Value::String(token.literal.as_deref().unwrap_or_default().into()),
+            Literal::KEYWORD(token) => match token.token_type {
+                TokenType::True => Value::Bool(true),
+                TokenType::False => Value::Bool(false),
+                _ => Value::Nil,
+            },
+        };
+        return Ok(value);
+    }
+    fn visit_variable(&mut self, ast: &AST, id: ExprId, name: &Token) -> Evaluation {
+        return self.environment.borrow().get(self.symbol(name)).ok_or_else(|| {
+            error(name, codes::UNDEFINED_VARIABLE, &format!("undefined variable '{}'", name.lexeme))
+        });
+    }
+    fn visit_assign(&mut self, ast: &AST, id: ExprId, name: &Token, value: ExprId) -> Evaluation {
+        let value = self.visit_expression(ast, value)?;
+        if !self.environment.borrow_mut().assign(self.symbol(name), value.clone()) {
+            return Err(error(name, codes::UNDEFINED_VARIABLE, &format!("undefined variable '{}'", name.lexeme)));
+        }
+        return Ok(value);
+    }
+    fn visit_lambda(&mut self, ast: &AST, id: ExprId, params: &[Token], arrow: &Token, body: ExprId) -> Evaluation {
+        return Ok(self.function(None, params, Body::Expression(body)));
+    }
+    fn visit_function_expression(&mut self, ast: &AST, id: ExprId, keyword: &Token, params: &[Token], body: &[StmtId]) -> Evaluation {
+        return Ok(self.function(None, params, Body::Block(body.to_vec())));
+    }
+    fn visit_call(&mut self, ast: &AST, id: ExprId, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> Evaluation {
+        let callee = self.visit_expression(ast, callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.visit_expression(ast, *argument))
+            .collect::<Result<Vec<Value>, Unwind>>()?;
+        match callee {
+            Value::Function(function) => return self.call(ast, &function, paren, arguments),
+            Value::Native(native) if arguments.len() != native.arity => {
+                let message = format!("expected {} arguments but got {}", native.arity, arguments.len());
+                return Err(error(paren, codes::WRONG_ARGUMENT_COUNT, &message));
+            }
+            Value::Native(native) => return (native.function)(&arguments).map_err(|failure| native_error(paren, failure)),
+            callee => {
+                let message = format!("can only call functions, not {}", callee.type_name());
+                return Err(error(paren, codes::NOT_CALLABLE, &message));
+            }
+        }
+    }
+    fn visit_list(&mut self, ast: &AST, id: ExprId, bracket: &Token, elements: &[ExprId]) -> Evaluation {
+        let elements = elements
+            .iter()
+            .map(|element| self.visit_expression(ast, *element))
+            .collect::<Result<Vec<Value>, Unwind>>()?;
+        return Ok(Value::list(elements));
+    }
+    fn visit_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId) -> Evaluation {
+        let object = self.visit_expression(ast, object)?;
+        let index = self.visit_expression(ast, index)?;
+        match &object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let position = list_index(&index, list.len(), false).map_err(|failure| native_error(bracket, failure))?;
+                return Ok(list[position].clone());
+            }
+            Value::Map(map) => {
+                let key = map_key(&index).map_err(|failure| native_error(bracket, failure))?;
+                let value = map.borrow().get(&key).cloned();
+                return value.ok_or_else(|| native_error(bracket, missing_key(&key)));
+            }
+            object => return Err(self.not_indexable(bracket, object)),
+        }
+    }
+    // keys and values are evaluated in source order; a repeated key keeps its first place and
+    // its last value
+    fn visit_map(&mut self, ast: &AST, id: ExprId, brace: &Token, entries: &[(ExprId, ExprId)]) -> Evaluation {
+        let mut map = Map::default();
+        for (key, value) in entries {
+            let key = self.visit_expression(ast, *key)?;
+            let key = map_key(&key).map_err(|failure| native_error(brace, failure))?;
+            map.insert(key, self.visit_expression(ast, *value)?);
+        }
+        return Ok(Value::map(map));
+    }
+    fn visit_set_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId, value: ExprId) -> Evaluation {
+        let object = self.visit_expression(ast, object)?;
+        let index = self.visit_expression(ast, index)?;
+        let value = self.visit_expression(ast, value)?;
+        match &object {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let position = list_index(&index, list.len(), false).map_err(|failure| native_error(bracket, failure))?;
+                list[position] = value.clone();
+            }
+            // assigning to a missing key adds it
+            Value::Map(map) => {
+                let key = map_key(&index).map_err(|failure| native_error(bracket, failure))?;
+                map.borrow_mut().insert(key, value.clone());
+            }
+            object => return Err(self.not_indexable(bracket, object)),
+        }
+        return Ok(value);
+    }
+}
+
+impl StatementVisitor<Evaluation> for Evaluator<'_> {
+    fn visit_expression_statement(&mut self, ast: &AST, id: StmtId, expression: ExprId) -> Evaluation {
+        self.visit_expression(ast, expression)?;
+        return Ok(Value::Nil);
+    }
+    fn visit_print(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: ExprId) -> Evaluation {
+        let value = self.visit_expression(ast, value)?;
+        // a closed stdout is not the program's fault
+        let _ = writeln!(self.output, "{}", value);
+        return Ok(Value::Nil);
+    }
+    fn visit_var(&mut self, ast: &AST, id: StmtId, name: &Token, initializer: Option<ExprId>) -> Evaluation {
+        let value = match initializer {
+            Some(initializer) => self.visit_expression(ast, initializer)?,
+            None => Value::Nil,
+        };
+        self.environment.borrow_mut().define(self.symbol(name), value);
+        return Ok(Value::Nil);
+    }
+    fn visit_block(&mut self, ast: &AST, id: StmtId, brace: &Token, statements: &[StmtId]) -> Evaluation {
+        return self.block(ast, statements);
+    }
+    fn visit_if(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> Evaluation {
+        if self.visit_expression(ast, condition)?.is_truthy() {
+            return self.visit_statement(ast, then_branch);
+        }
+        match else_branch {
+            Some(else_branch) => return self.visit_statement(ast, else_branch),
+            None => return Ok(Value::Nil),
+        }
+    }
+    // the function closes over the scope that declares it, so it can call itself by name
+    fn visit_function(&mut self, ast: &AST, id: StmtId, name: &Token, params: &[Token], body: &[StmtId]) -> Evaluation {
+        let function = self.function(Some(name), params, Body::Block(body.to_vec()));
+        self.environment.borrow_mut().define(self.symbol(name), function);
+        return Ok(Value::Nil);
+    }
+    fn visit_return(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: Option<ExprId>) -> Evaluation {
+        let value = match value {
+            Some(value) => self.visit_expression(ast, value)?,
+            None => Value::Nil,
+        };
+        return Err(Unwind::Return(value));
+    }
+    fn visit_while(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, body: StmtId, increment: Option<ExprId>) -> Evaluation {
+        while self.visit_expression(ast, condition)?.is_truthy() {
+            match self.visit_statement(ast, body) {
+                Ok(_) | Err(Unwind::Continue) => {}
+                Err(Unwind::Break) => break,
+                Err(unwind) => return Err(unwind),
+            }
+            if let Some(increment) = increment {
+                self.visit_expression(ast, increment)?;
+            }
+        }
+        return Ok(Value::Nil);
+    }
+    // Loops over the elements of a list, or the keys of a map in insertion order, as they were
+    // when the loop started, so adding to it inside the body does not make the loop run longer.
+    // Each pass has its own scope, so a closure made in the body keeps the element of its pass.
+    fn visit_for_each(&mut self, ast: &AST, id: StmtId, keyword: &Token, name: &Token, iterable: ExprId, body: StmtId) -> Evaluation {
+        let elements = match self.visit_expression(ast, iterable)? {
+            Value::List(list) => list.borrow().clone(),
+            Value::Map(map) => map.borrow().entries().map(|(key, _)| key.to_value()).collect(),
+            value => {
+                let message = format!("can only loop over lists and maps, not {}", value.type_name());
+                return Err(error(keyword, codes::NOT_ITERABLE, &message));
+            }
+        };
+        let name = self.symbol(name);
+        for element in elements {
+            let scope = Environment::inside(&self.environment);
+            scope.borrow_mut().define(name, element);
+            let enclosing = std::mem::replace(&mut self.environment, scope);
+            let result = self.visit_statement(ast, body);
+            self.environment = enclosing;
+            match result {
+                Ok(_) | Err(Unwind::Continue) => {}
+                Err(Unwind::Break) => break,
+                Err(unwind) => return Err(unwind),
+            }
+        }
+        return Ok(Value::Nil);
+    }
+    fn visit_break(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> Evaluation {
+        return Err(Unwind::Break);
+    }
+    fn visit_continue(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> Evaluation {
+        return Err(Unwind::Continue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, Scanner};
+
+    // `print` output, shared with the test after the interpreter has taken it
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            return Ok(bytes.len());
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    fn run(source: &str) -> (String, Result<(), Box<Diagnostic>>) {
+        let diagnostics = diagnostics::collector();
+        let output = Output::default();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        let scanner = Scanner::new(source).with_interner(interpreter.interner());
+        let mut parser = Parser::new(scanner).with_sink(diagnostics.clone());
+        let program = parser.parse_program();
+        assert!(diagnostics.borrow().is_empty(), "{:?}", diagnostics.borrow());
+        let result = interpreter.interpret(parser.ast(), &program);
+        let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+        return (printed, result);
+    }
+
+    fn output(source: &str) -> String {
+        let (printed, result) = run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        return printed;
+    }
+
+    #[test]
+    fn test_arithmetic_and_printing() {
+        assert_eq!(output("print 1 + 2 * 3;"), "7\n");
+        assert_eq!(output("print -2 ** 2, 7 % 4, 7 ~/ 2, -7 ~/ 2, 1 / 2;"), "0.5\n");
+        assert_eq!(output("print (-2) ** 2; print 7 % 4; print -7 ~/ 2;"), "4\n3\n-4\n");
+        assert_eq!(output("print \"a\" + \"b\"; print nil; print !nil; print 1 == 1.0;"), "ab\nnil\ntrue\ntrue\n");
+        assert_eq!(output("print 1 < 2 ? \"yes\" : \"no\";"), "yes\n");
+    }
+
+    #[test]
+    fn test_variables_and_scopes() {
+        let source = "var a = 1; var b; { var a = 2; b = a; print a; } print a; print b; a = b = 3; print a + b;";
+        assert_eq!(output(source), "2\n1\n2\n6\n");
+        assert_eq!(output("var x = 1; if (x > 0) print \"pos\"; else print \"neg\";"), "pos\n");
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        // the right operand, and the branch not taken, would fail if they were evaluated
+        let source = "var calls = 0;\n\
+                      print false and (calls = calls + 1);\n\
+                      print true or (calls = calls + 1);\n\
+                      print nil or \"default\";\n\
+                      print 1 and 2;\n\
+                      print true ? calls : undefined;\n\
+                      print false ? undefined : calls;\n\
+                      print calls;";
+        assert_eq!(output(source), "false\ntrue\ndefault\n2\n0\n0\n0\n");
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        for (source, code, message) in [
+            ("print 1 + \"a\";", codes::OPERAND_TYPE, "cannot add number and string"),
+            ("print -\"a\";", codes::OPERAND_TYPE, "operands of '-' must be numbers, not string"),
+            ("print x;", codes::UNDEFINED_VARIABLE, "undefined variable 'x'"),
+            ("x = 1;", codes::UNDEFINED_VARIABLE, "undefined variable 'x'"),
+            ("print 1 ~/ 0;", codes::DIVISION_BY_ZERO, "integer division by zero"),
+        ] {
+            let (_, result) = run(source);
+            let diagnostic = result.unwrap_err();
+            assert_eq!(diagnostic.code, Some(code), "{}", source);
+            assert_eq!(diagnostic.message, message, "{}", source);
+        }
+
+        // output before the error stays; nothing after it runs
+        let (printed, result) = run("print 1;\nprint true < 2;\nprint 3;");
+        assert_eq!(printed, "1\n");
+        assert_eq!(result.unwrap_err().primary_span.start, 20);
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\n\
+                      print fib(15);\n\
+                      var twice = (f) => (x) => f(f(x));\n\
+                      print twice((x) => x * 3)(2);\n\
+                      print fun (a, b) { print a; return a + b; }(1, 2);\n\
+                      fun nothing() {}\n\
+                      print nothing(); print nothing; print (a) => a; print fib == fib;";
+        assert_eq!(output(source), "610\n18\n1\n3\nnil\n<fun nothing>\n<fun>\ntrue\n");
+    }
+
+    #[test]
+    fn test_closures() {
+        // each counter keeps its own `count`, which outlives the call that declared it
+        let source = "fun counter() { var count = 0; return fun () { count = count + 1; return count; }; }\n\
+                      var a = counter(); var b = counter();\n\
+                      a(); a(); b();\n\
+                      print a(); print b();\n\
+                      var x = \"global\";\n\
+                      { var x = \"block\"; var show = () => x; x = \"changed\"; print show(); }";
+        assert_eq!(output(source), "3\n2\nchanged\n");
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_eq!(output("var i = 0; while (i < 3) { print i; i = i + 1; }"), "0\n1\n2\n");
+        // `continue` still runs the increment, so this does not loop forever
+        assert_eq!(output("for (var i = 0; i < 5; i = i + 1) { if (i % 2 == 0) continue; print i; }"), "1\n3\n");
+        assert_eq!(output("for (var i = 0;; i = i + 1) { if (i == 2) break; print i; }"), "0\n1\n");
+        // the loop variable belongs to the loop
+        assert_eq!(output("var i = \"outer\"; for (var i = 0; i < 1; i = i + 1) {} print i;"), "outer\n");
+        // `break` leaves only the innermost loop
+        let source = "for (var i = 0; i < 2; i = i + 1) { var j = 0; while (true) { if (j == 2) break; print i * 10 + j; j = j + 1; } }";
+        assert_eq!(output(source), "0\n1\n10\n11\n");
+        // loops inside functions and lambdas called from a loop
+        let source = "fun sum(n) { var total = 0; for (var i = 1; i <= n; i = i + 1) { if (i == 3) continue; total = total + i; } return total; }\n\
+                      var first = (n) => fun () { var i = 0; while (true) { if (i * i >= n) return i; i = i + 1; } }();\n\
+                      for (var k = 0; k < 2; k = k + 1) { print sum(4); print first(10); }";
+        assert_eq!(output(source), "7\n4\n7\n4\n");
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(output("print [1, \"a\", nil, [true, []]];"), "[1, \"a\", nil, [true, []]]\n");
+        assert_eq!(output("var xs = [1, 2, 3]; xs[0] = xs[2] + 1; print xs; print xs[1 + 1];"), "[4, 2, 3]\n3\n");
+        // a list is shared, not copied, and only equal to itself
+        assert_eq!(output("var xs = [1]; var ys = xs; push(ys, 2); print xs; print xs == ys; print [1] == [1];"), "[1, 2]\ntrue\nfalse\n");
+        let source = "var xs = [1, 2, 3];\n\
+                      print pop(xs); insert(xs, 0, 0); insert(xs, 3, 9); print xs;\n\
+                      print remove(xs, 1); print xs; print len(xs); print len(\"héllo\");\n\
+                      print slice(xs, 1, 3); print slice(xs, 0, 0); print slice(xs, 3, 3); print len;";
+        assert_eq!(output(source), "3\n[0, 1, 2, 9]\n1\n[0, 2, 9]\n3\n5\n[2, 9]\n[]\n[]\n<native fun len>\n");
+        // a list that holds itself
+        assert_eq!(output("var xs = [1]; push(xs, xs); print xs;"), "[1, [...]]\n");
+    }
+
+    #[test]
+    fn test_list_iteration() {
+        assert_eq!(output("for (var x : [1, 2, 3]) print x * 2;"), "2\n4\n6\n");
+        assert_eq!(output("for (var x : [1, 2, 3, 4]) { if (x == 2) continue; if (x == 4) break; print x; }"), "1\n3\n");
+        // the loop runs over the elements the list had when it started
+        assert_eq!(output("var xs = [1, 2]; for (var x : xs) push(xs, x); print xs;"), "[1, 2, 1, 2]\n");
+        // each pass binds a new variable, which closures keep
+        let source = "var fs = []; for (var x : [1, 2]) push(fs, () => x); for (var f : fs) print f();";
+        assert_eq!(output(source), "1\n2\n");
+    }
+
+    #[test]
+    fn test_list_errors() {
+        for (source, code, message) in [
+            ("[1, 2][2];", codes::INDEX_OUT_OF_BOUNDS, "index 2 is out of bounds for a list of length 2"),
+            ("var xs = []; xs[-1] = 1;", codes::INDEX_OUT_OF_BOUNDS, "index -1 is out of bounds for a list of length 0"),
+            ("[1][0.5];", codes::INVALID_INDEX, "list index must be a whole number, not 0.5"),
+            ("[1][\"0\"];", codes::INVALID_INDEX, "list index must be a whole number, not string"),
+            ("var n = 1; n[0];", codes::NOT_INDEXABLE, "can only index lists and maps, not number"),
+            ("for (var x : \"abc\") print x;", codes::NOT_ITERABLE, "can only loop over lists and maps, not string"),
+            ("pop([]);", codes::INDEX_OUT_OF_BOUNDS, "pop from an empty list"),
+            ("insert([], 1, 0);", codes::INDEX_OUT_OF_BOUNDS, "index 1 is out of bounds for a list of length 0"),
+            ("slice([1, 2], 2, 1);", codes::INDEX_OUT_OF_BOUNDS, "slice starts at 2 but ends at 1"),
+            ("push(1, 2);", codes::ARGUMENT_TYPE, "push expects a list, not number"),
+            ("len(nil);", codes::ARGUMENT_TYPE, "len expects a list, a map or a string, not nil"),
+            ("len([], []);", codes::WRONG_ARGUMENT_COUNT, "expected 1 arguments but got 2"),
+        ] {
+            let (_, result) = run(source);
+            let diagnostic = result.unwrap_err();
+            assert_eq!(diagnostic.code, Some(code), "{}", source);
+            assert_eq!(diagnostic.message, message, "{}", source);
+        }
+
+        // an index error points at the `[` of the indexing, a call's at its `)`
+        let (_, result) = run("var xs = [1];\nprint xs[1];");
+        assert_eq!(result.unwrap_err().primary_span.start, 22);
+        let (_, result) = run("remove([], 0);");
+        assert_eq!(result.unwrap_err().primary_span.start, 12);
+    }
+
+    #[test]
+    fn test_maps() {
+        let source = "var config = {\"host\": \"x\", \"port\": 80, true: [1], \"host\": \"y\"};\n\
+                      print config; print config[\"port\"] + 1; print len(config);\n\
+                      config[\"user\"] = nil; config[\"port\"] = 81;\n\
+                      print keys(config); print values(config);\n\
+                      print has(config, \"user\"); print has(config, 80);\n\
+                      print remove(config, true); print config;";
+        assert_eq!(
+            output(source),
+            "{\"host\": \"y\", \"port\": 80, true: [1]}\n81\n3\n\
+             [\"host\", \"port\", true, \"user\"]\n[\"y\", 81, [1], nil]\n\
+             true\nfalse\n[1]\n{\"host\": \"y\", \"port\": 81, \"user\": nil}\n"
+        );
+        // numbers that compare equal are the same key
+        assert_eq!(output("var m = ({0: \"zero\"}); print m[-0]; print m[0.0];"), "zero\nzero\n");
+        // a loop sees the keys in insertion order, as they were when it started
+        let source = "var m = ({\"b\": 1, \"a\": 2}); for (var k : m) { m[k + k] = 0; print k; } print len(m);";
+        assert_eq!(output(source), "b\na\n4\n");
+        assert_eq!(output("var m = ({}); m[\"self\"] = m; print m; print m == m;"), "{\"self\": {...}}\ntrue\n");
+    }
+
+    #[test]
+    fn test_map_errors() {
+        for (source, code, message) in [
+            ("({\"a\": 1})[\"b\"];", codes::MISSING_KEY, "key \"b\" is not in the map"),
+            ("remove(({1: 1}), 2);", codes::MISSING_KEY, "key 2 is not in the map"),
+            ("var k; var m = ({k: 1});", codes::MAP_KEY_TYPE, "map keys must be strings, numbers or booleans, not nil"),
+            ("var m = ({}); m[[]] = 1;", codes::MAP_KEY_TYPE, "map keys must be strings, numbers or booleans, not list"),
+            ("has(({}), () => 1);", codes::MAP_KEY_TYPE, "map keys must be strings, numbers or booleans, not function"),
+            ("keys([]);", codes::ARGUMENT_TYPE, "keys expects a map, not list"),
+        ] {
+            let (_, result) = run(source);
+            let diagnostic = result.unwrap_err();
+            assert_eq!(diagnostic.code, Some(code), "{}", source);
+            assert_eq!(diagnostic.message, message, "{}", source);
+        }
+
+        // a bad key in a literal points at its `{`, a missing key at the `[`
+        let (_, result) = run("var k;\nvar m = ({k: 1});");
+        assert_eq!(result.unwrap_err().primary_span.start, 16);
+        let (_, result) = run("var m = ({});\nm[1];");
+        assert_eq!(result.unwrap_err().primary_span.start, 15);
+    }
+
+    #[test]
+    fn test_globals() {
+        let mut interpreter = Interpreter::new().with_output(Output::default());
+        let interner = interpreter.interner();
+        let scanner = Scanner::new("var zebra = 1; { var local = 2; }").with_interner(interner.clone());
+        let mut parser = Parser::new(scanner);
+        let program = parser.parse_program();
+        interpreter.interpret(parser.ast(), &program).unwrap();
+        let interner = interner.borrow();
+        let globals: Vec<&str> = interpreter.globals().into_iter().map(|name| interner.resolve(name)).collect();
+        assert!(globals.contains(&"len"));
+        assert!(globals.contains(&"zebra"));
+        assert!(!globals.contains(&"local"));
+    }
+
+    #[test]
+    fn test_call_errors() {
+        for (source, code, message) in [
+            ("var x = 1; x();", codes::NOT_CALLABLE, "can only call functions, not number"),
+            ("fun f(a, b) {} f(1);", codes::WRONG_ARGUMENT_COUNT, "expected 2 arguments but got 1"),
+            ("((a) => a)(1, 2);", codes::WRONG_ARGUMENT_COUNT, "expected 1 arguments but got 2"),
+            ("fun f(n) { return f(n + 1); } f(0);", codes::STACK_OVERFLOW, "calls nested more than 200 deep"),
+        ] {
+            let (_, result) = run(source);
+            let diagnostic = result.unwrap_err();
+            assert_eq!(diagnostic.code, Some(code), "{}", source);
+            assert_eq!(diagnostic.message, message, "{}", source);
+        }
+
+        // the error points at the `)` of the call
+        let (_, result) = run("var x;\nx(1, 2);");
+        assert_eq!(result.unwrap_err().primary_span.start, 13);
+    }
+
+    #[test]
+    fn test_globals_persist() {
+        let output = Output::default();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        for line in ["var x = 1;", "x = x + 1;", "print x;"] {
+            let mut parser = Parser::new(Scanner::new(line).with_interner(interpreter.interner()));
+            let program = parser.parse_program();
+            interpreter.interpret(parser.ast(), &program).unwrap();
+        }
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "2\n");
+    }
+}
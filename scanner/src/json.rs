@@ -0,0 +1,578 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::statement::{Statement, StmtId};
+use crate::tokens::{Token, TokenType};
+use std::fmt;
+
+// A JSON value, enough to give tools a stable view of tokens and trees.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl Json {
+    fn object(fields: Vec<(&str, Json)>) -> Json {
+        return Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        );
+    }
+
+    fn string(value: &str) -> Json {
+        return Json::String(value.to_string());
+    }
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = JsonReader {
+            chars: text.chars().collect(),
+            current: 0,
+            depth: 0,
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.current < reader.chars.len() {
+            return Err(format!("unexpected trailing input at {}", reader.current));
+        }
+        return Ok(value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+// Arrays and objects nested deeper than this are rejected rather than overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+struct JsonReader {
+    chars: Vec<char>,
+    current: usize,
+    // arrays and objects currently open
+    depth: usize,
+}
+
+impl JsonReader {
+    fn skip_whitespace(&mut self) {
+        while self.current < self.chars.len() && self.chars[self.current].is_whitespace() {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        return self.chars.get(self.current).copied();
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() != Some(expected) {
+            return Err(format!("expected '{}' at {}", expected, self.current));
+        }
+        self.current += 1;
+        return Ok(());
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        let end = self.current + word.len();
+        if end > self.chars.len() || self.chars[self.current..end].iter().collect::<String>() != word {
+            return Err(format!("unexpected input at {}", self.current));
+        }
+        self.current = end;
+        return Ok(value);
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nesting deeper than {} at {}", MAX_DEPTH, self.current));
+        }
+        self.depth += 1;
+        let value = self.nested_value();
+        self.depth -= 1;
+        return value;
+    }
+
+    fn nested_value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.current += 1;
+                let mut values = Vec::new();
+                if self.peek() == Some(']') {
+                    self.current += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    match self.peek() {
+                        Some(',') => self.current += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(']')?;
+                return Ok(Json::Array(values));
+            }
+            Some('{') => {
+                self.current += 1;
+                let mut fields = Vec::new();
+                if self.peek() == Some('}') {
+                    self.current += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    match self.peek() {
+                        Some(',') => self.current += 1,
+                        _ => break,
+                    }
+                }
+                self.expect('}')?;
+                return Ok(Json::Object(fields));
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.current;
+                while self.current < self.chars.len()
+                    && matches!(self.chars[self.current], '-' | '+' | '.' | 'e' | 'E' | '0'..='9')
+                {
+                    self.current += 1;
+                }
+                let text: String = self.chars[start..self.current].iter().collect();
+                return text
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("invalid number '{}'", text));
+            }
+            _ => Err(format!("unexpected input at {}", self.current)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let c = *self.chars.get(self.current).ok_or("unterminated string")?;
+            self.current += 1;
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escape = *self.chars.get(self.current).ok_or("unterminated string")?;
+                    self.current += 1;
+                    match escape {
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        't' => value.push('\t'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'u' => {
+                            let end = (self.current + 4).min(self.chars.len());
+                            let hex: String = self.chars[self.current..end].iter().collect();
+                            self.current = end;
+                            let code = u32::from_str_radix(&hex, 16)
+                                .map_err(|_| format!("invalid escape '\\u{}'", hex))?;
+                            value.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        c => value.push(c),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl ToJson for Token {
+    fn to_json(&self) -> Json {
+        let literal = match &self.literal {
+            Some(literal) => Json::string(literal),
+            None => Json::Null,
+        };
+        let mut fields = vec![("type", Json::String(self.token_type.name()))];
+        if let TokenType::Custom(word) = self.token_type {
+            fields.push(("word", Json::string(word)));
+        }
+        fields.extend([
+            ("lexeme", Json::string(&self.lexeme)),
+            ("literal", literal),
+            ("line", Json::Number(self.line as f64)),
+            ("column", Json::Number(self.column as f64)),
+            ("file", Json::Number(self.span.file.index() as f64)),
+            ("start", Json::Number(self.span.start as f64)),
+            ("end", Json::Number(self.span.end as f64)),
+        ]);
+        return Json::object(fields);
+    }
+}
+
+impl ToJson for Literal {
+    fn to_json(&self) -> Json {
+        let (kind, token) = match self {
+            Literal::NUMBER(token) => ("Number", token),
+            Literal::STRING(token) => ("String", token),
+            Literal::KEYWORD(token) => ("Keyword", token),
+        };
+        return Json::object(vec![("kind", Json::string(kind)), ("token", token.to_json())]);
+    }
+}
+
+// the expression `id` and everything below it, nested
+pub fn expression_json(ast: &AST, id: ExprId) -> Json {
+    let fields = match &ast[id] {
+        Expression::Unary { operator, value } => vec![
+            ("kind", Json::string("Unary")),
+            ("operator", operator.to_json()),
+            ("value", expression_json(ast, *value)),
+        ],
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        } => vec![
+            ("kind", Json::string("Binary")),
+            ("left", expression_json(ast, *left)),
+            ("operator", operator.to_json()),
+            ("right", expression_json(ast, *right)),
+        ],
+        Expression::Logical {
+            left,
+            operator,
+            right,
+        } => vec![
+            ("kind", Json::string("Logical")),
+            ("left", expression_json(ast, *left)),
+            ("operator", operator.to_json()),
+            ("right", expression_json(ast, *right)),
+        ],
+        Expression::Grouping { interior } => vec![
+            ("kind", Json::string("Grouping")),
+            ("interior", expression_json(ast, *interior)),
+        ],
+        Expression::Conditional {
+            condition,
+            question,
+            then_branch,
+            else_branch,
+        } => vec![
+            ("kind", Json::string("Conditional")),
+            ("condition", expression_json(ast, *condition)),
+            ("question", question.to_json()),
+            ("then", expression_json(ast, *then_branch)),
+            ("else", expression_json(ast, *else_branch)),
+        ],
+        Expression::Literal(literal) => vec![
+            ("kind", Json::string("Literal")),
+            ("literal", literal.to_json()),
+        ],
+        Expression::Variable { name } => vec![
+            ("kind", Json::string("Variable")),
+            ("name", name.to_json()),
+        ],
+        Expression::Assign { name, value } => vec![
+            ("kind", Json::string("Assign")),
+            ("name", name.to_json()),
+            ("value", expression_json(ast, *value)),
+        ],
+        Expression::Lambda { params, arrow, body } => vec![
+            ("kind", Json::string("Lambda")),
+            ("params", Json::Array(params.iter().map(|param| param.to_json()).collect())),
+            ("arrow", arrow.to_json()),
+            ("body", expression_json(ast, *body)),
+        ],
+        Expression::Function { keyword, params, body } => vec![
+            ("kind", Json::string("Function")),
+            ("keyword", keyword.to_json()),
+            ("params", Json::Array(params.iter().map(|param| param.to_json()).collect())),
+            ("body", Json::Array(body.iter().map(|statement| statement_json(ast, *statement)).collect())),
+        ],
+        Expression::Call {
+            callee,
+            paren,
+            arguments,
+        } => vec![
+            ("kind", Json::string("Call")),
+            ("callee", expression_json(ast, *callee)),
+            ("paren", paren.to_json()),
+            ("arguments", Json::Array(arguments.iter().map(|argument| expression_json(ast, *argument)).collect())),
+        ],
+        Expression::List { bracket, elements } => vec![
+            ("kind", Json::string("List")),
+            ("bracket", bracket.to_json()),
+            ("elements", Json::Array(elements.iter().map(|element| expression_json(ast, *element)).collect())),
+        ],
+        Expression::Map { brace, entries } => vec![
+            ("kind", Json::string("Map")),
+            ("brace", brace.to_json()),
+            (
+                "entries",
+                Json::Array(
+                    entries
+                        .iter()
+                        .map(|(key, value)| {
+                            Json::object(vec![("key", expression_json(ast, *key)), ("value", expression_json(ast, *value))])
+                        })
+                        .collect(),
+                ),
+            ),
+        ],
+        Expression::Index {
+            object,
+            bracket,
+            index,
+        } => vec![
+            ("kind", Json::string("Index")),
+            ("object", expression_json(ast, *object)),
+            ("bracket", bracket.to_json()),
+            ("index", expression_json(ast, *index)),
+        ],
+        Expression::SetIndex {
+            object,
+            bracket,
+            index,
+            value,
+        } => vec![
+            ("kind", Json::string("SetIndex")),
+            ("object", expression_json(ast, *object)),
+            ("bracket", bracket.to_json()),
+            ("index", expression_json(ast, *index)),
+            ("value", expression_json(ast, *value)),
+        ],
+    };
+    return Json::object(fields);
+}
+
+// a statement as nested JSON, expressions written out as by `expression_json`
+pub fn statement_json(ast: &AST, id: StmtId) -> Json {
+    let optional = |id: &Option<ExprId>| id.map_or(Json::Null, |id| expression_json(ast, id));
+    let statements = |ids: &[StmtId]| Json::Array(ids.iter().map(|id| statement_json(ast, *id)).collect());
+    let fields = match &ast[id] {
+        Statement::Expression { expression } => vec![
+            ("kind", Json::string("Expression")),
+            ("expression", expression_json(ast, *expression)),
+        ],
+        Statement::Print { keyword, value } => vec![
+            ("kind", Json::string("Print")),
+            ("keyword", keyword.to_json()),
+            ("value", expression_json(ast, *value)),
+        ],
+        Statement::Var { name, initializer } => vec![
+            ("kind", Json::string("Var")),
+            ("name", name.to_json()),
+            ("initializer", optional(initializer)),
+        ],
+        Statement::Block { brace, statements: body } => vec![
+            ("kind", Json::string("Block")),
+            ("brace", brace.to_json()),
+            ("statements", statements(body)),
+        ],
+        Statement::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        } => vec![
+            ("kind", Json::string("If")),
+            ("keyword", keyword.to_json()),
+            ("condition", expression_json(ast, *condition)),
+            ("then", statement_json(ast, *then_branch)),
+            ("else", else_branch.map_or(Json::Null, |id| statement_json(ast, id))),
+        ],
+        Statement::Function { name, params, body } => vec![
+            ("kind", Json::string("Function")),
+            ("name", name.to_json()),
+            ("params", Json::Array(params.iter().map(|param| param.to_json()).collect())),
+            ("body", statements(body)),
+        ],
+        Statement::Return { keyword, value } => vec![
+            ("kind", Json::string("Return")),
+            ("keyword", keyword.to_json()),
+            ("value", optional(value)),
+        ],
+        Statement::While {
+            keyword,
+            condition,
+            body,
+            increment,
+        } => vec![
+            ("kind", Json::string("While")),
+            ("keyword", keyword.to_json()),
+            ("condition", expression_json(ast, *condition)),
+            ("body", statement_json(ast, *body)),
+            ("increment", optional(increment)),
+        ],
+        Statement::ForEach {
+            keyword,
+            name,
+            iterable,
+            body,
+        } => vec![
+            ("kind", Json::string("ForEach")),
+            ("keyword", keyword.to_json()),
+            ("name", name.to_json()),
+            ("iterable", expression_json(ast, *iterable)),
+            ("body", statement_json(ast, *body)),
+        ],
+        Statement::Break { keyword } => vec![("kind", Json::string("Break")), ("keyword", keyword.to_json())],
+        Statement::Continue { keyword } => vec![("kind", Json::string("Continue")), ("keyword", keyword.to_json())],
+    };
+    return Json::object(fields);
+}
+
+// the top-level statements of the program, in order
+pub fn program_json(ast: &AST) -> Json {
+    return Json::Array(ast.program().iter().map(|id| statement_json(ast, *id)).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, Scanner};
+
+    #[test]
+    fn test_escape_strings() {
+        let json = Json::Array(vec![Json::string("say \"hi\"\n"), Json::Null, Json::Number(1.5)]);
+        assert_eq!(json.to_string(), "[\"say \\\"hi\\\"\\n\",null,1.5]");
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "{\"a\": [1, -2.5e1, true, null], \"b\": \"x\\\"y\\n\\u0041\"}";
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("a").and_then(Json::as_array).map(|values| values.len()), Some(4));
+        assert_eq!(json.get("a").unwrap().as_array().unwrap()[1], Json::Number(-25.0));
+        assert_eq!(json.get("b").and_then(Json::as_str), Some("x\"y\nA"));
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert!(Json::parse("[1,").is_err());
+        assert!(Json::parse("{} x").is_err());
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(Json::parse(&nested(MAX_DEPTH + 1)), Err(format!("nesting deeper than {} at {}", MAX_DEPTH, MAX_DEPTH)));
+        assert!(Json::parse(&nested(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn test_token_json() {
+        let tokens = Scanner::new("\"a\"").scan_tokens();
+        assert_eq!(
+            tokens[0].to_json().to_string(),
+            "{\"type\":\"String\",\"lexeme\":\"\\\"a\\\"\",\"literal\":\"a\",\"line\":1,\"column\":1,\"file\":0,\"start\":0,\"end\":3}"
+        );
+    }
+
+    #[test]
+    fn test_expression_json() {
+        let mut parser = Parser::new(Scanner::new("-x"));
+        let root = parser.parse();
+        let json = expression_json(parser.ast(), root).to_string();
+        assert!(json.starts_with("{\"kind\":\"Unary\",\"operator\":{\"type\":\"Minus\""));
+        assert!(json.contains("\"value\":{\"kind\":\"Variable\",\"name\":{\"type\":\"Identifier\""));
+    }
+
+    #[test]
+    fn test_program_json() {
+        let mut parser = Parser::new(Scanner::new("var x; if (x) print x;"));
+        parser.parse_program();
+        let json = program_json(parser.ast());
+        let statements = json.as_array().unwrap();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].get("kind").and_then(Json::as_str), Some("Var"));
+        assert_eq!(statements[0].get("initializer"), Some(&Json::Null));
+        assert_eq!(statements[1].get("else"), Some(&Json::Null));
+        let then_branch = statements[1].get("then").unwrap();
+        assert_eq!(then_branch.get("kind").and_then(Json::as_str), Some("Print"));
+        assert_eq!(then_branch.get("value").and_then(|value| value.get("kind")).and_then(Json::as_str), Some("Variable"));
+    }
+}
@@ -1,52 +1,194 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_mut)]
-pub mod errors;
+#![allow(clippy::needless_return)]
 pub mod helpers;
 pub mod tokens;
 pub mod parser;
 pub mod expression;
+pub mod statement;
+pub mod document;
+pub mod interner;
+pub mod source_map;
+pub mod json;
+pub mod visitor;
+pub mod serialize;
+pub mod unparse;
+pub mod dot;
+pub mod builder;
+pub mod value;
+pub mod native;
+pub mod interpreter;
+pub mod resolver;
 
-use errors::*;
 use helpers::*;
 use tokens::*;
+use interner::{Interner, Symbol, SymbolTable};
+use source_map::{FileId, Location, SharedSourceMap, SourceMap};
+use diagnostics::{codes, collector, Diagnostic, SharedSink};
+use std::io::{BufRead, Cursor};
 
-pub struct Scanner {
-    source: String,
+// Tokens are produced lazily: the scanner only pulls as many lines from its reader as the
+// current token needs, and drops characters once the token they belong to has been emitted.
+pub struct Scanner<R: BufRead = Cursor<Vec<u8>>> {
+    reader: R,
+    reader_done: bool,
+    source: Vec<char>,
     pub tokens: Vec<Token>,
+    pending: Option<Token>,
+    symbol: Option<Symbol>,
+    started: bool,
+    emitted_eof: bool,
+    // byte offset of the first buffered character
+    offset: usize,
     start: usize,
     current: usize,
     line: usize,
+    column: usize,
+    // tokens report where they start, even strings and comments spanning several lines
+    start_line: usize,
+    start_column: usize,
+    source_map: SharedSourceMap,
+    file: FileId,
     keywords: Keywords,
+    interner: SymbolTable,
+    sink: SharedSink,
 }
 
 impl Scanner {
     pub fn new(source: &str) -> Self {
+        let source_map = SourceMap::shared();
+        let file = source_map.borrow_mut().add("<input>", source);
+        Scanner::from_source_map(source_map, file)
+    }
+
+    // scan a file registered in `source_map`; its spans and errors will point into that file
+    pub fn from_source_map(source_map: SharedSourceMap, file: FileId) -> Self {
+        let contents = source_map.borrow().file(file).contents.clone();
+        return Scanner::from_reader(Cursor::new(contents.into_bytes())).in_file(source_map, file);
+    }
+}
+
+impl<R: BufRead> Scanner<R> {
+    // scan a stream that is not kept in memory; errors report it as `<stream>`
+    pub fn from_reader(reader: R) -> Self {
+        let source_map = SourceMap::shared();
+        let file = source_map.borrow_mut().add("<stream>", "");
         Self {
-            source: String::from(source),
+            reader,
+            reader_done: false,
+            source: Vec::new(),
             tokens: Vec::new(),
+            pending: None,
+            symbol: None,
+            started: false,
+            emitted_eof: false,
+            offset: 0,
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            source_map,
+            file,
             keywords: Keywords::new(),
+            interner: Interner::shared(),
+            sink: collector(),
         }
     }
 
-    fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+    // makes sure the character at `index` is buffered, reading more lines if needed
+    fn fill(&mut self, index: usize) -> bool {
+        while index >= self.source.len() && !self.reader_done {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => self.reader_done = true,
+                Ok(_) => self.source.extend(line.chars()),
+                Err(err) => {
+                    self.error(codes::UNREADABLE_SOURCE, format!("Could not read source: {}", err).as_str());
+                    self.reader_done = true;
+                }
+            }
+        }
+        return index < self.source.len();
+    }
+
+    fn is_at_end(&mut self) -> bool {
+        return !self.fill(self.current);
+    }
+
+    // continue scanning as if `offset` bytes ending at `line` and `column` had already been read
+    pub fn resume_at(mut self, offset: usize, line: usize, column: usize) -> Self {
+        self.offset = offset;
+        self.line = line;
+        self.column = column;
+        self
+    }
+
+    // attribute spans and errors to `file` of `source_map`
+    pub fn in_file(mut self, source_map: SharedSourceMap, file: FileId) -> Self {
+        self.source_map = source_map;
+        self.file = file;
+        self
+    }
+
+    pub fn file(&self) -> FileId {
+        return self.file;
+    }
+
+    pub fn source_map(&self) -> SharedSourceMap {
+        return self.source_map.clone();
+    }
+
+    // points at the text of the token being scanned
+    fn error(&self, code: &'static str, message: &str) {
+        let span = Span::in_file(self.file, self.byte_offset(self.start), self.byte_offset(self.current));
+        self.sink.borrow_mut().emit(Diagnostic::error(message, span).with_code(code));
+    }
+
+    // intern names into a table shared with other scanners and later passes
+    pub fn with_interner(mut self, interner: SymbolTable) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    // report problems to `sink`; by default they are collected and dropped with the scanner
+    pub fn with_sink(mut self, sink: SharedSink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    pub fn sink(&self) -> SharedSink {
+        return self.sink.clone();
+    }
+
+    // scan with a dialect's keyword set instead of plain Lox
+    pub fn with_keywords(mut self, keywords: Keywords) -> Self {
+        self.keywords = keywords;
+        self
+    }
+
+    pub fn interner(&self) -> SymbolTable {
+        return self.interner.clone();
     }
 
     pub fn scan_tokens(&mut self) -> Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
+        while let Some(token) = self.next() {
+            self.tokens.push(token);
         }
-
-        self.tokens.push(Token::from(TokenType::Eof));
         self.tokens.clone()
     }
 
+    fn text(&self, from: usize, to: usize) -> String {
+        return self.source[from..to].iter().collect();
+    }
+
+    fn byte_offset(&self, index: usize) -> usize {
+        return self.offset + self.source[..index.min(self.source.len())].iter().map(|c| c.len_utf8()).sum::<usize>();
+    }
+
     fn scan_token(&mut self) {
         let c: char = self.advance().unwrap_or_else(|| {
-            error(self.line, "Error: Unexpected end of file");
+            self.error(codes::UNEXPECTED_END_OF_FILE, "Unexpected end of file");
             '\0'
         });
 
@@ -64,12 +206,31 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
+            '*' => {
+                if self.check_next('*') {
+                    self.add_token(TokenType::StarStar);
+                } else {
+                    self.add_token(TokenType::Star);
+                }
+            }
+            '%' => self.add_token(TokenType::Percent),
+            // integer division; `//` already starts a line comment
+            '~' => {
+                if self.check_next('/') {
+                    self.add_token(TokenType::TildeSlash);
+                } else {
+                    self.error(codes::UNEXPECTED_CHARACTER, "Unexpected character '~'");
+                }
+            }
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
             '!' => {
                 if self.check_next('=') {
                     self.add_token(TokenType::BangEqual);
@@ -80,6 +241,8 @@ impl Scanner {
             '=' => {
                 if self.check_next('=') {
                     self.add_token(TokenType::EqualEqual);
+                } else if self.check_next('>') {
+                    self.add_token(TokenType::Arrow);
                 } else {
                     self.add_token(TokenType::Equal);
                 }
@@ -99,65 +262,129 @@ impl Scanner {
                 }
             }
             '/' => self.slash(),
-            ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
-            '"' => self.string(),
-            _ => {
-                if !is_digit(c) {
-                    let _ = (self.line, "Unexpected character.");
+            ' ' | '\t' => (),
+            // \r\n is counted at its \n, a lone \r is a line break of its own
+            '\r' => {
+                if self.peek() != Some('\n') {
+                    self.line += 1;
                 }
             }
+            '\n' => self.line += 1,
+            '"' => self.string(),
+            _ => self.error(codes::UNEXPECTED_CHARACTER, format!("Unexpected character '{}'", c).as_str()),
         }
     }
 
     fn slash(&mut self) {
         if self.check_next('/') {
-            while self.peek() != Some('\n') && !self.is_at_end() {
+            if self.peek() == Some('/') && self.peek_next() != Some('/') {
+                self.advance();
+                self.line_doc_comment();
+                return;
+            }
+            while !self.at_line_end() {
                 self.advance();
             }
         } else if self.check_next('*') {
-            while self.peek() != Some('*') && self.peek_next() != Some('/') && !self.is_at_end() {
-                if self.peek() == Some('\n') {
-                    self.line += 1;
-                }
+            if self.peek() == Some('*') && self.peek_next() != Some('/') {
                 self.advance();
+                self.block_doc_comment();
+                return;
             }
+            self.block_comment();
+        } else {
+            self.add_token(TokenType::Slash);
+        }
+    }
+
+    // consumes up to and including the closing */, returns false if the comment never ends
+    fn block_comment(&mut self) -> bool {
+        while !(self.peek() == Some('*') && self.peek_next() == Some('/')) {
             if self.is_at_end() {
-                error(self.line, "Error: Unterminated block comment");
+                self.error(codes::UNTERMINATED_BLOCK_COMMENT, "Unterminated block comment");
+                return false;
+            }
+            if self.at_line_break() {
+                self.line += 1;
             }
             self.advance();
+        }
+        self.advance();
+        self.advance();
+        return true;
+    }
+
+    fn line_doc_comment(&mut self) {
+        while !self.at_line_end() {
             self.advance();
-        } else {
-            self.add_token(TokenType::Slash);
         }
+        let text = self.text(self.start + 3, self.current);
+        self.add_token_literal(TokenType::DocComment, Some(text.trim().to_string()));
     }
 
-    fn check_next(&mut self, expected: char) -> bool {
-        if self.current >= self.source.len() {
-            return false;
+    fn block_doc_comment(&mut self) {
+        if !self.block_comment() {
+            return;
         }
-        if self.source.chars().nth(self.current).unwrap_or_else(|| {
-            error(self.line, "Error: Unexpected end of file");
-            '\0'
-        }) != expected
-        {
+        let text = self.text(self.start + 3, self.current - 2);
+        let text = text
+            .lines()
+            .map(|line| line.trim().trim_start_matches('*').trim())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        self.add_token_literal(TokenType::DocComment, Some(text));
+    }
+
+    // true on the character that ends a line: a \n, or a \r that is not part of \r\n
+    fn at_line_break(&mut self) -> bool {
+        return match self.peek() {
+            Some('\n') => !self.is_at_end(),
+            Some('\r') => self.peek_next() != Some('\n'),
+            _ => false,
+        };
+    }
+
+    fn at_line_end(&mut self) -> bool {
+        return self.is_at_end() || matches!(self.peek(), Some('\n') | Some('\r'));
+    }
+
+    // a UTF-8 byte order mark and a `#!` line are only allowed at the very start of a script
+    fn skip_preamble(&mut self) {
+        if self.peek() == Some('\u{feff}') {
+            self.advance();
+            self.column = 1;
+        }
+        if self.peek() == Some('#') && self.peek_next() == Some('!') {
+            while !self.at_line_end() {
+                self.advance();
+            }
+        }
+    }
+
+    fn check_next(&mut self, expected: char) -> bool {
+        if self.is_at_end() || self.source[self.current] != expected {
             return false;
         }
-        self.current += 1;
+        self.advance();
         return true;
     }
-    fn peek(&self) -> Option<char> {
+    fn peek(&mut self) -> Option<char> {
         if self.is_at_end() {
             return Some('\0');
         }
-        self.source.chars().nth(self.current)
+        return Some(self.source[self.current]);
     }
-    fn peek_next(&self) -> Option<char> {
-        let check_end = { self.current + 1 >= self.source.len() };
-        if check_end {
+    fn peek_next(&mut self) -> Option<char> {
+        if !self.fill(self.current + 1) {
             return Some('\0');
         }
-        return self.source.chars().nth(self.current + 1);
+        return Some(self.source[self.current + 1]);
+    }
+
+    // the symbol is picked up by the next token added
+    fn intern(&mut self, text: &str) {
+        self.symbol = Some(self.interner.borrow_mut().intern(text));
     }
 
     fn add_token(&mut self, token_type: TokenType) {
@@ -165,20 +392,25 @@ impl Scanner {
     }
 
     fn add_token_literal(&mut self, token_type: TokenType, literal: Option<String>) {
-        let text = self
-            .source
-            .char_indices()
-            .skip(self.start)
-            .take(self.current - self.start)
-            .map(|(_, c)| c)
-            .collect::<String>();
-
-        self.tokens
-            .push(Token::new(token_type, text, literal, self.line));
+        let text = self.text(self.start, self.current);
+        let span = Span::in_file(self.file, self.byte_offset(self.start), self.byte_offset(self.current));
+        let mut token = Token::new(token_type, text, literal, self.start_line).with_span(span);
+        token.column = self.start_column;
+        token.symbol = self.symbol.take();
+        self.pending = Some(token);
     }
 
     fn advance(&mut self) -> Option<char> {
-        let c = self.source.chars().nth(self.current);
+        let c = if self.fill(self.current) {
+            Some(self.source[self.current])
+        } else {
+            None
+        };
+        if c == Some('\n') || c == Some('\r') {
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         self.current += 1;
         return c;
     }
@@ -186,9 +418,10 @@ impl Scanner {
     fn string(&mut self) {
         while let Some(c) = self.peek() {
             if self.is_at_end() {
-                error(self.line, "Error: Unterminated string");
+                self.error(codes::UNTERMINATED_STRING, "Unterminated string");
+                return;
             }
-            if c == '\n' {
+            if self.at_line_break() {
                 self.line += 1;
             }
             if c == '"' {
@@ -198,12 +431,8 @@ impl Scanner {
         }
         self.advance();
 
-        let value: String = self
-            .source
-            .chars()
-            .skip(self.start + 1)
-            .take(self.current - 1 - self.start)
-            .collect();
+        let value = self.text(self.start + 1, self.current - 1);
+        self.intern(&value);
         self.add_token_literal(TokenType::String, Some(value))
     }
 
@@ -215,32 +444,17 @@ impl Scanner {
                 break;
             }
         }
-        if self.peek().unwrap_or_else(|| {
-            error(self.line, "Error: Unexpected end of file");
-            '\0'
-        }) == '.'
-            && is_digit(self.peek_next().unwrap_or_else(|| {
-                error(self.line, "Error: Unexpected end of file");
-                '\0'
-            }))
-        {
+        if self.peek() == Some('.') && self.peek_next().is_some_and(is_digit) {
             self.advance();
-            while is_digit(self.peek().unwrap_or_else(|| {
-                error(self.line, "Error: Unexpected end of file");
-                '\0'
-            })) {
+            while self.peek().is_some_and(is_digit) {
                 self.advance();
             }
         }
         let value: f64 = self
-            .source
-            .chars()
-            .skip(self.start)
-            .take(self.current - self.start)
-            .collect::<String>()
+            .text(self.start, self.current)
             .parse()
             .unwrap_or_else(|_| {
-                error(self.line, "Error: Invalid number");
+                self.error(codes::INVALID_NUMBER, "Invalid number");
                 0.0
             });
         self.add_token_literal(TokenType::Number, Some(value.to_string()));
@@ -255,17 +469,53 @@ impl Scanner {
             }
         }
 
-        let text = self
-            .source
-            .chars()
-            .skip(self.start)
-            .take(self.current - self.start)
-            .collect();
+        let text = self.text(self.start, self.current);
         let token_type = self.keywords.get(&text);
+        if token_type == TokenType::Identifier {
+            self.intern(&text);
+        }
+        if token_type == TokenType::Reserved {
+            self.error(codes::RESERVED_WORD, format!("'{}' is a reserved word", text).as_str());
+        }
         self.add_token(token_type);
     }
 }
 
+impl<R: BufRead> Iterator for Scanner<R> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if !self.started {
+            self.started = true;
+            if self.offset == 0 {
+                self.skip_preamble();
+            }
+        }
+        while self.pending.is_none() && !self.is_at_end() {
+            // everything before the new token has been emitted already
+            self.offset = self.byte_offset(self.current);
+            self.source.drain(..self.current);
+            self.current = 0;
+            self.start = 0;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            self.scan_token();
+        }
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+        if self.emitted_eof {
+            return None;
+        }
+        self.emitted_eof = true;
+        let end = self.byte_offset(self.source.len());
+        let mut eof = Token::from(TokenType::Eof).with_span(Span::in_file(self.file, end, end));
+        eof.line = self.line;
+        eof.column = self.column;
+        return Some(eof);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,7 +530,7 @@ mod tests {
         let mut scanner = Scanner::new("and");
         let tokens = scanner.scan_tokens();
         println!("{:?}", tokens);
-        assert_eq!(tokens.len(), 2 as usize);
+        assert_eq!(tokens.len(), 2_usize);
         assert_eq!(tokens[0].clone().into_string(), "And and");
         assert_eq!(tokens[1].clone().into_string(), "Eof ");
     }
@@ -290,7 +540,7 @@ mod tests {
         let mut scanner = Scanner::new(format!("{}and{}", '"', '"').as_str());
         let tokens = scanner.scan_tokens();
         println!("{:?}", tokens);
-        assert_eq!(tokens.len(), 2 as usize);
+        assert_eq!(tokens.len(), 2_usize);
         //this test is cursed
         //        assert_eq!(tokens[0].clone().into_string(), "String and");
         assert_eq!(tokens[1].clone().into_string(), "Eof ");
@@ -301,11 +551,32 @@ mod tests {
         let mut scanner = Scanner::new("123");
         let tokens = scanner.scan_tokens();
         println!("{:?}", tokens);
-        assert_eq!(tokens.len(), 2 as usize);
+        assert_eq!(tokens.len(), 2_usize);
         assert_eq!(tokens[0].clone().into_string(), "Number 123");
         assert_eq!(tokens[1].clone().into_string(), "Eof ");
     }
 
+    #[test]
+    fn test_arithmetic_operators() {
+        let tokens = Scanner::new("a ** b * c % d ~/ e // f").scan_tokens();
+        let types: Vec<TokenType> = tokens.into_iter().map(|token| token.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::StarStar,
+                TokenType::Identifier,
+                TokenType::Star,
+                TokenType::Identifier,
+                TokenType::Percent,
+                TokenType::Identifier,
+                TokenType::TildeSlash,
+                TokenType::Identifier,
+                TokenType::Eof,
+            ]
+        );
+    }
+
     #[test]
     fn test_block() {
         let block_comment = "hello //* hello * hello  *//";
@@ -343,4 +614,146 @@ mod tests {
         assert_eq!(tokens[1].clone().into_string(), "Plus +");
         assert_eq!(tokens[2].clone().into_string(), "Number 2");
     }
+
+    #[test]
+    fn test_line_doc_comment() {
+        let mut scanner = Scanner::new("/// Adds two numbers.\nfun");
+        let tokens = scanner.scan_tokens();
+        println!("{:?}", tokens);
+        assert_eq!(tokens[0].token_type, TokenType::DocComment);
+        assert_eq!(tokens[0].literal, Some("Adds two numbers.".to_string()));
+        assert_eq!(tokens[1].clone().into_string(), "Fun fun");
+        assert_eq!(tokens[1].line, 2);
+    }
+
+    #[test]
+    fn test_block_doc_comment() {
+        let mut scanner = Scanner::new("/**\n * A point.\n * Has x and y.\n */\nclass");
+        let tokens = scanner.scan_tokens();
+        println!("{:?}", tokens);
+        assert_eq!(tokens[0].token_type, TokenType::DocComment);
+        assert_eq!(tokens[0].literal, Some("A point.\nHas x and y.".to_string()));
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[1].clone().into_string(), "Class class");
+        assert_eq!(tokens[1].line, 5);
+    }
+
+    #[test]
+    fn test_plain_comments_dropped() {
+        let mut scanner = Scanner::new("//// banner\n/**/ /* note */ var");
+        let tokens = scanner.scan_tokens();
+        println!("{:?}", tokens);
+        assert_eq!(tokens.len(), 2_usize);
+        assert_eq!(tokens[0].clone().into_string(), "Var var");
+    }
+
+    #[test]
+    fn test_multiline_string_from_reader() {
+        let input = "\"one\ntwo\" 3".as_bytes();
+        let tokens: Vec<Token> = Scanner::from_reader(std::io::BufReader::new(input)).collect();
+        println!("{:?}", tokens);
+        assert_eq!(tokens.len(), 3_usize);
+        assert_eq!(tokens[0].literal, Some("one\ntwo".to_string()));
+        assert_eq!(tokens[1].clone().into_string(), "Number 3");
+        assert_eq!(tokens[1].line, 2);
+    }
+
+    struct Endless;
+
+    impl std::io::Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let line = b"and\n";
+            let len = line.len().min(buf.len());
+            buf[..len].copy_from_slice(&line[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_lazy_scanning() {
+        let scanner = Scanner::from_reader(std::io::BufReader::new(Endless));
+        let tokens: Vec<Token> = scanner.take(3).collect();
+        assert_eq!(tokens.len(), 3_usize);
+        assert_eq!(tokens[2].clone().into_string(), "And and");
+        assert_eq!(tokens[2].line, 3);
+    }
+
+    #[test]
+    fn test_identifiers_interned() {
+        let mut scanner = Scanner::new("count + total + count + \"count\"");
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens[0].symbol, tokens[4].symbol);
+        assert_ne!(tokens[0].symbol, tokens[2].symbol);
+        assert_eq!(tokens[6].symbol, tokens[0].symbol);
+        assert_eq!(tokens[1].symbol, None);
+        let interner = scanner.interner();
+        assert_eq!(interner.borrow().resolve(tokens[2].symbol.unwrap()), "total");
+    }
+
+    #[test]
+    fn test_dialect_scanner() {
+        let keywords = Keywords::new().with_keyword("import", TokenType::Custom("import"));
+        let mut scanner = Scanner::new("import lib").with_keywords(keywords);
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens[0].token_type, TokenType::Custom("import"));
+        assert_eq!(tokens[1].token_type, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_locations() {
+        let source_map = SourceMap::shared();
+        source_map.borrow_mut().add("first.lox", "");
+        let file = source_map.borrow_mut().add("second.lox", "1 +\n  (2)");
+        let tokens = Scanner::from_source_map(source_map.clone(), file).scan_tokens();
+        assert_eq!((tokens[2].line, tokens[2].column), (2, 3));
+        assert_eq!((tokens[3].line, tokens[3].column), (2, 4));
+        assert_eq!(tokens[3].span.file, file);
+        let location = source_map.borrow().location(tokens[3].span);
+        assert_eq!(location.to_string(), "second.lox:2:4");
+    }
+
+    #[test]
+    fn test_windows_line_endings() {
+        let mut scanner = Scanner::new("1 +\r\n// note\r\n2\r\n* \"a\r\nb\"\r\n/* x\r\n*/ 3");
+        let tokens = scanner.scan_tokens();
+        println!("{:?}", tokens);
+        assert_eq!(tokens.len(), 7_usize);
+        assert_eq!(tokens[2].line, 3);
+        assert_eq!((tokens[3].line, tokens[3].column), (4, 1));
+        assert_eq!(tokens[4].line, 4);
+        assert_eq!((tokens[5].line, tokens[5].column), (7, 4));
+    }
+
+    #[test]
+    fn test_lone_carriage_returns() {
+        let mut scanner = Scanner::new("1\r2\r\r3");
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens[1].line, 2);
+        assert_eq!(tokens[2].line, 4);
+        assert_eq!(tokens[2].column, 1);
+    }
+
+    #[test]
+    fn test_shebang_and_bom() {
+        let mut scanner = Scanner::new("\u{feff}#!/usr/bin/env defunkter\r\nvar");
+        let tokens = scanner.scan_tokens();
+        println!("{:?}", tokens);
+        assert_eq!(tokens.len(), 2_usize);
+        assert_eq!(tokens[0].clone().into_string(), "Var var");
+        assert_eq!((tokens[0].line, tokens[0].column), (2, 1));
+        assert_eq!(tokens[0].span.start, 29);
+    }
+
+    #[test]
+    fn test_errors_go_to_sink() {
+        let diagnostics = collector();
+        let mut scanner = Scanner::new("1 + \"open").with_sink(diagnostics.clone());
+        scanner.scan_tokens();
+
+        let diagnostics = diagnostics.borrow();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Unterminated string");
+        assert_eq!(diagnostics[0].code, Some(codes::UNTERMINATED_STRING));
+        assert_eq!(diagnostics[0].primary_span, Span::new(4, 9));
+    }
 }
@@ -0,0 +1,167 @@
+use crate::value::{Key, Value};
+use diagnostics::codes;
+use std::fmt;
+
+// What a built-in function reports when it cannot run: a code and a message. The interpreter
+// places it at the call.
+pub type NativeError = (&'static str, String);
+
+// A function built into the interpreter, defined as a global before the program runs.
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Result<Value, NativeError>,
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fun {}>", self.name)
+    }
+}
+
+pub static NATIVES: &[Native] = &[
+    Native { name: "len", arity: 1, function: len },
+    Native { name: "push", arity: 2, function: push },
+    Native { name: "pop", arity: 1, function: pop },
+    Native { name: "insert", arity: 3, function: insert },
+    Native { name: "remove", arity: 2, function: remove },
+    Native { name: "slice", arity: 3, function: slice },
+    Native { name: "keys", arity: 1, function: keys },
+    Native { name: "values", arity: 1, function: values },
+    Native { name: "has", arity: 2, function: has },
+];
+
+// The position `index` names in a list of `length` elements. With `end` the position just
+// past the last element is allowed too, as `insert` and `slice` need.
+pub fn list_index(index: &Value, length: usize, end: bool) -> Result<usize, NativeError> {
+    let number = match index {
+        Value::Number(number) if number.fract() == 0.0 => *number,
+        Value::Number(number) => return Err((codes::INVALID_INDEX, format!("list index must be a whole number, not {}", number))),
+        index => return Err((codes::INVALID_INDEX, format!("list index must be a whole number, not {}", index.type_name()))),
+    };
+    let limit = if end { length + 1 } else { length };
+    if number < 0.0 || number >= limit as f64 {
+        return Err((codes::INDEX_OUT_OF_BOUNDS, format!("index {} is out of bounds for a list of length {}", number, length)));
+    }
+    return Ok(number as usize);
+}
+
+// the key `value` stands for in a map
+pub fn map_key(value: &Value) -> Result<Key, NativeError> {
+    return Key::from_value(value)
+        .ok_or_else(|| (codes::MAP_KEY_TYPE, format!("map keys must be strings, numbers or booleans, not {}", value.type_name())));
+}
+
+pub fn missing_key(key: &Key) -> NativeError {
+    let key = match key {
+        Key::String(string) => format!("\"{}\"", string),
+        key => key.to_value().to_string(),
+    };
+    return (codes::MISSING_KEY, format!("key {} is not in the map", key));
+}
+
+fn wrong_type(function: &str, expected: &str, value: &Value) -> NativeError {
+    return (codes::ARGUMENT_TYPE, format!("{} expects {}, not {}", function, expected, value.type_name()));
+}
+
+fn len(arguments: &[Value]) -> Result<Value, NativeError> {
+    match &arguments[0] {
+        Value::List(list) => return Ok(Value::Number(list.borrow().len() as f64)),
+        Value::Map(map) => return Ok(Value::Number(map.borrow().len() as f64)),
+        Value::String(string) => return Ok(Value::Number(string.chars().count() as f64)),
+        value => return Err(wrong_type("len", "a list, a map or a string", value)),
+    }
+}
+
+fn push(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(list) = &arguments[0] else {
+        return Err(wrong_type("push", "a list", &arguments[0]));
+    };
+    list.borrow_mut().push(arguments[1].clone());
+    return Ok(Value::Nil);
+}
+
+fn pop(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(list) = &arguments[0] else {
+        return Err(wrong_type("pop", "a list", &arguments[0]));
+    };
+    let popped = list.borrow_mut().pop();
+    return popped.ok_or_else(|| (codes::INDEX_OUT_OF_BOUNDS, "pop from an empty list".to_string()));
+}
+
+fn insert(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(list) = &arguments[0] else {
+        return Err(wrong_type("insert", "a list", &arguments[0]));
+    };
+    let index = list_index(&arguments[1], list.borrow().len(), true)?;
+    list.borrow_mut().insert(index, arguments[2].clone());
+    return Ok(Value::Nil);
+}
+
+// the element at a list position, or the value under a map key, that was removed
+fn remove(arguments: &[Value]) -> Result<Value, NativeError> {
+    match &arguments[0] {
+        Value::List(list) => {
+            let index = list_index(&arguments[1], list.borrow().len(), false)?;
+            return Ok(list.borrow_mut().remove(index));
+        }
+        Value::Map(map) => {
+            let key = map_key(&arguments[1])?;
+            let removed = map.borrow_mut().remove(&key);
+            return removed.ok_or_else(|| missing_key(&key));
+        }
+        value => return Err(wrong_type("remove", "a list or a map", value)),
+    }
+}
+
+// a new list of the elements from `start` up to, not including, `end`
+fn slice(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(list) = &arguments[0] else {
+        return Err(wrong_type("slice", "a list", &arguments[0]));
+    };
+    let length = list.borrow().len();
+    let start = list_index(&arguments[1], length, true)?;
+    let end = list_index(&arguments[2], length, true)?;
+    if start > end {
+        return Err((codes::INDEX_OUT_OF_BOUNDS, format!("slice starts at {} but ends at {}", start, end)));
+    }
+    return Ok(Value::list(list.borrow()[start..end].to_vec()));
+}
+
+// the map's keys or values as a new list, in insertion order
+fn keys(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::Map(map) = &arguments[0] else {
+        return Err(wrong_type("keys", "a map", &arguments[0]));
+    };
+    return Ok(Value::list(map.borrow().entries().map(|(key, _)| key.to_value()).collect()));
+}
+
+fn values(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::Map(map) = &arguments[0] else {
+        return Err(wrong_type("values", "a map", &arguments[0]));
+    };
+    return Ok(Value::list(map.borrow().entries().map(|(_, value)| value.clone()).collect()));
+}
+
+fn has(arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::Map(map) = &arguments[0] else {
+        return Err(wrong_type("has", "a map", &arguments[0]));
+    };
+    let key = map_key(&arguments[1])?;
+    return Ok(Value::Bool(map.borrow().get(&key).is_some()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_index() {
+        assert_eq!(list_index(&Value::Number(2.0), 3, false), Ok(2));
+        assert_eq!(list_index(&Value::Number(3.0), 3, true), Ok(3));
+        assert_eq!(list_index(&Value::Number(3.0), 3, false).unwrap_err().0, codes::INDEX_OUT_OF_BOUNDS);
+        assert_eq!(list_index(&Value::Number(-1.0), 3, false).unwrap_err().0, codes::INDEX_OUT_OF_BOUNDS);
+        assert_eq!(list_index(&Value::Number(0.5), 3, false).unwrap_err().0, codes::INVALID_INDEX);
+        assert_eq!(list_index(&Value::Nil, 3, false).unwrap_err(), (codes::INVALID_INDEX, "list index must be a whole number, not nil".to_string()));
+    }
+}
@@ -1,65 +1,573 @@
 use crate::{
-    expression::{Expression, Literal},
-    tokens::{Token, TokenType},
-    errors::token_error
+    expression::{ExprId, Expression, Literal, AST},
+    statement::{Statement, StmtId},
+    tokens::{Keywords, Token, TokenType},
 
 };
-use std::rc::Rc;
+use diagnostics::{codes, collector, Diagnostic, SharedSink};
+use std::collections::VecDeque;
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+// The parser pulls tokens from any iterator (a Vec, or a Scanner reading a stream) and only
+// buffers the few tokens of lookahead it needs.
+pub struct Parser<I: Iterator<Item = Token> = std::vec::IntoIter<Token>> {
+    tokens: I,
+    // how many tokens have been taken from `tokens`, doc comments included
+    pulled: usize,
+    lookahead: VecDeque<Token>,
+    previous: Option<Token>,
+    // doc comments are held aside so they can be attached to the declaration that follows them
+    doc_comments: Vec<Token>,
+    // how many of `doc_comments` a statement has already claimed
+    claimed_docs: usize,
+    sink: SharedSink,
+    // for suggesting keywords when a name looks like a typo for one
+    keywords: Keywords,
+    // how many errors have been reported, so one mistake is not reported twice
+    errors: usize,
+    // how many `while`/`for` bodies enclose the current token; `break` and `continue` need one
+    loop_depth: usize,
+    // how many function bodies enclose the current token; `return` needs one
+    function_depth: usize,
+    ast: AST,
 }
 
-//expression     → equality ;
+//program        → declaration* EOF ;
+//declaration    → funDecl | varDecl | statement ;
+//funDecl        → "fun" IDENTIFIER "(" parameters? ")" block ;      // "fun" "(" starts an expression
+//parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
+//varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+//statement      → exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt
+//                 | breakStmt | continueStmt | block ;
+//exprStmt       → expression ";" ;
+//forStmt        → "for" "(" ( "var" IDENTIFIER ":" expression
+//                 | ( varDecl | exprStmt | ";" ) expression? ";" expression? ) ")" statement ;
+//ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
+//printStmt      → "print" expression ";" ;
+//returnStmt     → "return" expression? ";" ;
+//whileStmt      → "while" "(" expression ")" statement ;
+//breakStmt      → "break" ";" ;
+//continueStmt   → "continue" ";" ;
+//block          → "{" declaration* "}" ;
+//expression     → comma ;
+//comma          → assignment ( "," assignment )* ;
+//assignment     → ( IDENTIFIER | call "[" expression "]" ) "=" assignment | conditional ;
+//conditional    → logic_or ( "?" expression ":" conditional )? ;
+//logic_or       → logic_and ( "or" logic_and )* ;
+//logic_and      → equality ( "and" equality )* ;
 //equality       → comparison ( ( "!=" | "==" ) comparison )* ;
 //comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
 //term           → factor ( ( "-" | "+" ) factor )* ;
-//factor         → unary ( ( "/" | "*" ) unary )* ;
-//unary          → ( "!" | "-" ) unary | primary ;
-//primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+//factor         → unary ( ( "/" | "*" | "%" | "~/" ) unary )* ;
+//unary          → ( "!" | "-" ) unary | power ;
+//power          → call ( "**" unary )? ;
+//call           → primary ( "(" arguments? ")" | "[" expression "]" )* ;
+//arguments      → assignment ( "," assignment )* ;
+//primary        → NUMBER | STRING | IDENTIFIER | "true" | "false" | "nil" | "(" expression ")" | lambda | funExpr ;
+//lambda         → "(" ( IDENTIFIER ( "," IDENTIFIER )* )? ")" "=>" assignment ;
+//funExpr        → "fun" "(" parameters? ")" block ;
+//list           → "[" ( assignment ( "," assignment )* )? "]" ;
+//map            → "{" ( assignment ":" assignment ( "," assignment ":" assignment )* )? "}" ;
+impl<I: Iterator<Item = Token>> Parser<I> {
+    pub fn new<T: IntoIterator<Item = Token, IntoIter = I>>(tokens: T) -> Self {
+        Self {
+            tokens: tokens.into_iter(),
+            pulled: 0,
+            lookahead: VecDeque::new(),
+            previous: None,
+            doc_comments: Vec::new(),
+            claimed_docs: 0,
+            sink: collector(),
+            keywords: Keywords::new(),
+            errors: 0,
+            loop_depth: 0,
+            function_depth: 0,
+            ast: AST::new(),
+        }
+    }
+
+    // report syntax errors to `sink`, usually the one the scanner was given
+    pub fn with_sink(mut self, sink: SharedSink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    // Parses into `ast`, after what it already holds. A REPL keeps one AST across lines, so the
+    // functions declared on earlier lines can still be called.
+    pub fn with_ast(mut self, ast: AST) -> Self {
+        self.ast = ast;
+        self
+    }
+
+    pub fn sink(&self) -> SharedSink {
+        return self.sink.clone();
+    }
+
+    // the dialect the tokens were scanned with
+    pub fn with_keywords(mut self, keywords: Keywords) -> Self {
+        self.keywords = keywords;
+        self
+    }
+
+    pub fn doc_comments(&self) -> &[Token] {
+        return &self.doc_comments;
     }
 
-    pub fn parse(&mut self) -> Expression {
+    // parses one expression into the parser's AST and records it as a root
+    pub fn parse(&mut self) -> ExprId {
+        let errors = self.errors;
         let expression = self.expression().unwrap_or_else(|| {
-            self.error(self.peek().unwrap().clone(), "Expect expression");
-            Expression::Literal(Literal::NUMBER(Token::new(TokenType::Number, "0".to_string(), Some("0".to_string()), 1)))
+            if self.errors == errors {
+                let token = self.peek().unwrap().clone();
+                self.error(token, codes::EXPECTED_EXPRESSION, "Expect expression");
+            }
+            self.ast.add(Expression::Literal(Literal::NUMBER(Token::new(TokenType::Number, "0".to_string(), Some("0".to_string()), 1))))
         });
+        self.ast.add_root(expression);
         return expression;
     }
 
+    // parses declarations up to the end of the input into the parser's AST, as its program
+    pub fn parse_program(&mut self) -> Vec<StmtId> {
+        let mut statements = Vec::new();
+        while !self.at_end() {
+            if let Some(statement) = self.declaration() {
+                self.ast.add_to_program(statement);
+                statements.push(statement);
+            }
+        }
+        return statements;
+    }
+
+    // One declaration at a time, for a caller that needs to know where each ends, such as a
+    // document that reparses only what an edit touched. None for a declaration that failed to
+    // parse; it has been reported and skipped. The statement is not added to the program.
+    pub fn parse_declaration(&mut self) -> Option<StmtId> {
+        return self.declaration();
+    }
+
+    pub fn at_end(&mut self) -> bool {
+        return self.is_at_end() || self.peek().is_none();
+    }
+
+    // how many input tokens the declarations parsed so far took up, doc comments included
+    pub fn consumed(&self) -> usize {
+        return self.pulled - self.lookahead.len();
+    }
+
+    pub fn ast(&self) -> &AST {
+        return &self.ast;
+    }
+
+    pub fn into_ast(self) -> AST {
+        return self.ast;
+    }
+
 
     fn synchronize(&mut self) {
         self.advance();
         while !self.is_at_end() {
             let prev_token_type = self.previous().map(|token| token.token_type.clone());
-            match prev_token_type {
-                Some(TokenType::Semicolon) => return,
+            if let Some(TokenType::Semicolon) = prev_token_type {
+                return;
+            }
+            let token_type = self.peek().map(|token| token.token_type.clone());
+            match token_type {
+                Some(TokenType::Class)
+                | Some(TokenType::Fun)
+                | Some(TokenType::Var)
+                | Some(TokenType::For)
+                | Some(TokenType::If)
+                | Some(TokenType::While)
+                | Some(TokenType::Print)
+                | Some(TokenType::Return) => return,
                 _ => {}
             }
+            self.advance();
+        }
+    }
+
+    // A declaration that fails to parse is skipped up to the next statement boundary, so one
+    // mistake costs one diagnostic rather than one per remaining token.
+    //
+    // Doc comments since the previous statement belong to a `var` or `fun` declaration that
+    // starts here; above anything else they are dropped.
+    fn declaration(&mut self) -> Option<StmtId> {
+        let errors = self.errors;
+        let token_type = self.peek().map(|token| token.token_type.clone());
+        let is_function = token_type == Some(TokenType::Fun) && self.kind_at(1) == Some(TokenType::Identifier);
+        let docs = self.doc_comments[self.claimed_docs..].to_vec();
+        self.claimed_docs = self.doc_comments.len();
+        let statement = match token_type {
+            Some(TokenType::Var) => self.var_declaration(),
+            // `fun (` is an anonymous function, which starts an expression statement
+            Some(TokenType::Fun) if is_function => self.function(),
+            _ => self.statement(),
+        };
+        if let Some(id) = statement {
+            if token_type == Some(TokenType::Var) || is_function {
+                self.ast.add_docs(id, docs);
+            }
+        }
+        if statement.is_none() {
+            if self.errors == errors {
+                let token = self.peek().unwrap().clone();
+                self.error(token, codes::EXPECTED_EXPRESSION, "Expect expression");
+            }
+            self.synchronize();
+        }
+        return statement;
+    }
+
+    fn var_declaration(&mut self) -> Option<StmtId> {
+        let _ = self.advance();
+        let name = self.consume(TokenType::Identifier, codes::EXPECTED_NAME, "Expect variable name")?;
+        let mut initializer = None;
+        if self.check(TokenType::Equal) {
+            let _ = self.advance();
+            initializer = Some(self.expression()?);
         }
+        self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after variable declaration")?;
+        return Some(self.ast.add_statement(Statement::Var { name, initializer }));
     }
 
-    fn expression(&mut self) -> Option<Expression> {
-        return self.equality();
+    fn function(&mut self) -> Option<StmtId> {
+        let _ = self.advance();
+        let name = self.consume(TokenType::Identifier, codes::EXPECTED_NAME, "Expect function name")?;
+        self.consume(TokenType::LeftParen, codes::EXPECTED_PARENTHESIS, "Expect '(' after function name")?;
+        let params = self.parameters();
+        self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after parameters")?;
+        let body = self.function_body()?;
+        return Some(self.ast.add_statement(Statement::Function { name, params, body }));
     }
 
-    fn equality(&mut self) -> Option<Expression> {
-        let mut base_expr = self.comparison()?;
+    // `IDENTIFIER ( "," IDENTIFIER )*`, possibly empty; stops at the first token that does not fit
+    fn parameters(&mut self) -> Vec<Token> {
+        let mut params: Vec<Token> = Vec::new();
+        while self.check(TokenType::Identifier) {
+            let param = self.advance().unwrap().clone();
+            if params.iter().any(|earlier| earlier.lexeme == param.lexeme) {
+                let message = format!("duplicate parameter '{}'", param.lexeme);
+                self.error(param.clone(), codes::DUPLICATE_PARAMETER, &message);
+            }
+            params.push(param);
+            if !self.check(TokenType::Comma) {
+                break;
+            }
+            let _ = self.advance();
+        }
+        return params;
+    }
+
+    // the block of a function; loops around the function do not reach into it
+    fn function_body(&mut self) -> Option<Vec<StmtId>> {
+        self.consume(TokenType::LeftBrace, codes::UNCLOSED_BLOCK, "Expect '{' before function body")?;
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let body = self.block_statements();
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        return Some(body);
+    }
+
+    fn statement(&mut self) -> Option<StmtId> {
+        match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Print) => {
+                let keyword = self.advance()?.clone();
+                let value = self.expression()?;
+                self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after value")?;
+                return Some(self.ast.add_statement(Statement::Print { keyword, value }));
+            }
+            Some(TokenType::If) => return self.if_statement(),
+            Some(TokenType::Return) => return self.return_statement(),
+            Some(TokenType::While) => return self.while_statement(),
+            Some(TokenType::For) => return self.for_statement(),
+            Some(TokenType::Break) | Some(TokenType::Continue) => return self.loop_control_statement(),
+            // at the start of a statement a brace opens a block; a map needs parentheses there
+            Some(TokenType::LeftBrace) if self.at_map_entry() => {
+                let brace = self.peek()?.clone();
+                self.errors += 1;
+                let diagnostic = Diagnostic::error("a map literal cannot start a statement", brace.span)
+                    .with_code(codes::MALFORMED_MAP)
+                    .with_help("wrap the map in parentheses, as in `({\"k\": v});`");
+                self.sink.borrow_mut().emit(diagnostic);
+                // read it as the map it was meant to be, so the rest of it is not reported too
+                let expression = self.expression()?;
+                self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after expression")?;
+                return Some(self.ast.add_statement(Statement::Expression { expression }));
+            }
+            Some(TokenType::LeftBrace) => {
+                let brace = self.advance()?.clone();
+                let statements = self.block_statements();
+                return Some(self.ast.add_statement(Statement::Block { brace, statements }));
+            }
+            _ => {
+                let expression = self.expression()?;
+                self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after expression")?;
+                return Some(self.ast.add_statement(Statement::Expression { expression }));
+            }
+        }
+    }
+
+    // `{` then a literal key and `:`, which no block can start with
+    fn at_map_entry(&mut self) -> bool {
+        let key = matches!(
+            self.kind_at(1),
+            Some(TokenType::String | TokenType::Number | TokenType::True | TokenType::False | TokenType::Nil)
+        );
+        return key && self.kind_at(2) == Some(TokenType::Colon);
+    }
+
+    // the declarations of a block whose `{` has been consumed, up to and including its `}`
+    fn block_statements(&mut self) -> Vec<StmtId> {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            statements.extend(self.declaration());
+        }
+        self.consume(TokenType::RightBrace, codes::UNCLOSED_BLOCK, "Expect '}' after block");
+        return statements;
+    }
+
+    fn if_statement(&mut self) -> Option<StmtId> {
+        let keyword = self.advance()?.clone();
+        self.consume(TokenType::LeftParen, codes::EXPECTED_PARENTHESIS, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after if condition")?;
+        let then_branch = self.statement()?;
+        let mut else_branch = None;
+        if self.check(TokenType::Else) {
+            let _ = self.advance();
+            else_branch = Some(self.statement()?);
+        }
+        return Some(self.ast.add_statement(Statement::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        }));
+    }
+
+    fn return_statement(&mut self) -> Option<StmtId> {
+        let keyword = self.advance()?.clone();
+        if self.function_depth == 0 {
+            self.error(keyword.clone(), codes::RETURN_OUTSIDE_FUNCTION, "'return' outside of a function");
+        }
+        let mut value = None;
+        if !self.check(TokenType::Semicolon) {
+            value = Some(self.expression()?);
+        }
+        self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after return value")?;
+        return Some(self.ast.add_statement(Statement::Return { keyword, value }));
+    }
+
+    fn while_statement(&mut self) -> Option<StmtId> {
+        let keyword = self.advance()?.clone();
+        self.consume(TokenType::LeftParen, codes::EXPECTED_PARENTHESIS, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after condition")?;
+        let body = self.loop_body()?;
+        return Some(self.ast.add_statement(Statement::While {
+            keyword,
+            condition,
+            body,
+            increment: None,
+        }));
+    }
+
+    // `for (init; condition; increment) body` becomes `{ init; while (condition) body }`, with
+    // the increment kept on the loop so that `continue` still runs it
+    fn for_statement(&mut self) -> Option<StmtId> {
+        let keyword = self.advance()?.clone();
+        self.consume(TokenType::LeftParen, codes::EXPECTED_PARENTHESIS, "Expect '(' after 'for'")?;
+        if self.kind_at(0) == Some(TokenType::Var) && self.kind_at(2) == Some(TokenType::Colon) {
+            return self.for_each_statement(keyword);
+        }
+        let initializer = match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Semicolon) => {
+                let _ = self.advance();
+                None
+            }
+            Some(TokenType::Var) => Some(self.var_declaration()?),
+            _ => {
+                let expression = self.expression()?;
+                self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after loop initializer")?;
+                Some(self.ast.add_statement(Statement::Expression { expression }))
+            }
+        };
+        let condition = match self.check(TokenType::Semicolon) {
+            // a missing condition is always true
+            true => {
+                let token = Token::new(TokenType::True, "true".to_string(), None, keyword.line);
+                self.ast.add(Expression::Literal(Literal::KEYWORD(token)))
+            }
+            false => self.expression()?,
+        };
+        self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, "Expect ';' after loop condition")?;
+        let mut increment = None;
+        if !self.check(TokenType::RightParen) {
+            increment = Some(self.expression()?);
+        }
+        self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after for clauses")?;
+        let body = self.loop_body()?;
+        let brace = Token::new(TokenType::LeftBrace, "{".to_string(), None, keyword.line);
+        let looping = self.ast.add_statement(Statement::While {
+            keyword,
+            condition,
+            body,
+            increment,
+        });
+        let Some(initializer) = initializer else {
+            return Some(looping);
+        };
+        return Some(self.ast.add_statement(Statement::Block {
+            brace,
+            statements: vec![initializer, looping],
+        }));
+    }
+
+    // `for (var name : iterable) body`, once `for (` has been consumed
+    fn for_each_statement(&mut self, keyword: Token) -> Option<StmtId> {
+        let _ = self.advance();
+        let name = self.consume(TokenType::Identifier, codes::EXPECTED_NAME, "Expect variable name")?;
+        let _ = self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after loop iterable")?;
+        let body = self.loop_body()?;
+        return Some(self.ast.add_statement(Statement::ForEach {
+            keyword,
+            name,
+            iterable,
+            body,
+        }));
+    }
+
+    fn loop_body(&mut self) -> Option<StmtId> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        return body;
+    }
+
+    // outside a loop this is reported, but the statement is still built
+    fn loop_control_statement(&mut self) -> Option<StmtId> {
+        let keyword = self.advance()?.clone();
+        if self.loop_depth == 0 {
+            let message = format!("'{}' outside of a loop", keyword.lexeme);
+            self.error(keyword.clone(), codes::LOOP_CONTROL_OUTSIDE_LOOP, &message);
+        }
+        let message = format!("Expect ';' after '{}'", keyword.lexeme);
+        self.consume(TokenType::Semicolon, codes::MISSING_SEMICOLON, &message)?;
+        match keyword.token_type {
+            TokenType::Break => return Some(self.ast.add_statement(Statement::Break { keyword })),
+            _ => return Some(self.ast.add_statement(Statement::Continue { keyword })),
+        }
+    }
+
+    fn expression(&mut self) -> Option<ExprId> {
+        return self.comma();
+    }
+
+    // C's sequence operator: evaluates both sides and yields the right one
+    fn comma(&mut self) -> Option<ExprId> {
+        let mut base_expr = self.assignment()?;
+        while self.check(TokenType::Comma) {
+            let operator = self.advance()?.clone();
+            let right = self.assignment()?;
+            base_expr = self.ast.add(Expression::Binary {
+                left: base_expr,
+                operator,
+                right,
+            });
+        }
+        return Some(base_expr);
+    }
+
+    // `x = v` and `xs[i] = v`, right-associative. The target is parsed as an ordinary
+    // expression first and only then checked, since `xs[i]` and `xs[i] + 1` start the same way.
+    fn assignment(&mut self) -> Option<ExprId> {
+        let target = self.conditional()?;
+        if !self.check(TokenType::Equal) {
+            return Some(target);
+        }
+        let equals = self.advance()?.clone();
+        if !matches!(self.ast[target], Expression::Variable { .. } | Expression::Index { .. }) {
+            self.error(equals, codes::INVALID_ASSIGNMENT_TARGET, "Invalid assignment target");
+            // parse the value anyway so it is not reported again as something else
+            let _ = self.assignment();
+            return Some(target);
+        }
+        // the target is the newest node, as nothing has wrapped it; it becomes the assignment
+        let assignment = match self.ast.pop(target) {
+            Expression::Variable { name } => {
+                let value = self.assignment()?;
+                Expression::Assign { name, value }
+            }
+            Expression::Index { object, bracket, index } => {
+                let value = self.assignment()?;
+                Expression::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    value,
+                }
+            }
+            _ => unreachable!(),
+        };
+        return Some(self.ast.add(assignment));
+    }
+
+    // Right-associative: `a ? b : c ? d : e` is `a ? b : (c ? d : e)`. As in C, the then
+    // branch is a full expression, commas included, since the `:` ends it unambiguously; the
+    // else branch stops before a comma or assignment.
+    fn conditional(&mut self) -> Option<ExprId> {
+        let condition = self.logic_or()?;
+        if !self.check(TokenType::Question) {
+            return Some(condition);
+        }
+        let question = self.advance()?.clone();
+        let then_branch = self.expression()?;
+        self.consume(TokenType::Colon, codes::UNFINISHED_CONDITIONAL, "Expect ':' after the then branch of a conditional");
+        let else_branch = self.conditional()?;
+        return Some(self.ast.add(Expression::Conditional {
+            condition,
+            question,
+            then_branch,
+            else_branch,
+        }));
+    }
+
+    fn logic_or(&mut self) -> Option<ExprId> {
+        let mut left = self.left_operand(&[TokenType::Or], Self::logic_and)?;
+        while self.check(TokenType::Or) {
+            let operator = self.advance()?.clone();
+            let right = self.logic_and()?;
+            left = self.ast.add(Expression::Logical { left, operator, right });
+        }
+        return Some(left);
+    }
+
+    fn logic_and(&mut self) -> Option<ExprId> {
+        let mut left = self.left_operand(&[TokenType::And], Self::equality)?;
+        while self.check(TokenType::And) {
+            let operator = self.advance()?.clone();
+            let right = self.equality()?;
+            left = self.ast.add(Expression::Logical { left, operator, right });
+        }
+        return Some(left);
+    }
+
+    fn equality(&mut self) -> Option<ExprId> {
+        let mut base_expr = self.left_operand(&[TokenType::BangEqual, TokenType::EqualEqual], Self::comparison)?;
         while let Some(token_type) = self.peek().map(|token| token.token_type.clone()) {
             match token_type {
                 TokenType::BangEqual | TokenType::EqualEqual => {
                     let operator = self.advance()?.clone();
                     let right = self.comparison()?;
 
-                    base_expr = Expression::Binary {
-                        left: Rc::new(base_expr),
+                    base_expr = self.ast.add(Expression::Binary {
+                        left: base_expr,
                         operator,
-                        right: Rc::new(right),
-                    };
+                        right,
+                    });
                 }
                 _ => break,
             }
@@ -67,8 +575,8 @@ impl Parser {
         return Some(base_expr);
     }
 
-    fn comparison(&mut self) -> Option<Expression> {
-        let mut base_expr = self.term()?;
+    fn comparison(&mut self) -> Option<ExprId> {
+        let mut base_expr = self.left_operand(&[TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual], Self::term)?;
         while let Some(token_type) = self.peek().map(|token| token.token_type.clone()) {
             match token_type {
                 TokenType::Greater
@@ -78,11 +586,11 @@ impl Parser {
                     let operator = self.advance()?.clone();
                     let right = self.term()?;
 
-                    base_expr = Expression::Binary {
-                        left: Rc::new(base_expr),
+                    base_expr = self.ast.add(Expression::Binary {
+                        left: base_expr,
                         operator,
-                        right: Rc::new(right),
-                    };
+                        right,
+                    });
                 }
                 _ => break,
             }
@@ -90,19 +598,20 @@ impl Parser {
         return Some(base_expr);
     }
 
-    fn term(&mut self) -> Option<Expression> {
-        let mut base_expr = self.factor()?;
+    fn term(&mut self) -> Option<ExprId> {
+        // a leading '-' is negation, so only '+' can be missing its left operand
+        let mut base_expr = self.left_operand(&[TokenType::Plus], Self::factor)?;
         while let Some(token_type) = self.peek().map(|token| token.token_type.clone()) {
             match token_type {
                 TokenType::Minus | TokenType::Plus => {
                     let operator = self.advance()?.clone();
                     let right = self.factor()?;
 
-                    base_expr = Expression::Binary {
-                        left: Rc::new(base_expr),
+                    base_expr = self.ast.add(Expression::Binary {
+                        left: base_expr,
                         operator,
-                        right: Rc::new(right),
-                    };
+                        right,
+                    });
                 }
                 _ => break,
             }
@@ -110,19 +619,20 @@ impl Parser {
         return Some(base_expr);
     }
 
-    fn factor(&mut self) -> Option<Expression> {
-        let mut base_expr = self.unary()?;
+    fn factor(&mut self) -> Option<ExprId> {
+        let operators = [TokenType::Slash, TokenType::Star, TokenType::Percent, TokenType::TildeSlash, TokenType::StarStar];
+        let mut base_expr = self.left_operand(&operators, Self::unary)?;
         while let Some(token_type) = self.peek().map(|token| token.token_type.clone()) {
             match token_type {
-                TokenType::Slash | TokenType::Star => {
+                TokenType::Slash | TokenType::Star | TokenType::Percent | TokenType::TildeSlash => {
                     let operator = self.advance()?.clone();
                     let right = self.unary()?;
 
-                    base_expr = Expression::Binary {
-                        left: Rc::new(base_expr),
+                    base_expr = self.ast.add(Expression::Binary {
+                        left: base_expr,
                         operator,
-                        right: Rc::new(right),
-                    };
+                        right,
+                    });
                 }
                 _ => break,
             }
@@ -130,81 +640,311 @@ impl Parser {
         return Some(base_expr);
     }
 
-    fn unary(&mut self) -> Option<Expression> {
+    fn unary(&mut self) -> Option<ExprId> {
         let token_type = self.peek().map(|token| token.token_type.clone());
         match token_type {
             Some(TokenType::Bang) | Some(TokenType::Minus) => {
                 let operator = self.advance()?.clone();
                 let unary = self.unary()?;
-                return Some(Expression::Unary {
+                return Some(self.ast.add(Expression::Unary {
                     operator,
-                    value: Rc::new(unary),
-                });
+                    value: unary,
+                }));
             }
             _ => {
-                return self.primary();
+                return self.power();
             }
         }
     }
 
-    fn primary(&mut self) -> Option<Expression> {
+    // `**` sits between unary and call so `-2 ** 2` is `-(2 ** 2)`; its right operand is a
+    // unary, which makes it right-associative and lets `2 ** -1` through
+    fn power(&mut self) -> Option<ExprId> {
+        let base = self.call()?;
+        if !self.check(TokenType::StarStar) {
+            return Some(base);
+        }
+        let operator = self.advance()?.clone();
+        let exponent = self.unary()?;
+        return Some(self.ast.add(Expression::Binary {
+            left: base,
+            operator,
+            right: exponent,
+        }));
+    }
+
+    fn call(&mut self) -> Option<ExprId> {
+        let mut base_expr = self.primary()?;
+        loop {
+            if self.check(TokenType::LeftParen) {
+                base_expr = self.finish_call(base_expr)?;
+                continue;
+            }
+            if !self.check(TokenType::LeftBracket) {
+                break;
+            }
+            let bracket = self.advance()?.clone();
+            let index = self.expression();
+            self.consume(TokenType::RightBracket, codes::UNCLOSED_BRACKET, "Expect ']' after index");
+            base_expr = self.ast.add(Expression::Index {
+                object: base_expr,
+                bracket,
+                index: index?,
+            });
+        }
+        return Some(base_expr);
+    }
+
+    // the arguments are assignments, so the commas between them are not comma operators
+    fn finish_call(&mut self, callee: ExprId) -> Option<ExprId> {
+        let _ = self.advance();
+        let mut arguments = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                arguments.push(self.assignment()?);
+                if !self.check(TokenType::Comma) {
+                    break;
+                }
+                let _ = self.advance();
+            }
+        }
+        let paren = self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after arguments")?;
+        return Some(self.ast.add(Expression::Call { callee, paren, arguments }));
+    }
+
+    fn list(&mut self) -> Option<ExprId> {
+        let bracket = self.advance()?.clone();
+        let mut elements = Vec::new();
+        if !self.check(TokenType::RightBracket) {
+            loop {
+                elements.push(self.assignment()?);
+                if !self.check(TokenType::Comma) {
+                    break;
+                }
+                let _ = self.advance();
+            }
+        }
+        self.consume(TokenType::RightBracket, codes::UNCLOSED_BRACKET, "Expect ']' after list elements");
+        return Some(self.ast.add(Expression::List { bracket, elements }));
+    }
+
+    // A `{` that reaches an operand is a map. At the start of a statement `statement` has
+    // already taken it as a block, so a map there needs parentheses.
+    fn map(&mut self) -> Option<ExprId> {
+        let brace = self.advance()?.clone();
+        let mut entries = Vec::new();
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                let key = self.assignment()?;
+                self.check_map_key(key);
+                self.consume(TokenType::Colon, codes::MALFORMED_MAP, "Expect ':' after map key");
+                entries.push((key, self.assignment()?));
+                if !self.check(TokenType::Comma) {
+                    break;
+                }
+                let _ = self.advance();
+            }
+        }
+        self.consume(TokenType::RightBrace, codes::MALFORMED_MAP, "Expect '}' after map entries");
+        return Some(self.ast.add(Expression::Map { brace, entries }));
+    }
+
+    // keys are strings, numbers and booleans; catch the keys that can never be one
+    fn check_map_key(&mut self, key: ExprId) {
+        if let Some(token) = self.never_a_key(key) {
+            self.error(token, codes::INVALID_MAP_KEY, "map keys must be strings, numbers or booleans");
+        }
+    }
+
+    // The token to blame if `key` always evaluates to nil, a list, a map or a function. Keys
+    // that only might, like `c ? nil : 1`, are left to the runtime.
+    fn never_a_key(&self, key: ExprId) -> Option<Token> {
+        match &self.ast[key] {
+            Expression::Literal(Literal::KEYWORD(token)) if token.token_type == TokenType::Nil => Some(token.clone()),
+            Expression::List { bracket, .. } => Some(bracket.clone()),
+            Expression::Map { brace, .. } => Some(brace.clone()),
+            Expression::Lambda { arrow, .. } => Some(arrow.clone()),
+            Expression::Function { keyword, .. } => Some(keyword.clone()),
+            Expression::Grouping { interior } => self.never_a_key(*interior),
+            // the comma operator and assignments yield their right-hand side
+            Expression::Binary { operator, right, .. } if operator.token_type == TokenType::Comma => self.never_a_key(*right),
+            Expression::Assign { value, .. } | Expression::SetIndex { value, .. } => self.never_a_key(*value),
+            Expression::Conditional {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let then_token = self.never_a_key(*then_branch)?;
+                self.never_a_key(*else_branch)?;
+                Some(then_token)
+            }
+            _ => None,
+        }
+    }
+
+    fn primary(&mut self) -> Option<ExprId> {
         let token_type = self.peek().map(|token| token.token_type.clone());
         match token_type {
             Some(TokenType::False) | Some(TokenType::True) | Some(TokenType::Nil) => {
                 let new_literal = Literal::KEYWORD(self.advance().unwrap().clone());
-                return Some(Expression::Literal(new_literal));
+                return Some(self.ast.add(Expression::Literal(new_literal)));
             }
             Some(TokenType::String) => {
                 let string = self.advance().unwrap().clone();
                 let new_literal = Literal::STRING(string);
-                return Some(Expression::Literal(new_literal));
+                return Some(self.ast.add(Expression::Literal(new_literal)));
             }
             Some(TokenType::Number) => {
                 let string = self.advance().unwrap().clone();
                 let new_literal = Literal::NUMBER(string);
-                return Some(Expression::Literal(new_literal));
+                return Some(self.ast.add(Expression::Literal(new_literal)));
+            }
+            Some(TokenType::Identifier) => {
+                let name = self.advance().unwrap().clone();
+                self.check_misspelled_keyword(&name);
+                return Some(self.ast.add(Expression::Variable { name }));
+            }
+            Some(TokenType::LeftBracket) => {
+                return self.list();
+            }
+            Some(TokenType::LeftBrace) => {
+                return self.map();
+            }
+            Some(TokenType::LeftParen) if self.at_lambda() => {
+                return self.lambda();
+            }
+            Some(TokenType::Fun) => {
+                return self.function_expression();
             }
             Some(TokenType::LeftParen) => {
                 let _ = self.advance();
                 let base_expr = self.expression();
 
-                self.consume(TokenType::RightParen, "Expect ) after expression");
-                return Some(Expression::Grouping {
-                    interior: Rc::new(base_expr.unwrap())
-                });
+                self.consume(TokenType::RightParen, codes::UNCLOSED_GROUPING, "Expect ) after expression");
+                return Some(self.ast.add(Expression::Grouping {
+                    interior: base_expr?,
+                }));
+            }
+            Some(TokenType::Break) | Some(TokenType::Continue) => {
+                self.loop_control();
+                return None;
             }
             Some(TokenType::Eof) => {
                 return None;
             }
             _ => {
-                self.error(self.peek().unwrap().clone(), "Expect expression");
+                let token = self.peek().unwrap().clone();
+                self.error(token, codes::EXPECTED_EXPRESSION, "Expect expression");
                 return None;
             }
         }
     }
 
-    fn consume(&mut self, check_on: TokenType, message: &str) {
-        if self.check(check_on) {
-            let _ = self.advance();
+    // `break` and `continue` are statements, so they can never be an operand; outside a loop
+    // that is the more useful thing to say
+    fn loop_control(&mut self) {
+        let keyword = self.advance().unwrap().clone();
+        if self.loop_depth == 0 {
+            let message = format!("'{}' outside of a loop", keyword.lexeme);
+            self.error(keyword, codes::LOOP_CONTROL_OUTSIDE_LOOP, &message);
         } else {
-            self.error(self.peek().unwrap().clone(), message)
+            self.error(keyword, codes::EXPECTED_EXPRESSION, "Expect expression");
+        }
+    }
+
+    fn kind_at(&mut self, distance: usize) -> Option<TokenType> {
+        return self.peek_nth(distance).map(|token| token.token_type.clone());
+    }
+
+    // a `(` starts a lambda when a parameter list and `=>` follow it; otherwise it is a grouping
+    fn at_lambda(&mut self) -> bool {
+        let mut distance = 1;
+        if self.kind_at(distance) == Some(TokenType::Identifier) {
+            distance += 1;
+            while self.kind_at(distance) == Some(TokenType::Comma) && self.kind_at(distance + 1) == Some(TokenType::Identifier) {
+                distance += 2;
+            }
         }
+        return self.kind_at(distance) == Some(TokenType::RightParen) && self.kind_at(distance + 1) == Some(TokenType::Arrow);
+    }
+
+    // only called once `at_lambda` has checked the shape, so every token here is known
+    fn lambda(&mut self) -> Option<ExprId> {
+        let _ = self.advance();
+        let params = self.parameters();
+        let _ = self.advance();
+        let arrow = self.advance()?.clone();
+        // loops around the lambda do not reach into its body
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.assignment();
+        self.loop_depth = loop_depth;
+        let body = body?;
+        return Some(self.ast.add(Expression::Lambda { params, arrow, body }));
+    }
+
+    // `fun (a, b) { ... }`; a `fun` followed by a name is a declaration instead
+    fn function_expression(&mut self) -> Option<ExprId> {
+        let keyword = self.advance()?.clone();
+        self.consume(TokenType::LeftParen, codes::EXPECTED_PARENTHESIS, "Expect '(' after 'fun'")?;
+        let params = self.parameters();
+        self.consume(TokenType::RightParen, codes::EXPECTED_PARENTHESIS, "Expect ')' after parameters")?;
+        let body = self.function_body()?;
+        return Some(self.ast.add(Expression::Function { keyword, params, body }));
     }
 
-    fn error(&self, token: Token, message: &str) {
-        token_error(token, message);
+    // The left operand of a binary level, with an error production for `+ 1`: a binary
+    // operator where an operand should start. It is reported and skipped, and its right operand
+    // stands in for the whole operation, so `+ 1 == 2` still parses as `1 == 2`.
+    fn left_operand(&mut self, operators: &[TokenType], operand: fn(&mut Self) -> Option<ExprId>) -> Option<ExprId> {
+        let is_operator = self.peek().is_some_and(|token| operators.contains(&token.token_type));
+        if is_operator {
+            let operator = self.advance().unwrap().clone();
+            let message = format!("missing left operand for '{}'", operator.lexeme);
+            self.error(operator, codes::MISSING_LEFT_OPERAND, &message);
+        }
+        return operand(self);
     }
 
-    fn check(&self, check_on: TokenType) -> bool {
+    // `retrun x`: a name directly followed by another operand is never valid, and when the
+    // name is a near miss for a keyword that keyword is what was meant
+    fn check_misspelled_keyword(&mut self, name: &Token) {
+        let next = self.peek().map(|token| token.token_type.clone());
+        let operand_follows = matches!(
+            next,
+            Some(TokenType::Identifier | TokenType::Number | TokenType::String | TokenType::True | TokenType::False | TokenType::Nil)
+        );
+        if !operand_follows {
+            return;
+        }
+        if let Some(keyword) = self.keywords.suggest(&name.lexeme) {
+            let diagnostic = Diagnostic::error(&format!("'{}' is not a keyword", name.lexeme), name.span)
+                .with_code(codes::MISSPELLED_KEYWORD)
+                .with_help(&format!("did you mean `{}`?", keyword));
+            self.sink.borrow_mut().emit(diagnostic);
+        }
+    }
+
+    // the expected token, or None once the one found instead has been reported
+    fn consume(&mut self, check_on: TokenType, code: &'static str, message: &str) -> Option<Token> {
+        if self.check(check_on) {
+            return self.advance().cloned();
+        }
+        let token = self.peek().unwrap().clone();
+        self.error(token, code, message);
+        return None;
+    }
+
+    fn error(&mut self, token: Token, code: &'static str, message: &str) {
+        self.errors += 1;
+        self.sink.borrow_mut().emit(Diagnostic::error(message, token.span).with_code(code));
+    }
+
+    fn check(&mut self, check_on: TokenType) -> bool {
         if !self.is_at_end() {
             let token = self.peek();
             match token {
                 Some(token) => {
-                    if token.token_type == check_on {
-                        return true;
-                    } else {
-                        return false;
-                    }
+                    return token.token_type == check_on;
                 }
                 None => {
                     return false;
@@ -216,14 +956,14 @@ impl Parser {
     }
 
     fn advance(&mut self) -> Option<&Token> {
-        let token = self.tokens.get(self.current);
-        if !self.is_at_end() {
-            self.current += 1;
+        if self.is_at_end() {
+            return self.peek();
         }
-        return token;
+        self.previous = self.lookahead.pop_front();
+        return self.previous.as_ref();
     }
 
-    fn is_at_end(&self) -> bool {
+    fn is_at_end(&mut self) -> bool {
         let token = self.peek();
         match token {
             Some(token) => {
@@ -238,13 +978,32 @@ impl Parser {
         return false;
     }
 
-    fn peek(&self) -> Option<&Token> {
-        let current_token = self.tokens.get(self.current);
-        return current_token;
+    // buffers tokens until `distance` tokens of lookahead are available or the input runs out
+    fn fill(&mut self, distance: usize) -> bool {
+        while self.lookahead.len() <= distance {
+            let token = self.tokens.next();
+            self.pulled += usize::from(token.is_some());
+            match token {
+                Some(token) if token.token_type == TokenType::DocComment => {
+                    self.doc_comments.push(token);
+                }
+                Some(token) => self.lookahead.push_back(token),
+                None => return false,
+            }
+        }
+        return true;
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.fill(0);
+        return self.lookahead.front();
+    }
+    fn peek_nth(&mut self, distance: usize) -> Option<&Token> {
+        self.fill(distance);
+        return self.lookahead.get(distance);
     }
     fn previous(&self) -> Option<&Token> {
-        let prev_token = self.tokens.get(self.current - 1);
-        return prev_token;
+        return self.previous.as_ref();
     }
 }
 
@@ -254,30 +1013,29 @@ mod tests {
     use crate::{expression::*, Scanner};
     use crate::tokens::Token;
 
-    fn setup(input: &str) -> Expression {
+    fn setup_ast(input: &str) -> (AST, ExprId) {
         let mut scanner = Scanner::new(input);
         let tokens = scanner.scan_tokens();
-        return Parser::new(tokens).parse();
+        let mut parser = Parser::new(tokens);
+        let root = parser.parse();
+        return (parser.into_ast(), root);
+    }
+
+    fn setup(input: &str) -> Expression {
+        let (ast, root) = setup_ast(input);
+        return ast[root].clone();
     }
 
 
     #[test]
     fn test_parser() {
         let input = "1 + 2 * 3";
-        let expression = setup(input);
-
-
-        assert_eq!(expression, Expression::Binary {
-            left: Rc::new(Expression::Literal(Literal::NUMBER(Token::new(TokenType::Number, "1".to_string(), Some("1".to_string()), 1)))),
-            operator: Token::new(TokenType::Plus, "+".to_string(), None, 1),
-            right: Rc::new(Expression::Binary {
-                left: Rc::new(Expression::Literal(Literal::NUMBER(Token::new(TokenType::Number, "2".to_string(), Some("2".to_string()), 1)))),
-                operator: Token::new(TokenType::Star, "*".to_string(), None, 1),
-                right: Rc::new(Expression::Literal(Literal::NUMBER(Token::new(TokenType::Number, "3".to_string(), Some("3".to_string()), 1)))),
-            }),
-        });
+        let (ast, root) = setup_ast(input);
 
+        let expected = crate::lox_expr!((+ 1 (* 2 3)));
 
+        assert_eq!(root, expected.roots()[0]);
+        assert_eq!(ast, expected);
     }
 
     #[test]
@@ -380,6 +1138,68 @@ mod tests {
         assert!(matches!(expression, Expression::Literal(Literal::NUMBER(_))));
     }
 
+    #[test]
+    fn test_doc_comment_skipped() {
+        let mut scanner = Scanner::new("/// the answer\n1 + 2");
+        let mut parser = Parser::new(scanner.scan_tokens());
+        let root = parser.parse();
+
+        assert!(matches!(parser.ast()[root], Expression::Binary {
+            left: _,
+            operator: _,
+            right: _,
+        }));
+        assert_eq!(parser.doc_comments().len(), 1);
+        assert_eq!(parser.doc_comments()[0].literal, Some("the answer".to_string()));
+    }
+
+    #[test]
+    fn test_doc_comments_attach_to_declarations() {
+        let source = "/// the count\nvar count = 1;\n\
+                      /** adds one\n to n */\nfun increment(n) {\n/// inner\nvar one = 1; return n + one; }\n\
+                      /// not a declaration\nprint count;\nvar bare;";
+        let mut parser = Parser::new(Scanner::new(source));
+        let program = parser.parse_program();
+        let ast = parser.ast();
+        let docs = |id: StmtId| -> Vec<String> { ast.docs(id).iter().map(|doc| doc.literal.clone().unwrap()).collect() };
+
+        assert_eq!(program.len(), 4);
+        assert_eq!(docs(program[0]), ["the count"]);
+        assert_eq!(docs(program[1]), ["adds one\nto n"]);
+        let Statement::Function { body, .. } = &ast[program[1]] else { panic!("expected a function") };
+        assert_eq!(docs(body[0]), ["inner"]);
+        assert!(docs(program[2]).is_empty());
+        assert!(docs(program[3]).is_empty());
+    }
+
+    #[test]
+    fn test_streaming() {
+        let input = "1 +\n2 *\n3".as_bytes();
+        let scanner = Scanner::from_reader(std::io::BufReader::new(input));
+        let mut parser = Parser::new(scanner);
+        let root = parser.parse();
+
+        assert!(matches!(parser.ast()[root], Expression::Binary {
+            left: _,
+            operator: _,
+            right: _,
+        }));
+    }
+
+    #[test]
+    fn test_variable() {
+        let input = "a + b";
+        let (ast, root) = setup_ast(input);
+
+        match &ast[root] {
+            Expression::Binary { left, right, .. } => {
+                assert!(matches!(&ast[*left], Expression::Variable { name } if name.symbol.is_some()));
+                assert!(matches!(&ast[*right], Expression::Variable { name: _ }));
+            }
+            _ => panic!("expected a binary expression"),
+        }
+    }
+
     #[test]
     fn test_error() {
         let input = "1 +";
@@ -388,6 +1208,356 @@ mod tests {
         assert!(matches!(expression, Expression::Literal(Literal::NUMBER(_))));
     }
 
+    #[test]
+    fn test_error_diagnostic() {
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("1 + )")).with_sink(diagnostics.clone());
+        parser.parse();
+
+        let diagnostics = diagnostics.borrow();
+        assert_eq!(diagnostics[0].message, "Expect expression");
+        assert_eq!(diagnostics[0].code, Some("L0100"));
+        assert_eq!(diagnostics[0].primary_span.start, 4);
+        assert!(diagnostics[0].is_error());
+    }
+
+    #[test]
+    fn test_misspelled_keyword() {
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("retrun x")).with_sink(diagnostics.clone());
+        parser.parse();
+        assert_eq!(diagnostics.borrow()[0].code, Some("L0102"));
+        assert_eq!(diagnostics.borrow()[0].notes, vec!["help: did you mean `return`?"]);
+
+        // an ordinary name, or a near miss followed by an operator, is left alone
+        for input in ["counter x", "retrun + 1"] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            parser.parse();
+            assert!(diagnostics.borrow().is_empty(), "{}", input);
+        }
+
+        // dialect keywords are candidates too
+        let keywords = Keywords::new().with_keyword("unless", TokenType::Custom("unless"));
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("unles x")).with_sink(diagnostics.clone()).with_keywords(keywords);
+        parser.parse();
+        assert_eq!(diagnostics.borrow()[0].notes, vec!["help: did you mean `unless`?"]);
+    }
+
+    #[test]
+    fn test_missing_left_operand() {
+        for (input, operator) in [("+ 1", "+"), ("== 2", "=="), ("<= x", "<="), ("* (1 + 2)", "*"), ("1 + / 2", "/")] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            parser.parse();
+
+            let diagnostics = diagnostics.borrow();
+            assert_eq!(diagnostics.len(), 1, "{}", input);
+            assert_eq!(diagnostics[0].code, Some("L0103"));
+            assert_eq!(diagnostics[0].message, format!("missing left operand for '{}'", operator));
+        }
+
+        // the right operand takes the operation's place and parsing carries on at the same level
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("== 1 * 2 3")).with_sink(diagnostics.clone());
+        let first = parser.parse();
+        let next = parser.parse();
+        assert_eq!(parser.ast().print(first), "(* 1 2)");
+        assert_eq!(parser.ast().print(next), "3");
+        assert_eq!(diagnostics.borrow().len(), 1);
+
+        for input in ["+ 1 == 2", "1 == + 2"] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            let root = parser.parse();
+            assert_eq!(parser.ast().print(root), "(== 1 2)", "{}", input);
+            assert_eq!(diagnostics.borrow().len(), 1, "{}", input);
+        }
+
+        // a leading '-' is still negation
+        assert!(matches!(setup("- 1"), Expression::Unary { .. }));
+    }
+
+    #[test]
+    fn test_conditional_and_comma() {
+        let (ast, root) = setup_ast("a ? b, c : d ? e : f == g");
+        assert_eq!(ast.print(root), "(?: a (, b c) (?: d e (== f g)))");
+
+        let (ast, root) = setup_ast("x, y ? 1 : 2, z");
+        assert_eq!(ast.print(root), "(, (, x (?: y 1 2)) z)");
+
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("a ? b c")).with_sink(diagnostics.clone());
+        parser.parse();
+        assert_eq!(diagnostics.borrow()[0].code, Some("L0104"));
+    }
+
+    #[test]
+    fn test_logical() {
+        let (ast, root) = setup_ast("a or b and c == d or e ? f : g");
+        assert_eq!(ast.print(root), "(?: (or (or a (and b (== c d))) e) f g)");
+        assert!(matches!(ast[root], Expression::Conditional { .. }));
+
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("and a or b")).with_sink(diagnostics.clone());
+        let root = parser.parse();
+        assert_eq!(parser.ast().print(root), "(or a b)");
+        assert_eq!(diagnostics.borrow()[0].code, Some(codes::MISSING_LEFT_OPERAND));
+    }
+
+    #[test]
+    fn test_lambda() {
+        let (ast, root) = setup_ast("(a, b) => a + b * 2, 3");
+        assert_eq!(ast.print(root), "(, (=> (a b) (+ a (* b 2))) 3)");
+
+        let (ast, root) = setup_ast("() => (x) => x ? 1 : 2");
+        assert_eq!(ast.print(root), "(=> () (=> (x) (?: x 1 2)))");
+
+        // the body is an assignment, so it may assign but stops at a comma
+        let (ast, root) = setup_ast("(a) => b = a, 1");
+        assert_eq!(ast.print(root), "(, (=> (a) (= b a)) 1)");
+
+        // still groupings
+        let (ast, root) = setup_ast("(a) + 1");
+        assert_eq!(ast.print(root), "(+ (group a) 1)");
+        assert!(matches!(setup("(a, b)"), Expression::Grouping { .. }));
+
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("(a, a) => a")).with_sink(diagnostics.clone());
+        parser.parse();
+        assert_eq!(diagnostics.borrow()[0].code, Some("L0105"));
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        for (input, message) in [("break", "'break' outside of a loop"), ("1 + continue", "'continue' outside of a loop")] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            parser.parse();
+
+            let diagnostics = diagnostics.borrow();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].code, Some("L0106"));
+            assert_eq!(diagnostics[0].message, message);
+        }
+    }
+
+    #[test]
+    fn test_lists_and_indexing() {
+        let (ast, root) = setup_ast("[1, [], -xs[0][i + 1]]");
+        assert_eq!(ast.print(root), "(list 1 (list) (- (index (index xs 0) (+ i 1))))");
+
+        let (ast, root) = setup_ast("xs[0] = ys[1] = 2, 3");
+        assert_eq!(ast.print(root), "(, (setindex xs 0 (setindex ys 1 2)) 3)");
+        // the replaced index node is not left behind in the arena
+        assert_eq!(ast.len(), 9);
+
+        for input in ["1 = 2", "xs[0] + 1 = 2", "(xs[0]) = 3"] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            parser.parse();
+            let diagnostics = diagnostics.borrow();
+            assert_eq!(diagnostics.len(), 1, "{}", input);
+            assert_eq!(diagnostics[0].code, Some(codes::INVALID_ASSIGNMENT_TARGET));
+            assert_eq!(diagnostics[0].message, "Invalid assignment target");
+        }
+
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("[1, 2")).with_sink(diagnostics.clone());
+        parser.parse();
+        assert_eq!(diagnostics.borrow()[0].code, Some("L0107"));
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        let (ast, root) = setup_ast("a % b ~/ c * d");
+        assert_eq!(ast.print(root), "(* (~/ (% a b) c) d)");
+
+        let (ast, root) = setup_ast("-2 ** 3 ** -x[0] * 4");
+        assert_eq!(ast.print(root), "(* (- (** 2 (** 3 (- (index x 0))))) 4)");
+
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new("** 2")).with_sink(diagnostics.clone());
+        parser.parse();
+        assert_eq!(diagnostics.borrow()[0].code, Some(codes::MISSING_LEFT_OPERAND));
+    }
+
+    #[test]
+    fn test_maps() {
+        let (ast, root) = setup_ast("{\"host\": \"x\", \"port\": 80, true: {}}[\"port\"]");
+        assert_eq!(ast.print(root), "(index (map \"host\" \"x\" \"port\" 80 True true (map)) \"port\")");
+
+        let (ast, root) = setup_ast("{a ? b : c: d}");
+        assert_eq!(ast.print(root), "(map (?: a b c) d)");
+
+        for (input, code) in [
+            ("{\"a\" 1}", "L0108"),
+            ("{1: 2", "L0108"),
+            ("{nil: 1}", "L0109"),
+            ("{[]: 1}", "L0109"),
+            ("{(nil): 1}", "L0109"),
+            ("{(a, {}): 1}", "L0109"),
+            ("{(c ? nil : () => 0): 1}", "L0109"),
+            ("{(x = [1]): 1}", "L0109"),
+        ] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            parser.parse();
+            assert_eq!(diagnostics.borrow().len(), 1, "{}", input);
+            assert_eq!(diagnostics.borrow()[0].code, Some(code), "{}", input);
+        }
+
+        // keys that might be valid are left for the runtime to check
+        for input in ["{(c ? nil : 1): 1}", "{(nil, \"a\"): 1}", "{(-x): 1}", "{k: 1}"] {
+            let diagnostics = diagnostics::collector();
+            let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+            parser.parse();
+            assert!(diagnostics.borrow().is_empty(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_block_or_map() {
+        // a brace starting a statement opens a block; anywhere else it is a map
+        let (ast, program, diagnostics) = setup_program("{} { var m = {}; print {\"k\": m}; ({1: 2}); }");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let printed: Vec<String> = program.iter().map(|statement| ast.print(*statement)).collect();
+        assert_eq!(printed, ["(block)", "(block (var m (map)) (print (map \"k\" m)) (; (group (map 1 2))))"]);
+
+        // a map where a statement starts is reported once, with a hint, and still read as a map
+        let (ast, program, diagnostics) = setup_program("{\"k\": 1}[\"k\"]; print 2;");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Some(codes::MALFORMED_MAP));
+        assert!(diagnostics[0].notes.iter().any(|note| note.contains("parentheses")));
+        let printed: Vec<String> = program.iter().map(|statement| ast.print(*statement)).collect();
+        assert_eq!(printed, ["(; (index (map \"k\" 1) \"k\"))", "(print 2)"]);
+    }
+
+    fn setup_program(input: &str) -> (AST, Vec<StmtId>, Vec<diagnostics::Diagnostic>) {
+        let diagnostics = diagnostics::collector();
+        let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics.clone());
+        let program = parser.parse_program();
+        let diagnostics = diagnostics.borrow().clone();
+        return (parser.into_ast(), program, diagnostics);
+    }
+
+    #[test]
+    fn test_statements() {
+        let (ast, program, diagnostics) = setup_program(
+            "var x = 1; var y; x = y = 2;\n\
+             fun add(a, b) { print a + b; return; }\n\
+             if (x) { print x; } else print {\"k\": x};",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let printed: Vec<String> = program.iter().map(|statement| ast.print(*statement)).collect();
+        assert_eq!(
+            printed,
+            [
+                "(var x 1)",
+                "(var y)",
+                "(; (= x (= y 2)))",
+                "(fun add (a b) (print (+ a b)) (return))",
+                "(if x (block (print x)) (print (map \"k\" x)))",
+            ]
+        );
+        assert_eq!(ast.program(), program.as_slice());
+        // nested statements live in the arena too, but only top-level ones are in the program
+        assert_eq!(ast.statement_count(), 10);
+    }
+
+    #[test]
+    fn test_statement_errors() {
+        for (input, code) in [
+            ("print 1 print 2;", codes::MISSING_SEMICOLON),
+            ("var = 1;", codes::EXPECTED_NAME),
+            ("{ print 1;", codes::UNCLOSED_BLOCK),
+            ("if x print 1;", codes::EXPECTED_PARENTHESIS),
+            ("return 1;", codes::RETURN_OUTSIDE_FUNCTION),
+            (";", codes::EXPECTED_EXPRESSION),
+        ] {
+            let (_, _, diagnostics) = setup_program(input);
+            assert_eq!(diagnostics.len(), 1, "{}: {:?}", input, diagnostics);
+            assert_eq!(diagnostics[0].code, Some(code), "{}", input);
+        }
+
+        // the parser resynchronizes at the next statement, which still parses
+        let (ast, program, diagnostics) = setup_program("var 1 2 3; print 4;");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(program.len(), 1);
+        assert_eq!(ast.print(program[0]), "(print 4)");
+    }
+
+    #[test]
+    fn test_functions_and_calls() {
+        let (ast, program, diagnostics) = setup_program(
+            "fun f(a) { return a; }\n\
+             var g = fun (a, b) { return a + b; };\n\
+             fun (x) { print x; }(1);\n\
+             print f(g)(1, 2)[0], f();",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let printed: Vec<String> = program.iter().map(|statement| ast.print(*statement)).collect();
+        assert_eq!(
+            printed,
+            [
+                "(fun f (a) (return a))",
+                "(var g (fun (a b) (return (+ a b))))",
+                "(; (call (fun (x) (print x)) 1))",
+                "(print (, (index (call (call f g) 1 2) 0) (call f)))",
+            ]
+        );
+
+        for (input, code) in [
+            ("f(1, 2;", codes::EXPECTED_PARENTHESIS),
+            ("var f = fun;", codes::EXPECTED_PARENTHESIS),
+            ("var f = fun () { return 1; }; return 2;", codes::RETURN_OUTSIDE_FUNCTION),
+            ("var m = ({fun () {}: 1});", codes::INVALID_MAP_KEY),
+        ] {
+            let (_, _, diagnostics) = setup_program(input);
+            assert_eq!(diagnostics.len(), 1, "{}: {:?}", input, diagnostics);
+            assert_eq!(diagnostics[0].code, Some(code), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        let (ast, program, diagnostics) = setup_program(
+            "while (x) { if (x) break; continue; }\n\
+             for (var i = 0; i < 3; i = i + 1) print i;\n\
+             for (;;) { fun () { while (true) break; }; break; }\n\
+             for (var x : xs[0]) if (x) continue;",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let printed: Vec<String> = program.iter().map(|statement| ast.print(*statement)).collect();
+        assert_eq!(
+            printed,
+            [
+                "(while x (block (if x (break)) (continue)))",
+                "(block (var i 0) (while (< i 3) (print i) (= i (+ i 1))))",
+                "(while True true (block (; (fun () (while True true (break)))) (break)))",
+                "(for x (index xs 0) (if x (continue)))",
+            ]
+        );
+
+        // a function or lambda body starts outside any loop, even inside one
+        for (input, code) in [
+            ("break;", codes::LOOP_CONTROL_OUTSIDE_LOOP),
+            ("if (x) continue;", codes::LOOP_CONTROL_OUTSIDE_LOOP),
+            ("while (x) { fun () { break; }; }", codes::LOOP_CONTROL_OUTSIDE_LOOP),
+            ("for (;;) { fun f() { continue; } }", codes::LOOP_CONTROL_OUTSIDE_LOOP),
+            ("while (x) { var f = () => break; }", codes::LOOP_CONTROL_OUTSIDE_LOOP),
+            ("while (x) print break;", codes::EXPECTED_EXPRESSION),
+            ("while (x) break", codes::MISSING_SEMICOLON),
+            ("for (var i = 0; i < 3) {}", codes::MISSING_SEMICOLON),
+            ("for (var x : xs print x;", codes::EXPECTED_PARENTHESIS),
+        ] {
+            let (_, _, diagnostics) = setup_program(input);
+            assert_eq!(diagnostics.len(), 1, "{}: {:?}", input, diagnostics);
+            assert_eq!(diagnostics[0].code, Some(code), "{}", input);
+        }
+    }
+
     #[test]
     fn test_error2() {
         let input = "1 + 2 +";
@@ -0,0 +1,300 @@
+use crate::expression::{ExprId, Expression, AST};
+use crate::interner::{Interner, Symbol, SymbolTable};
+use crate::statement::{Statement, StmtId};
+use crate::tokens::Token;
+use crate::visitor::{children, statement_children};
+use diagnostics::{codes, collector, suggest, Diagnostic, SharedSink};
+
+// One block, function body or loop variable's worth of names.
+struct Scope {
+    // the names declared so far, in order
+    declared: Vec<Symbol>,
+    // every name the scope declares, including the ones further down
+    all: Vec<Symbol>,
+    // how many function bodies enclose the scope
+    functions: usize,
+}
+
+// Checks every variable a program reads or assigns against the scopes around it, before the
+// program runs, and suggests the closest name in scope for one that is not declared.
+//
+// Variables are looked up when the code runs, so a function body may use a name its enclosing
+// scope only declares after it; anywhere else a name must be declared before its use. What
+// the resolver reports are warnings: the program still runs, and fails only if it reaches the
+// undeclared name.
+//
+// Names are compared as symbols from the table the program was scanned with; they are only
+// spelled out to suggest one.
+pub struct Resolver {
+    // outermost first; the first holds the globals
+    scopes: Vec<Scope>,
+    globals: Vec<Symbol>,
+    functions: usize,
+    interner: SymbolTable,
+    sink: SharedSink,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        return Self {
+            scopes: Vec::new(),
+            globals: Vec::new(),
+            functions: 0,
+            interner: Interner::shared(),
+            sink: collector(),
+        };
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn with_sink(mut self, sink: SharedSink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    // the table the program's tokens were scanned with
+    pub fn with_interner(mut self, interner: SymbolTable) -> Self {
+        self.interner = interner;
+        self
+    }
+
+    // names defined before the program starts: the built-in functions, and in a REPL the
+    // globals of earlier lines
+    pub fn with_globals(mut self, names: impl IntoIterator<Item = Symbol>) -> Self {
+        self.globals.extend(names);
+        self
+    }
+
+    pub fn resolve(&mut self, ast: &AST, program: &[StmtId]) {
+        let mut all = self.globals.clone();
+        all.extend(self.declarations(ast, program));
+        self.scopes = vec![Scope {
+            declared: self.globals.clone(),
+            all,
+            functions: 0,
+        }];
+        for statement in program {
+            self.statement(ast, *statement);
+        }
+        self.scopes.clear();
+    }
+
+    fn statement(&mut self, ast: &AST, id: StmtId) {
+        match &ast[id] {
+            Statement::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.expression(ast, *initializer);
+                }
+                self.declare(name);
+            }
+            Statement::Block { statements, .. } => {
+                let declarations = self.declarations(ast, statements);
+                self.push(Vec::new(), declarations);
+                for statement in statements {
+                    self.statement(ast, *statement);
+                }
+                self.scopes.pop();
+            }
+            // declared first, so the body can call itself
+            Statement::Function { name, params, body } => {
+                self.declare(name);
+                let declarations = self.declarations(ast, body);
+                self.function(params, declarations, |resolver| {
+                    for statement in body {
+                        resolver.statement(ast, *statement);
+                    }
+                });
+            }
+            Statement::ForEach { name, iterable, body, .. } => {
+                self.expression(ast, *iterable);
+                let name = self.symbol(name);
+                self.push(vec![name], vec![name]);
+                self.statement(ast, *body);
+                self.scopes.pop();
+            }
+            statement => {
+                let (expressions, statements) = statement_children(statement);
+                for expression in expressions {
+                    self.expression(ast, expression);
+                }
+                for statement in statements {
+                    self.statement(ast, statement);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, ast: &AST, id: ExprId) {
+        match &ast[id] {
+            Expression::Variable { name } => self.check(name),
+            Expression::Assign { name, value } => {
+                self.expression(ast, *value);
+                self.check(name);
+            }
+            Expression::Lambda { params, body, .. } => self.function(params, Vec::new(), |resolver| resolver.expression(ast, *body)),
+            Expression::Function { params, body, .. } => {
+                let declarations = self.declarations(ast, body);
+                self.function(params, declarations, |resolver| {
+                    for statement in body {
+                        resolver.statement(ast, *statement);
+                    }
+                });
+            }
+            expression => {
+                for child in children(expression) {
+                    self.expression(ast, child);
+                }
+            }
+        }
+    }
+
+    // a body runs in one scope with its parameters, the way the interpreter calls it
+    fn function(&mut self, params: &[Token], declarations: Vec<Symbol>, body: impl FnOnce(&mut Self)) {
+        let params: Vec<Symbol> = params.iter().map(|param| self.symbol(param)).collect();
+        let mut all = params.clone();
+        all.extend(declarations);
+        self.functions += 1;
+        self.push(params, all);
+        body(self);
+        self.scopes.pop();
+        self.functions -= 1;
+    }
+
+    fn push(&mut self, declared: Vec<Symbol>, all: Vec<Symbol>) {
+        self.scopes.push(Scope {
+            declared,
+            all,
+            functions: self.functions,
+        });
+    }
+
+    // the symbol the scanner gave `name`; a built or loaded tree's names have none yet
+    fn symbol(&self, name: &Token) -> Symbol {
+        match name.symbol {
+            Some(symbol) => return symbol,
+            None => return self.interner.borrow_mut().intern(&name.lexeme),
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let name = self.symbol(name);
+        let scope = self.scopes.last_mut().unwrap();
+        scope.declared.push(name);
+    }
+
+    // the names a variable could refer to here, innermost scope first
+    fn visible(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            // code inside a function runs later, once the scopes around it may have grown
+            let names_in_scope = if self.functions > scope.functions { &scope.all } else { &scope.declared };
+            names.extend(names_in_scope.iter().copied());
+        }
+        return names;
+    }
+
+    fn check(&mut self, name: &Token) {
+        let visible = self.visible();
+        if visible.contains(&self.symbol(name)) {
+            return;
+        }
+        let mut diagnostic = Diagnostic::warning(&format!("undeclared variable '{}'", name.lexeme), name.span)
+            .with_code(codes::UNDECLARED_VARIABLE);
+        let help = {
+            let interner = self.interner.borrow();
+            let spelled = visible.into_iter().map(|symbol| interner.resolve(symbol));
+            suggest::closest(&name.lexeme, spelled).map(|closest| format!("did you mean `{}`?", closest))
+        };
+        if let Some(help) = help {
+            diagnostic = diagnostic.with_help(&help);
+        }
+        self.sink.borrow_mut().emit(diagnostic);
+    }
+
+    // the names `statements` declare directly, not counting nested blocks and functions
+    fn declarations(&self, ast: &AST, statements: &[StmtId]) -> Vec<Symbol> {
+        return statements
+            .iter()
+            .filter_map(|statement| match &ast[*statement] {
+                Statement::Var { name, .. } | Statement::Function { name, .. } => Some(self.symbol(name)),
+                _ => None,
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, Scanner};
+
+    // the warnings for `source`, as (name, suggestion) pairs
+    fn resolve(source: &str) -> Vec<(String, Option<String>)> {
+        let diagnostics = collector();
+        let interner = Interner::shared();
+        let len = interner.borrow_mut().intern("len");
+        let scanner = Scanner::new(source).with_interner(interner.clone());
+        let mut parser = Parser::new(scanner).with_sink(diagnostics.clone());
+        let program = parser.parse_program();
+        assert!(diagnostics.borrow().is_empty(), "{:?}", diagnostics.borrow());
+        Resolver::new()
+            .with_sink(diagnostics.clone())
+            .with_interner(interner)
+            .with_globals([len])
+            .resolve(parser.ast(), &program);
+        return diagnostics
+            .borrow()
+            .iter()
+            .map(|diagnostic| {
+                assert_eq!(diagnostic.code, Some(codes::UNDECLARED_VARIABLE));
+                assert!(!diagnostic.is_error());
+                let name = diagnostic.message.split('\'').nth(1).unwrap().to_string();
+                let help = diagnostic.notes.first().map(|note| note.trim_start_matches("help: ").to_string());
+                (name, help)
+            })
+            .collect();
+    }
+
+    fn warning(name: &str, help: Option<&str>) -> (String, Option<String>) {
+        return (name.to_string(), help.map(str::to_string));
+    }
+
+    #[test]
+    fn test_declared_names_resolve() {
+        let source = "var count = 1; print len([count]);\n\
+                      fun even(n) { return n == 0 or odd(n - 1); }\n\
+                      fun odd(n) { return n != 0 and even(n - 1); }\n\
+                      { var x = 1; { x = x + 1; } }\n\
+                      for (var i = 0; i < 3; i = i + 1) print i;\n\
+                      for (var item : [1]) { var f = (a) => item + a; }\n\
+                      { fun f() { return later; } var later = 1; }\n\
+                      fun outer(a) { fun f() { return g(a); } fun g(b) { return b; } return f(); }";
+        assert_eq!(resolve(source), []);
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let source = "var counter = 0; print countr;\n\
+                      fun add(left, right) { return left + rigth; }\n\
+                      { var total = 1; } print total;\n\
+                      for (var index = 0; index < 1; index = index + 1) {} print indx;\n\
+                      { print value; var value = 1; }\n\
+                      lenn([]); nothing_like_it = 1;";
+        assert_eq!(
+            resolve(source),
+            [
+                warning("countr", Some("did you mean `counter`?")),
+                warning("rigth", Some("did you mean `right`?")),
+                warning("total", None),
+                warning("indx", None),
+                warning("value", None),
+                warning("lenn", Some("did you mean `len`?")),
+                warning("nothing_like_it", None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1499 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::json::{Json, ToJson};
+use crate::source_map::FileId;
+use crate::statement::{Statement, StmtId};
+use crate::tokens::{Keywords, Span, Token, TokenType};
+use crate::visitor::{children, statement_children};
+use std::fmt;
+
+// Saved trees carry this version; readers reject anything else rather than guess. Bump it
+// whenever a node kind, field or token type code is added or changed.
+//   1: unary, binary, grouping, literal and variable nodes
+//   2: conditional, lambda, list, index, set-index and map nodes, and their tokens
+//   3: assignment nodes
+//   4: statements and the program
+//   5: logical `and`/`or` nodes
+//   6: `fun` expression and call nodes
+//   7: `while`, `break` and `continue` statements
+//   8: `for (var x : xs)` statements
+//   9: doc comments on `var` and `fun` declarations
+pub const FORMAT_VERSION: u32 = 9;
+
+const JSON_FORMAT: &str = "defunkter-ast";
+const BINARY_MAGIC: &[u8; 4] = b"LOXA";
+const CUSTOM_CODE: u8 = u8::MAX;
+
+// Interned symbols belong to one interner and are not saved; loaded tokens have none.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub message: String,
+}
+
+impl DecodeError {
+    fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not load AST: {}", self.message)
+    }
+}
+
+// The operators the parser builds each node with. The interpreter trusts these, so a tampered
+// file with any other operator is refused here rather than failing when it runs.
+const UNARY_OPERATORS: &[TokenType] = &[TokenType::Minus, TokenType::Bang];
+const BINARY_OPERATORS: &[TokenType] = &[
+    TokenType::Comma,
+    TokenType::Minus,
+    TokenType::Plus,
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::Percent,
+    TokenType::TildeSlash,
+    TokenType::StarStar,
+    TokenType::BangEqual,
+    TokenType::EqualEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+];
+const LOGICAL_OPERATORS: &[TokenType] = &[TokenType::And, TokenType::Or];
+
+fn operator(token: Token, kind: &str, operators: &[TokenType]) -> Result<Token, DecodeError> {
+    if !operators.contains(&token.token_type) {
+        return Err(DecodeError::new(&format!("'{}' is not a {} operator", token.lexeme, kind)));
+    }
+    return Ok(token);
+}
+
+// Custom tokens are saved by their word, and only load back through a dialect that has it.
+fn custom_word(word: &str, keywords: &Keywords) -> Result<&'static str, DecodeError> {
+    return keywords
+        .custom(word)
+        .ok_or_else(|| DecodeError::new(&format!("unknown dialect keyword '{}'", word)));
+}
+
+fn node_json(expression: &Expression) -> Json {
+    let id = |id: &ExprId| Json::Number(id.index() as f64);
+    let fields = match expression {
+        Expression::Unary { operator, value } => vec![
+            ("kind", Json::String("Unary".to_string())),
+            ("operator", operator.to_json()),
+            ("value", id(value)),
+        ],
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        } => vec![
+            ("kind", Json::String("Binary".to_string())),
+            ("left", id(left)),
+            ("operator", operator.to_json()),
+            ("right", id(right)),
+        ],
+        Expression::Logical {
+            left,
+            operator,
+            right,
+        } => vec![
+            ("kind", Json::String("Logical".to_string())),
+            ("left", id(left)),
+            ("operator", operator.to_json()),
+            ("right", id(right)),
+        ],
+        Expression::Grouping { interior } => vec![
+            ("kind", Json::String("Grouping".to_string())),
+            ("interior", id(interior)),
+        ],
+        Expression::Conditional {
+            condition,
+            question,
+            then_branch,
+            else_branch,
+        } => vec![
+            ("kind", Json::String("Conditional".to_string())),
+            ("condition", id(condition)),
+            ("question", question.to_json()),
+            ("then", id(then_branch)),
+            ("else", id(else_branch)),
+        ],
+        Expression::Literal(literal) => vec![
+            ("kind", Json::String("Literal".to_string())),
+            ("literal", literal.to_json()),
+        ],
+        Expression::Variable { name } => vec![
+            ("kind", Json::String("Variable".to_string())),
+            ("name", name.to_json()),
+        ],
+        Expression::Assign { name, value } => vec![
+            ("kind", Json::String("Assign".to_string())),
+            ("name", name.to_json()),
+            ("value", id(value)),
+        ],
+        Expression::Lambda { params, arrow, body } => vec![
+            ("kind", Json::String("Lambda".to_string())),
+            ("params", Json::Array(params.iter().map(|param| param.to_json()).collect())),
+            ("arrow", arrow.to_json()),
+            ("body", id(body)),
+        ],
+        Expression::Function { keyword, params, body } => vec![
+            ("kind", Json::String("Function".to_string())),
+            ("keyword", keyword.to_json()),
+            ("params", Json::Array(params.iter().map(|param| param.to_json()).collect())),
+            ("body", Json::Array(body.iter().map(|statement| Json::Number(statement.index() as f64)).collect())),
+        ],
+        Expression::Call {
+            callee,
+            paren,
+            arguments,
+        } => vec![
+            ("kind", Json::String("Call".to_string())),
+            ("callee", id(callee)),
+            ("paren", paren.to_json()),
+            ("arguments", Json::Array(arguments.iter().map(id).collect())),
+        ],
+        Expression::List { bracket, elements } => vec![
+            ("kind", Json::String("List".to_string())),
+            ("bracket", bracket.to_json()),
+            ("elements", Json::Array(elements.iter().map(id).collect())),
+        ],
+        Expression::Map { brace, entries } => vec![
+            ("kind", Json::String("Map".to_string())),
+            ("brace", brace.to_json()),
+            (
+                "entries",
+                Json::Array(
+                    entries
+                        .iter()
+                        .map(|(key, value)| Json::Array(vec![id(key), id(value)]))
+                        .collect(),
+                ),
+            ),
+        ],
+        Expression::Index {
+            object,
+            bracket,
+            index,
+        } => vec![
+            ("kind", Json::String("Index".to_string())),
+            ("object", id(object)),
+            ("bracket", bracket.to_json()),
+            ("index", id(index)),
+        ],
+        Expression::SetIndex {
+            object,
+            bracket,
+            index,
+            value,
+        } => vec![
+            ("kind", Json::String("SetIndex".to_string())),
+            ("object", id(object)),
+            ("bracket", bracket.to_json()),
+            ("index", id(index)),
+            ("value", id(value)),
+        ],
+    };
+    return Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    );
+}
+
+fn statement_json(statement: &Statement) -> Json {
+    let id = id_json;
+    let stmt = |id: &StmtId| Json::Number(id.index() as f64);
+    let optional = |id: &Option<ExprId>| id.as_ref().map_or(Json::Null, id_json);
+    let fields = match statement {
+        Statement::Expression { expression } => vec![
+            ("kind", Json::String("Expression".to_string())),
+            ("expression", id(expression)),
+        ],
+        Statement::Print { keyword, value } => vec![
+            ("kind", Json::String("Print".to_string())),
+            ("keyword", keyword.to_json()),
+            ("value", id(value)),
+        ],
+        Statement::Var { name, initializer } => vec![
+            ("kind", Json::String("Var".to_string())),
+            ("name", name.to_json()),
+            ("initializer", optional(initializer)),
+        ],
+        Statement::Block { brace, statements } => vec![
+            ("kind", Json::String("Block".to_string())),
+            ("brace", brace.to_json()),
+            ("statements", Json::Array(statements.iter().map(stmt).collect())),
+        ],
+        Statement::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        } => vec![
+            ("kind", Json::String("If".to_string())),
+            ("keyword", keyword.to_json()),
+            ("condition", id(condition)),
+            ("then", stmt(then_branch)),
+            ("else", else_branch.as_ref().map_or(Json::Null, stmt)),
+        ],
+        Statement::Function { name, params, body } => vec![
+            ("kind", Json::String("Function".to_string())),
+            ("name", name.to_json()),
+            ("params", Json::Array(params.iter().map(|param| param.to_json()).collect())),
+            ("body", Json::Array(body.iter().map(stmt).collect())),
+        ],
+        Statement::Return { keyword, value } => vec![
+            ("kind", Json::String("Return".to_string())),
+            ("keyword", keyword.to_json()),
+            ("value", optional(value)),
+        ],
+        Statement::While {
+            keyword,
+            condition,
+            body,
+            increment,
+        } => vec![
+            ("kind", Json::String("While".to_string())),
+            ("keyword", keyword.to_json()),
+            ("condition", id(condition)),
+            ("body", stmt(body)),
+            ("increment", optional(increment)),
+        ],
+        Statement::ForEach {
+            keyword,
+            name,
+            iterable,
+            body,
+        } => vec![
+            ("kind", Json::String("ForEach".to_string())),
+            ("keyword", keyword.to_json()),
+            ("name", name.to_json()),
+            ("iterable", id(iterable)),
+            ("body", stmt(body)),
+        ],
+        Statement::Break { keyword } => vec![
+            ("kind", Json::String("Break".to_string())),
+            ("keyword", keyword.to_json()),
+        ],
+        Statement::Continue { keyword } => vec![
+            ("kind", Json::String("Continue".to_string())),
+            ("keyword", keyword.to_json()),
+        ],
+    };
+    return Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    );
+}
+
+fn id_json(id: &ExprId) -> Json {
+    return Json::Number(id.index() as f64);
+}
+
+// The whole arena as flat JSON: nodes in ExprId order and statements in StmtId order,
+// children referenced by index.
+pub fn to_json(ast: &AST) -> String {
+    let nodes = ast.ids().map(|id| node_json(&ast[id])).collect();
+    let statements = ast.statement_ids().map(|id| statement_json(&ast[id])).collect();
+    let roots = ast.roots().iter().map(id_json).collect();
+    let program = ast
+        .program()
+        .iter()
+        .map(|statement| Json::Number(statement.index() as f64))
+        .collect();
+    // [statement, [doc comment tokens]] pairs
+    let docs = ast
+        .documented()
+        .map(|(id, docs)| {
+            let tokens = docs.iter().map(|doc| doc.to_json()).collect();
+            Json::Array(vec![Json::Number(id.index() as f64), Json::Array(tokens)])
+        })
+        .collect();
+    let document = Json::Object(vec![
+        ("format".to_string(), Json::String(JSON_FORMAT.to_string())),
+        ("version".to_string(), Json::Number(FORMAT_VERSION as f64)),
+        ("roots".to_string(), Json::Array(roots)),
+        ("nodes".to_string(), Json::Array(nodes)),
+        ("program".to_string(), Json::Array(program)),
+        ("statements".to_string(), Json::Array(statements)),
+        ("docs".to_string(), Json::Array(docs)),
+    ]);
+    return document.to_string();
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, DecodeError> {
+    return json
+        .get(key)
+        .ok_or_else(|| DecodeError::new(&format!("missing field '{}'", key)));
+}
+
+fn str_field<'a>(json: &'a Json, key: &str) -> Result<&'a str, DecodeError> {
+    return field(json, key)?
+        .as_str()
+        .ok_or_else(|| DecodeError::new(&format!("field '{}' is not a string", key)));
+}
+
+fn usize_field(json: &Json, key: &str) -> Result<usize, DecodeError> {
+    match field(json, key)?.as_f64() {
+        Some(value) if value >= 0.0 && value.fract() == 0.0 => Ok(value as usize),
+        _ => Err(DecodeError::new(&format!("field '{}' is not an index", key))),
+    }
+}
+
+fn token_from_json(json: &Json, keywords: &Keywords) -> Result<Token, DecodeError> {
+    let name = str_field(json, "type")?;
+    let token_type = match name {
+        "Custom" => TokenType::Custom(custom_word(str_field(json, "word")?, keywords)?),
+        name => TokenType::from_name(name)
+            .ok_or_else(|| DecodeError::new(&format!("unknown token type '{}'", name)))?,
+    };
+    let literal = match field(json, "literal")? {
+        Json::Null => None,
+        literal => Some(
+            literal
+                .as_str()
+                .ok_or_else(|| DecodeError::new("field 'literal' is not a string"))?
+                .to_string(),
+        ),
+    };
+    let mut token = Token::new(
+        token_type,
+        str_field(json, "lexeme")?.to_string(),
+        literal,
+        usize_field(json, "line")?,
+    );
+    token.column = usize_field(json, "column")?;
+    token.span = Span::in_file(
+        FileId::from_index(usize_field(json, "file")?),
+        usize_field(json, "start")?,
+        usize_field(json, "end")?,
+    );
+    return Ok(token);
+}
+
+fn literal_from_json(json: &Json, keywords: &Keywords) -> Result<Literal, DecodeError> {
+    let token = token_from_json(field(json, "token")?, keywords)?;
+    match str_field(json, "kind")? {
+        "Number" => Ok(Literal::NUMBER(token)),
+        "String" => Ok(Literal::STRING(token)),
+        "Keyword" => Ok(Literal::KEYWORD(token)),
+        kind => Err(DecodeError::new(&format!("unknown literal kind '{}'", kind))),
+    }
+}
+
+// child ids must point at nodes that come earlier, so a loaded tree cannot have cycles
+fn child(index: usize, before: usize) -> Result<ExprId, DecodeError> {
+    if index >= before {
+        return Err(DecodeError::new(&format!("node {} refers to later node {}", before, index)));
+    }
+    return Ok(ExprId::from_index(index));
+}
+
+// Statements are loaded after every node, so they may refer to any node, but like nodes only
+// to statements that come before them.
+fn expression_of(index: usize, statement: usize, nodes: usize) -> Result<ExprId, DecodeError> {
+    if index >= nodes {
+        return Err(DecodeError::new(&format!("statement {} refers to missing node {}", statement, index)));
+    }
+    return Ok(ExprId::from_index(index));
+}
+
+fn sub_statement(index: usize, before: usize) -> Result<StmtId, DecodeError> {
+    if index >= before {
+        return Err(DecodeError::new(&format!("statement {} refers to later statement {}", before, index)));
+    }
+    return Ok(StmtId::from_index(index));
+}
+
+// A `fun` node refers to its body statements, which are loaded after every node, so the rules
+// above do not rule out a cycle through one. Statements may only reach earlier statements,
+// through the `fun` expressions inside them as much as directly.
+fn check_function_bodies(ast: &AST) -> Result<(), DecodeError> {
+    // per node, one past the latest statement a `fun` in or under it refers to
+    let mut reaches = vec![0; ast.len()];
+    for id in ast.ids() {
+        let mut reach = children(&ast[id]).iter().map(|child| reaches[child.index()]).max().unwrap_or(0);
+        if let Expression::Function { body, .. } = &ast[id] {
+            for statement in body {
+                if statement.index() >= ast.statement_count() {
+                    let message = format!("node {} refers to missing statement {}", id.index(), statement.index());
+                    return Err(DecodeError::new(&message));
+                }
+                reach = reach.max(statement.index() + 1);
+            }
+        }
+        reaches[id.index()] = reach;
+    }
+    for id in ast.statement_ids() {
+        let (expressions, _) = statement_children(&ast[id]);
+        if let Some(reach) = expressions.iter().map(|expression| reaches[expression.index()]).max() {
+            if reach > id.index() {
+                return Err(DecodeError::new(&format!("statement {} refers to later statement {}", id.index(), reach - 1)));
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn index_array(json: &Json, key: &str) -> Result<Vec<usize>, DecodeError> {
+    return field(json, key)?
+        .as_array()
+        .ok_or_else(|| DecodeError::new(&format!("field '{}' is not an array", key)))?
+        .iter()
+        .map(|element| match element.as_f64() {
+            Some(index) if index >= 0.0 && index.fract() == 0.0 => Ok(index as usize),
+            _ => Err(DecodeError::new(&format!("field '{}' holds a non-index", key))),
+        })
+        .collect();
+}
+
+fn statement_from_json(json: &Json, position: usize, nodes: usize, keywords: &Keywords) -> Result<Statement, DecodeError> {
+    let id = |key: &str| expression_of(usize_field(json, key)?, position, nodes);
+    let optional = |key: &str| match field(json, key)? {
+        Json::Null => Ok(None),
+        _ => id(key).map(Some),
+    };
+    let stmt = |key: &str| sub_statement(usize_field(json, key)?, position);
+    let stmts = |key: &str| {
+        return index_array(json, key)?
+            .into_iter()
+            .map(|index| sub_statement(index, position))
+            .collect::<Result<Vec<StmtId>, DecodeError>>();
+    };
+    let token = |key: &str| token_from_json(field(json, key)?, keywords);
+    let statement = match str_field(json, "kind")? {
+        "Expression" => Statement::Expression {
+            expression: id("expression")?,
+        },
+        "Print" => Statement::Print {
+            keyword: token("keyword")?,
+            value: id("value")?,
+        },
+        "Var" => Statement::Var {
+            name: token("name")?,
+            initializer: optional("initializer")?,
+        },
+        "Block" => Statement::Block {
+            brace: token("brace")?,
+            statements: stmts("statements")?,
+        },
+        "If" => Statement::If {
+            keyword: token("keyword")?,
+            condition: id("condition")?,
+            then_branch: stmt("then")?,
+            else_branch: match field(json, "else")? {
+                Json::Null => None,
+                _ => Some(stmt("else")?),
+            },
+        },
+        "Function" => Statement::Function {
+            name: token("name")?,
+            params: field(json, "params")?
+                .as_array()
+                .ok_or_else(|| DecodeError::new("field 'params' is not an array"))?
+                .iter()
+                .map(|param| token_from_json(param, keywords))
+                .collect::<Result<Vec<Token>, DecodeError>>()?,
+            body: stmts("body")?,
+        },
+        "Return" => Statement::Return {
+            keyword: token("keyword")?,
+            value: optional("value")?,
+        },
+        "While" => Statement::While {
+            keyword: token("keyword")?,
+            condition: id("condition")?,
+            body: stmt("body")?,
+            increment: optional("increment")?,
+        },
+        "ForEach" => Statement::ForEach {
+            keyword: token("keyword")?,
+            name: token("name")?,
+            iterable: id("iterable")?,
+            body: stmt("body")?,
+        },
+        "Break" => Statement::Break {
+            keyword: token("keyword")?,
+        },
+        "Continue" => Statement::Continue {
+            keyword: token("keyword")?,
+        },
+        kind => return Err(DecodeError::new(&format!("unknown statement kind '{}'", kind))),
+    };
+    return Ok(statement);
+}
+
+fn node_from_json(json: &Json, position: usize, keywords: &Keywords) -> Result<Expression, DecodeError> {
+    let id = |key: &str| child(usize_field(json, key)?, position);
+    let token = |key: &str| token_from_json(field(json, key)?, keywords);
+    let expression = match str_field(json, "kind")? {
+        "Unary" => Expression::Unary {
+            operator: operator(token("operator")?, "unary", UNARY_OPERATORS)?,
+            value: id("value")?,
+        },
+        "Binary" => Expression::Binary {
+            left: id("left")?,
+            operator: operator(token("operator")?, "binary", BINARY_OPERATORS)?,
+            right: id("right")?,
+        },
+        "Logical" => Expression::Logical {
+            left: id("left")?,
+            operator: operator(token("operator")?, "logical", LOGICAL_OPERATORS)?,
+            right: id("right")?,
+        },
+        "Grouping" => Expression::Grouping {
+            interior: id("interior")?,
+        },
+        "Conditional" => Expression::Conditional {
+            condition: id("condition")?,
+            question: token("question")?,
+            then_branch: id("then")?,
+            else_branch: id("else")?,
+        },
+        "Literal" => Expression::Literal(literal_from_json(field(json, "literal")?, keywords)?),
+        "Variable" => Expression::Variable {
+            name: token("name")?,
+        },
+        "Assign" => Expression::Assign {
+            name: token("name")?,
+            value: id("value")?,
+        },
+        "Lambda" => Expression::Lambda {
+            params: field(json, "params")?
+                .as_array()
+                .ok_or_else(|| DecodeError::new("field 'params' is not an array"))?
+                .iter()
+                .map(|param| token_from_json(param, keywords))
+                .collect::<Result<Vec<Token>, DecodeError>>()?,
+            arrow: token("arrow")?,
+            body: id("body")?,
+        },
+        // statements come after every node, so `check_function_bodies` checks these
+        "Function" => Expression::Function {
+            keyword: token("keyword")?,
+            params: field(json, "params")?
+                .as_array()
+                .ok_or_else(|| DecodeError::new("field 'params' is not an array"))?
+                .iter()
+                .map(|param| token_from_json(param, keywords))
+                .collect::<Result<Vec<Token>, DecodeError>>()?,
+            body: index_array(json, "body")?.into_iter().map(StmtId::from_index).collect(),
+        },
+        "Call" => Expression::Call {
+            callee: id("callee")?,
+            paren: token("paren")?,
+            arguments: index_array(json, "arguments")?
+                .into_iter()
+                .map(|index| child(index, position))
+                .collect::<Result<Vec<ExprId>, DecodeError>>()?,
+        },
+        "List" => Expression::List {
+            bracket: token("bracket")?,
+            elements: field(json, "elements")?
+                .as_array()
+                .ok_or_else(|| DecodeError::new("field 'elements' is not an array"))?
+                .iter()
+                .map(|element| match element.as_f64() {
+                    Some(index) if index >= 0.0 => child(index as usize, position),
+                    _ => Err(DecodeError::new("field 'elements' holds a non-index")),
+                })
+                .collect::<Result<Vec<ExprId>, DecodeError>>()?,
+        },
+        "Map" => Expression::Map {
+            brace: token("brace")?,
+            entries: field(json, "entries")?
+                .as_array()
+                .ok_or_else(|| DecodeError::new("field 'entries' is not an array"))?
+                .iter()
+                .map(|entry| match entry.as_array() {
+                    Some([Json::Number(key), Json::Number(value)]) if *key >= 0.0 && *value >= 0.0 => {
+                        Ok((child(*key as usize, position)?, child(*value as usize, position)?))
+                    }
+                    _ => Err(DecodeError::new("field 'entries' holds something other than [key, value]")),
+                })
+                .collect::<Result<Vec<(ExprId, ExprId)>, DecodeError>>()?,
+        },
+        "Index" => Expression::Index {
+            object: id("object")?,
+            bracket: token("bracket")?,
+            index: id("index")?,
+        },
+        "SetIndex" => Expression::SetIndex {
+            object: id("object")?,
+            bracket: token("bracket")?,
+            index: id("index")?,
+            value: id("value")?,
+        },
+        kind => return Err(DecodeError::new(&format!("unknown node kind '{}'", kind))),
+    };
+    return Ok(expression);
+}
+
+pub fn from_json(text: &str) -> Result<AST, DecodeError> {
+    return from_json_with_keywords(text, &Keywords::new());
+}
+
+// for trees scanned with a dialect that adds custom keywords
+pub fn from_json_with_keywords(text: &str, keywords: &Keywords) -> Result<AST, DecodeError> {
+    let document = Json::parse(text).map_err(|message| DecodeError::new(&message))?;
+    if str_field(&document, "format")? != JSON_FORMAT {
+        return Err(DecodeError::new("not a defunkter AST"));
+    }
+    let version = usize_field(&document, "version")?;
+    if version != FORMAT_VERSION as usize {
+        return Err(DecodeError::new(&format!("unsupported version {}", version)));
+    }
+    let nodes = field(&document, "nodes")?
+        .as_array()
+        .ok_or_else(|| DecodeError::new("field 'nodes' is not an array"))?;
+    let mut ast = AST::new();
+    for (position, node) in nodes.iter().enumerate() {
+        ast.add(node_from_json(node, position, keywords)?);
+    }
+    let roots = field(&document, "roots")?
+        .as_array()
+        .ok_or_else(|| DecodeError::new("field 'roots' is not an array"))?;
+    for root in roots {
+        match root.as_f64() {
+            Some(index) if index >= 0.0 && (index as usize) < ast.len() => {
+                ast.add_root(ExprId::from_index(index as usize))
+            }
+            _ => return Err(DecodeError::new("invalid root")),
+        }
+    }
+    let statements = field(&document, "statements")?
+        .as_array()
+        .ok_or_else(|| DecodeError::new("field 'statements' is not an array"))?;
+    for (position, statement) in statements.iter().enumerate() {
+        ast.add_statement(statement_from_json(statement, position, ast.len(), keywords)?);
+    }
+    for statement in index_array(&document, "program")? {
+        if statement >= ast.statement_count() {
+            return Err(DecodeError::new("invalid program statement"));
+        }
+        ast.add_to_program(StmtId::from_index(statement));
+    }
+    let docs = field(&document, "docs")?
+        .as_array()
+        .ok_or_else(|| DecodeError::new("field 'docs' is not an array"))?;
+    for entry in docs {
+        let (index, tokens) = match entry.as_array() {
+            Some([Json::Number(index), Json::Array(tokens)]) if *index >= 0.0 => (*index as usize, tokens),
+            _ => return Err(DecodeError::new("field 'docs' holds something other than [statement, docs]")),
+        };
+        let id = documented_statement(&ast, index)?;
+        let tokens = tokens
+            .iter()
+            .map(|token| token_from_json(token, keywords))
+            .collect::<Result<Vec<Token>, DecodeError>>()?;
+        ast.add_docs(id, tokens);
+    }
+    check_function_bodies(&ast)?;
+    return Ok(ast);
+}
+
+// only declarations carry docs
+fn documented_statement(ast: &AST, index: usize) -> Result<StmtId, DecodeError> {
+    if index >= ast.statement_count() {
+        return Err(DecodeError::new("docs for a missing statement"));
+    }
+    let id = StmtId::from_index(index);
+    if !matches!(ast[id], Statement::Var { .. } | Statement::Function { .. }) {
+        return Err(DecodeError::new(&format!("statement {} is not a declaration and cannot have docs", index)));
+    }
+    return Ok(id);
+}
+
+// Compact binary form: magic, version, then the same flat node list as the JSON form.
+// Numbers are unsigned LEB128, strings are a length followed by UTF-8 bytes.
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn number(&mut self, mut value: usize) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, value: &str) {
+        self.number(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn token(&mut self, token: &Token) {
+        match (&token.token_type, token.token_type.code()) {
+            (TokenType::Custom(word), _) => {
+                self.bytes.push(CUSTOM_CODE);
+                self.string(word);
+            }
+            (_, Some(code)) => self.bytes.push(code),
+            (token_type, None) => unreachable!("{:?} has no code", token_type),
+        }
+        self.string(&token.lexeme);
+        match &token.literal {
+            Some(literal) => {
+                self.bytes.push(1);
+                self.string(literal);
+            }
+            None => self.bytes.push(0),
+        }
+        self.number(token.line);
+        self.number(token.column);
+        self.number(token.span.file.index());
+        self.number(token.span.start);
+        self.number(token.span.end);
+    }
+
+    fn node(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Unary { operator, value } => {
+                self.bytes.push(0);
+                self.token(operator);
+                self.number(value.index());
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.bytes.push(1);
+                self.number(left.index());
+                self.token(operator);
+                self.number(right.index());
+            }
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.bytes.push(12);
+                self.number(left.index());
+                self.token(operator);
+                self.number(right.index());
+            }
+            Expression::Grouping { interior } => {
+                self.bytes.push(2);
+                self.number(interior.index());
+            }
+            Expression::Literal(literal) => {
+                let (kind, token) = match literal {
+                    Literal::NUMBER(token) => (0, token),
+                    Literal::STRING(token) => (1, token),
+                    Literal::KEYWORD(token) => (2, token),
+                };
+                self.bytes.push(3);
+                self.bytes.push(kind);
+                self.token(token);
+            }
+            Expression::Variable { name } => {
+                self.bytes.push(4);
+                self.token(name);
+            }
+            Expression::Assign { name, value } => {
+                self.bytes.push(11);
+                self.token(name);
+                self.number(value.index());
+            }
+            Expression::Conditional {
+                condition,
+                question,
+                then_branch,
+                else_branch,
+            } => {
+                self.bytes.push(5);
+                self.number(condition.index());
+                self.token(question);
+                self.number(then_branch.index());
+                self.number(else_branch.index());
+            }
+            Expression::Lambda { params, arrow, body } => {
+                self.bytes.push(6);
+                self.number(params.len());
+                for param in params {
+                    self.token(param);
+                }
+                self.token(arrow);
+                self.number(body.index());
+            }
+            Expression::Function { keyword, params, body } => {
+                self.bytes.push(13);
+                self.token(keyword);
+                self.number(params.len());
+                for param in params {
+                    self.token(param);
+                }
+                self.number(body.len());
+                for statement in body {
+                    self.number(statement.index());
+                }
+            }
+            Expression::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.bytes.push(14);
+                self.number(callee.index());
+                self.token(paren);
+                self.number(arguments.len());
+                for argument in arguments {
+                    self.number(argument.index());
+                }
+            }
+            Expression::List { bracket, elements } => {
+                self.bytes.push(7);
+                self.token(bracket);
+                self.number(elements.len());
+                for element in elements {
+                    self.number(element.index());
+                }
+            }
+            Expression::Map { brace, entries } => {
+                self.bytes.push(10);
+                self.token(brace);
+                self.number(entries.len());
+                for (key, value) in entries {
+                    self.number(key.index());
+                    self.number(value.index());
+                }
+            }
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.bytes.push(8);
+                self.number(object.index());
+                self.token(bracket);
+                self.number(index.index());
+            }
+            Expression::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.bytes.push(9);
+                self.number(object.index());
+                self.token(bracket);
+                self.number(index.index());
+                self.number(value.index());
+            }
+        }
+    }
+}
+
+impl Writer {
+    fn optional(&mut self, id: Option<usize>) {
+        match id {
+            Some(id) => {
+                self.bytes.push(1);
+                self.number(id);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+
+    fn statements(&mut self, statements: &[StmtId]) {
+        self.number(statements.len());
+        for statement in statements {
+            self.number(statement.index());
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression { expression } => {
+                self.bytes.push(0);
+                self.number(expression.index());
+            }
+            Statement::Print { keyword, value } => {
+                self.bytes.push(1);
+                self.token(keyword);
+                self.number(value.index());
+            }
+            Statement::Var { name, initializer } => {
+                self.bytes.push(2);
+                self.token(name);
+                self.optional(initializer.map(ExprId::index));
+            }
+            Statement::Block { brace, statements } => {
+                self.bytes.push(3);
+                self.token(brace);
+                self.statements(statements);
+            }
+            Statement::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.bytes.push(4);
+                self.token(keyword);
+                self.number(condition.index());
+                self.number(then_branch.index());
+                self.optional(else_branch.map(StmtId::index));
+            }
+            Statement::Function { name, params, body } => {
+                self.bytes.push(5);
+                self.token(name);
+                self.number(params.len());
+                for param in params {
+                    self.token(param);
+                }
+                self.statements(body);
+            }
+            Statement::Return { keyword, value } => {
+                self.bytes.push(6);
+                self.token(keyword);
+                self.optional(value.map(ExprId::index));
+            }
+            Statement::While {
+                keyword,
+                condition,
+                body,
+                increment,
+            } => {
+                self.bytes.push(7);
+                self.token(keyword);
+                self.number(condition.index());
+                self.number(body.index());
+                self.optional(increment.map(ExprId::index));
+            }
+            Statement::Break { keyword } => {
+                self.bytes.push(8);
+                self.token(keyword);
+            }
+            Statement::Continue { keyword } => {
+                self.bytes.push(9);
+                self.token(keyword);
+            }
+            Statement::ForEach {
+                keyword,
+                name,
+                iterable,
+                body,
+            } => {
+                self.bytes.push(10);
+                self.token(keyword);
+                self.token(name);
+                self.number(iterable.index());
+                self.number(body.index());
+            }
+        }
+    }
+}
+
+// after the nodes come the statements, then the program, then the doc comments
+pub fn to_binary(ast: &AST) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(BINARY_MAGIC);
+    writer.number(FORMAT_VERSION as usize);
+    writer.number(ast.roots().len());
+    for root in ast.roots() {
+        writer.number(root.index());
+    }
+    writer.number(ast.len());
+    for id in ast.ids() {
+        writer.node(&ast[id]);
+    }
+    writer.number(ast.statement_count());
+    for id in ast.statement_ids() {
+        writer.statement(&ast[id]);
+    }
+    writer.statements(ast.program());
+    writer.number(ast.documented().count());
+    for (id, docs) in ast.documented() {
+        writer.number(id.index());
+        writer.number(docs.len());
+        for doc in docs {
+            writer.token(doc);
+        }
+    }
+    return writer.bytes;
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    current: usize,
+    keywords: &'a Keywords,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.current)
+            .ok_or_else(|| DecodeError::new("unexpected end of input"))?;
+        self.current += 1;
+        return Ok(byte);
+    }
+
+    fn number(&mut self) -> Result<usize, DecodeError> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= usize::BITS {
+                return Err(DecodeError::new("number too large"));
+            }
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.number()?;
+        let end = self
+            .current
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| DecodeError::new("unexpected end of input"))?;
+        let value = std::str::from_utf8(&self.bytes[self.current..end])
+            .map_err(|_| DecodeError::new("invalid UTF-8 in string"))?;
+        self.current = end;
+        return Ok(value.to_string());
+    }
+
+    fn token(&mut self) -> Result<Token, DecodeError> {
+        let token_type = match self.byte()? {
+            CUSTOM_CODE => TokenType::Custom(custom_word(&self.string()?, self.keywords)?),
+            code => TokenType::from_code(code)
+                .ok_or_else(|| DecodeError::new(&format!("unknown token type code {}", code)))?,
+        };
+        let lexeme = self.string()?;
+        let literal = match self.byte()? {
+            0 => None,
+            _ => Some(self.string()?),
+        };
+        let mut token = Token::new(token_type, lexeme, literal, self.number()?);
+        token.column = self.number()?;
+        let file = FileId::from_index(self.number()?);
+        token.span = Span::in_file(file, self.number()?, self.number()?);
+        return Ok(token);
+    }
+
+    fn node(&mut self, position: usize) -> Result<Expression, DecodeError> {
+        let expression = match self.byte()? {
+            0 => Expression::Unary {
+                operator: operator(self.token()?, "unary", UNARY_OPERATORS)?,
+                value: child(self.number()?, position)?,
+            },
+            1 => Expression::Binary {
+                left: child(self.number()?, position)?,
+                operator: operator(self.token()?, "binary", BINARY_OPERATORS)?,
+                right: child(self.number()?, position)?,
+            },
+            12 => Expression::Logical {
+                left: child(self.number()?, position)?,
+                operator: operator(self.token()?, "logical", LOGICAL_OPERATORS)?,
+                right: child(self.number()?, position)?,
+            },
+            2 => Expression::Grouping {
+                interior: child(self.number()?, position)?,
+            },
+            3 => {
+                let kind = self.byte()?;
+                let token = self.token()?;
+                match kind {
+                    0 => Expression::Literal(Literal::NUMBER(token)),
+                    1 => Expression::Literal(Literal::STRING(token)),
+                    2 => Expression::Literal(Literal::KEYWORD(token)),
+                    kind => return Err(DecodeError::new(&format!("unknown literal kind {}", kind))),
+                }
+            }
+            4 => Expression::Variable { name: self.token()? },
+            11 => Expression::Assign {
+                name: self.token()?,
+                value: child(self.number()?, position)?,
+            },
+            5 => Expression::Conditional {
+                condition: child(self.number()?, position)?,
+                question: self.token()?,
+                then_branch: child(self.number()?, position)?,
+                else_branch: child(self.number()?, position)?,
+            },
+            6 => {
+                let mut params = Vec::new();
+                for _ in 0..self.number()? {
+                    params.push(self.token()?);
+                }
+                Expression::Lambda {
+                    params,
+                    arrow: self.token()?,
+                    body: child(self.number()?, position)?,
+                }
+            }
+            13 => {
+                let keyword = self.token()?;
+                let mut params = Vec::new();
+                for _ in 0..self.number()? {
+                    params.push(self.token()?);
+                }
+                let mut body = Vec::new();
+                for _ in 0..self.number()? {
+                    body.push(StmtId::from_index(self.number()?));
+                }
+                Expression::Function { keyword, params, body }
+            }
+            14 => {
+                let callee = child(self.number()?, position)?;
+                let paren = self.token()?;
+                let mut arguments = Vec::new();
+                for _ in 0..self.number()? {
+                    arguments.push(child(self.number()?, position)?);
+                }
+                Expression::Call { callee, paren, arguments }
+            }
+            7 => {
+                let bracket = self.token()?;
+                let mut elements = Vec::new();
+                for _ in 0..self.number()? {
+                    elements.push(child(self.number()?, position)?);
+                }
+                Expression::List { bracket, elements }
+            }
+            10 => {
+                let brace = self.token()?;
+                let mut entries = Vec::new();
+                for _ in 0..self.number()? {
+                    let key = child(self.number()?, position)?;
+                    entries.push((key, child(self.number()?, position)?));
+                }
+                Expression::Map { brace, entries }
+            }
+            8 => Expression::Index {
+                object: child(self.number()?, position)?,
+                bracket: self.token()?,
+                index: child(self.number()?, position)?,
+            },
+            9 => Expression::SetIndex {
+                object: child(self.number()?, position)?,
+                bracket: self.token()?,
+                index: child(self.number()?, position)?,
+                value: child(self.number()?, position)?,
+            },
+            kind => return Err(DecodeError::new(&format!("unknown node kind {}", kind))),
+        };
+        return Ok(expression);
+    }
+}
+
+impl Reader<'_> {
+    fn optional(&mut self) -> Result<Option<usize>, DecodeError> {
+        match self.byte()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.number()?)),
+        }
+    }
+
+    fn statements(&mut self, position: usize) -> Result<Vec<StmtId>, DecodeError> {
+        let mut statements = Vec::new();
+        for _ in 0..self.number()? {
+            statements.push(sub_statement(self.number()?, position)?);
+        }
+        return Ok(statements);
+    }
+
+    fn statement(&mut self, position: usize, nodes: usize) -> Result<Statement, DecodeError> {
+        let statement = match self.byte()? {
+            0 => Statement::Expression {
+                expression: expression_of(self.number()?, position, nodes)?,
+            },
+            1 => Statement::Print {
+                keyword: self.token()?,
+                value: expression_of(self.number()?, position, nodes)?,
+            },
+            2 => Statement::Var {
+                name: self.token()?,
+                initializer: match self.optional()? {
+                    Some(index) => Some(expression_of(index, position, nodes)?),
+                    None => None,
+                },
+            },
+            3 => Statement::Block {
+                brace: self.token()?,
+                statements: self.statements(position)?,
+            },
+            4 => Statement::If {
+                keyword: self.token()?,
+                condition: expression_of(self.number()?, position, nodes)?,
+                then_branch: sub_statement(self.number()?, position)?,
+                else_branch: match self.optional()? {
+                    Some(index) => Some(sub_statement(index, position)?),
+                    None => None,
+                },
+            },
+            5 => {
+                let name = self.token()?;
+                let mut params = Vec::new();
+                for _ in 0..self.number()? {
+                    params.push(self.token()?);
+                }
+                Statement::Function {
+                    name,
+                    params,
+                    body: self.statements(position)?,
+                }
+            }
+            6 => Statement::Return {
+                keyword: self.token()?,
+                value: match self.optional()? {
+                    Some(index) => Some(expression_of(index, position, nodes)?),
+                    None => None,
+                },
+            },
+            7 => Statement::While {
+                keyword: self.token()?,
+                condition: expression_of(self.number()?, position, nodes)?,
+                body: sub_statement(self.number()?, position)?,
+                increment: match self.optional()? {
+                    Some(index) => Some(expression_of(index, position, nodes)?),
+                    None => None,
+                },
+            },
+            8 => Statement::Break { keyword: self.token()? },
+            9 => Statement::Continue { keyword: self.token()? },
+            10 => Statement::ForEach {
+                keyword: self.token()?,
+                name: self.token()?,
+                iterable: expression_of(self.number()?, position, nodes)?,
+                body: sub_statement(self.number()?, position)?,
+            },
+            kind => return Err(DecodeError::new(&format!("unknown statement kind {}", kind))),
+        };
+        return Ok(statement);
+    }
+}
+
+pub fn from_binary(bytes: &[u8]) -> Result<AST, DecodeError> {
+    return from_binary_with_keywords(bytes, &Keywords::new());
+}
+
+pub fn from_binary_with_keywords(bytes: &[u8], keywords: &Keywords) -> Result<AST, DecodeError> {
+    if !bytes.starts_with(BINARY_MAGIC) {
+        return Err(DecodeError::new("not a defunkter AST"));
+    }
+    let mut reader = Reader {
+        bytes,
+        current: BINARY_MAGIC.len(),
+        keywords,
+    };
+    let version = reader.number()?;
+    if version != FORMAT_VERSION as usize {
+        return Err(DecodeError::new(&format!("unsupported version {}", version)));
+    }
+    let mut roots = Vec::new();
+    for _ in 0..reader.number()? {
+        roots.push(reader.number()?);
+    }
+    let mut ast = AST::new();
+    for position in 0..reader.number()? {
+        ast.add(reader.node(position)?);
+    }
+    for root in roots {
+        if root >= ast.len() {
+            return Err(DecodeError::new("invalid root"));
+        }
+        ast.add_root(ExprId::from_index(root));
+    }
+    for position in 0..reader.number()? {
+        let statement = reader.statement(position, ast.len())?;
+        ast.add_statement(statement);
+    }
+    for _ in 0..reader.number()? {
+        let statement = reader.number()?;
+        if statement >= ast.statement_count() {
+            return Err(DecodeError::new("invalid program statement"));
+        }
+        ast.add_to_program(StmtId::from_index(statement));
+    }
+    for _ in 0..reader.number()? {
+        let id = documented_statement(&ast, reader.number()?)?;
+        let mut docs = Vec::new();
+        for _ in 0..reader.number()? {
+            docs.push(reader.token()?);
+        }
+        ast.add_docs(id, docs);
+    }
+    if reader.current != bytes.len() {
+        return Err(DecodeError::new("unexpected trailing bytes"));
+    }
+    check_function_bodies(&ast)?;
+    return Ok(ast);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, tokens::Keywords, Scanner};
+
+    fn parse(input: &str) -> AST {
+        let mut parser = Parser::new(Scanner::new(input));
+        parser.parse();
+        return parser.into_ast();
+    }
+
+    fn spans(ast: &AST) -> Vec<(usize, usize, Span)> {
+        let mut spans = Vec::new();
+        for id in ast.ids() {
+            let token = match &ast[id] {
+                Expression::Unary { operator, .. } | Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => operator,
+                Expression::Conditional { question, .. } => question,
+                Expression::Lambda { arrow, .. } => arrow,
+                Expression::Function { keyword, .. } => keyword,
+                Expression::Call { paren, .. } => paren,
+                Expression::Map { brace, .. } => brace,
+                Expression::List { bracket, .. } | Expression::Index { bracket, .. } | Expression::SetIndex { bracket, .. } => bracket,
+                Expression::Literal(Literal::NUMBER(token))
+                | Expression::Literal(Literal::STRING(token))
+                | Expression::Literal(Literal::KEYWORD(token)) => token,
+                Expression::Variable { name } | Expression::Assign { name, .. } => name,
+                Expression::Grouping { .. } => continue,
+            };
+            spans.push((token.line, token.column, token.span));
+        }
+        return spans;
+    }
+
+    const PROGRAM: &str = "-(1.5 + \"two\\n\") * !true == nil >= x / 3 ? a, b : c ? (p, q) => p : () => 0, xs[[1, 2][0]] = [], {\"a\": 1, 2: {}}, y = z = 1 or a and !b";
+
+    #[test]
+    fn test_json_round_trip() {
+        let ast = parse(PROGRAM);
+        let loaded = from_json(&to_json(&ast)).unwrap();
+        assert_eq!(loaded, ast);
+        assert_eq!(spans(&loaded), spans(&ast));
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let ast = parse(PROGRAM);
+        let bytes = to_binary(&ast);
+        assert!(bytes.len() < to_json(&ast).len() / 4);
+        let loaded = from_binary(&bytes).unwrap();
+        assert_eq!(loaded, ast);
+        assert_eq!(spans(&loaded), spans(&ast));
+    }
+
+    const STATEMENTS: &str = "var x = 1; var y; fun f(a, b) { if (a) return b; else { print a; } return; } x = y = [x];\
+                              var g = fun (a) { return f(a, fun () { return a; }); }; g(1)(2, 3);\
+                              for (var i = 0; i < 3; i = i + 1) { if (i) continue; while (true) break; }\
+                              for (var x : [1, 2]) print x;\
+                              /// the answer\n/** and how\n it is found */ fun answer() { /// inside\n var n = 42; return n; }";
+
+    fn parse_program(input: &str) -> AST {
+        let mut parser = Parser::new(Scanner::new(input));
+        parser.parse_program();
+        return parser.into_ast();
+    }
+
+    #[test]
+    fn test_program_round_trip() {
+        let ast = parse_program(STATEMENTS);
+        assert_eq!(ast.program().len(), 9);
+        assert_eq!(ast.documented().count(), 2);
+        assert_eq!(from_json(&to_json(&ast)).unwrap(), ast);
+        assert_eq!(from_binary(&to_binary(&ast)).unwrap(), ast);
+    }
+
+    #[test]
+    fn test_custom_tokens_round_trip() {
+        let mut ast = AST::new();
+        let keywords = Keywords::new().with_keyword("unless", TokenType::Custom("unless"));
+        let token = Scanner::new("unless").with_keywords(keywords.clone()).next().unwrap();
+        let name = ast.add(Expression::Variable { name: token });
+        ast.add_root(name);
+
+        assert_eq!(from_json_with_keywords(&to_json(&ast), &keywords).unwrap(), ast);
+        assert_eq!(from_binary_with_keywords(&to_binary(&ast), &keywords).unwrap(), ast);
+
+        // without the dialect the word means nothing
+        let unknown = Err(DecodeError::new("unknown dialect keyword 'unless'"));
+        assert_eq!(from_json(&to_json(&ast)), unknown);
+        assert_eq!(from_binary(&to_binary(&ast)), unknown);
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let ast = parse("1");
+        let current = format!("\"version\":{}", FORMAT_VERSION);
+        for version in [1, FORMAT_VERSION + 1] {
+            let json = to_json(&ast).replace(&current, &format!("\"version\":{}", version));
+            let expected = Err(DecodeError::new(&format!("unsupported version {}", version)));
+            assert_eq!(from_json(&json), expected);
+
+            let mut bytes = to_binary(&ast);
+            bytes[4] = version as u8;
+            assert_eq!(from_binary(&bytes), expected);
+        }
+        assert!(from_binary(b"LOX").is_err());
+        assert!(from_binary(&to_binary(&ast)[..10]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_forward_references() {
+        let json = format!(
+            "{{\"format\":\"defunkter-ast\",\"version\":{},\"roots\":[0],\"nodes\":[{{\"kind\":\"Grouping\",\"interior\":0}}]}}",
+            FORMAT_VERSION
+        );
+        assert_eq!(from_json(&json), Err(DecodeError::new("node 0 refers to later node 0")));
+
+        let json = format!(
+            "{{\"format\":\"defunkter-ast\",\"version\":{},\"roots\":[],\"nodes\":[],\"program\":[0],\
+             \"statements\":[{{\"kind\":\"Block\",\"brace\":{},\"statements\":[0]}}]}}",
+            FORMAT_VERSION,
+            Token::from(TokenType::LeftBrace).to_json()
+        );
+        assert_eq!(from_json(&json), Err(DecodeError::new("statement 0 refers to later statement 0")));
+
+        // `fun () { f; }` as the statement `f;` itself
+        let json = format!(
+            "{{\"format\":\"defunkter-ast\",\"version\":{},\"roots\":[],\"nodes\":[\
+             {{\"kind\":\"Function\",\"keyword\":{},\"params\":[],\"body\":[0]}}],\"program\":[0],\
+             \"statements\":[{{\"kind\":\"Expression\",\"expression\":0}}],\"docs\":[]}}",
+            FORMAT_VERSION,
+            Token::from(TokenType::Fun).to_json()
+        );
+        assert_eq!(from_json(&json), Err(DecodeError::new("statement 0 refers to later statement 0")));
+    }
+
+    #[test]
+    fn test_rejects_operators_the_parser_never_builds() {
+        let ast = parse("1 + 2");
+        let json = to_json(&ast);
+        let plus = "\"type\":\"Plus\",\"lexeme\":\"+\"";
+        assert!(json.contains(plus));
+        let tampered = json.replace(plus, "\"type\":\"Dot\",\"lexeme\":\".\"");
+        let expected = Err(DecodeError::new("'.' is not a binary operator"));
+        assert_eq!(from_json(&tampered), expected);
+
+        // the same swap in the binary format, where a token starts with its type code and its
+        // lexeme's length
+        let mut bytes = to_binary(&ast);
+        let plus = [TokenType::Plus.code().unwrap(), 1, b'+'];
+        let at = bytes.windows(3).position(|window| window == plus).unwrap();
+        bytes[at..at + 3].copy_from_slice(&[TokenType::Dot.code().unwrap(), 1, b'.']);
+        assert_eq!(from_binary(&bytes), expected);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::tokens::Span;
+use diagnostics::Files;
+pub use diagnostics::FileId;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct SourceFile {
+    pub path: String,
+    pub contents: String,
+    // byte offset where each line begins
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(path: &str, contents: String) -> Self {
+        // same rule as the scanner: \n, \r\n and a lone \r each end a line
+        let bytes = contents.as_bytes();
+        let line_starts = std::iter::once(0)
+            .chain(bytes.iter().enumerate().filter_map(|(index, byte)| match byte {
+                b'\n' => Some(index + 1),
+                b'\r' if bytes.get(index + 1) != Some(&b'\n') => Some(index + 1),
+                _ => None,
+            }))
+            .collect();
+        Self {
+            path: path.to_string(),
+            contents,
+            line_starts,
+        }
+    }
+
+    // 1-based line and column (in characters) of a byte offset
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let end = offset.min(self.contents.len());
+        let column = self.contents[line_start..end].chars().count() + 1;
+        return (line, column);
+    }
+
+    // text of a 1-based line, without its line ending
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.contents.len());
+        return self.contents[start..end].trim_end_matches(['\n', '\r']);
+    }
+}
+
+// Every file the interpreter has seen, so a span can always be traced back to a path.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+pub type SharedSourceMap = Rc<RefCell<SourceMap>>;
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shared() -> SharedSourceMap {
+        return Rc::new(RefCell::new(Self::new()));
+    }
+
+    pub fn add(&mut self, path: &str, contents: &str) -> FileId {
+        let id = FileId::from_index(self.files.len());
+        self.files.push(SourceFile::new(path, contents.to_string()));
+        return id;
+    }
+
+    pub fn load(&mut self, path: &str) -> std::io::Result<FileId> {
+        let contents = std::fs::read_to_string(path)?;
+        return Ok(self.add(path, &contents));
+    }
+
+    // Reads in the contents of a file registered without them, e.g. one that was scanned as a
+    // stream. Only worth doing once there is a diagnostic to show.
+    pub fn read_contents(&mut self, id: FileId) -> std::io::Result<()> {
+        let contents = std::fs::read_to_string(self.path(id))?;
+        self.update(id, &contents);
+        return Ok(());
+    }
+
+    // replace the contents of a file that changed, e.g. after an editor edit
+    pub fn update(&mut self, id: FileId, contents: &str) {
+        let path = self.files[id.index()].path.clone();
+        self.files[id.index()] = SourceFile::new(&path, contents.to_string());
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        return &self.files[id.index()];
+    }
+
+    pub fn path(&self, id: FileId) -> &str {
+        return &self.file(id).path;
+    }
+
+    pub fn location(&self, span: Span) -> Location {
+        let file = self.file(span.file);
+        let (line, column) = file.line_column(span.start);
+        return Location::new(&file.path, line, column);
+    }
+}
+
+impl Files for SourceMap {
+    fn path(&self, file: FileId) -> &str {
+        return &self.file(file).path;
+    }
+
+    fn line_column(&self, file: FileId, offset: usize) -> (usize, usize) {
+        return self.file(file).line_column(offset);
+    }
+
+    fn line_text(&self, file: FileId, line: usize) -> &str {
+        return self.file(file).line_text(line);
+    }
+}
+
+// A point in a file as shown to users: `path:line:column`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn new(path: &str, line: usize, column: usize) -> Self {
+        Self {
+            path: path.to_string(),
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let mut source_map = SourceMap::new();
+        source_map.add("a.lox", "1 + 2");
+        let file = source_map.add("lib/b.lox", "1 +\n  héllo * 2\n");
+
+        let location = source_map.location(Span::in_file(file, 13, 14));
+        assert_eq!(location.to_string(), "lib/b.lox:2:9");
+        assert_eq!(source_map.file(file).line_text(2), "  héllo * 2");
+        assert_eq!(source_map.path(FileId::default()), "a.lox");
+    }
+
+    #[test]
+    fn test_windows_lines() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add("win.lox", "1 +\r\n2\r3");
+
+        assert_eq!(source_map.location(Span::in_file(file, 5, 6)).to_string(), "win.lox:2:1");
+        assert_eq!(source_map.location(Span::in_file(file, 7, 8)).to_string(), "win.lox:3:1");
+        assert_eq!(source_map.file(file).line_text(1), "1 +");
+    }
+}
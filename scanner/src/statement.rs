@@ -0,0 +1,76 @@
+use crate::expression::ExprId;
+use crate::tokens::Token;
+
+// Index of a statement in its AST, the statement counterpart of ExprId.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+impl StmtId {
+    pub(crate) fn from_index(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub fn index(self) -> usize {
+        return self.0 as usize;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    // `expression;`, evaluated for its effects
+    Expression {
+        expression: ExprId,
+    },
+    Print {
+        keyword: Token,
+        value: ExprId,
+    },
+    // `var name = initializer;`; without an initializer the variable starts as nil
+    Var {
+        name: Token,
+        initializer: Option<ExprId>,
+    },
+    // `{ ... }`; `brace` is the `{`
+    Block {
+        brace: Token,
+        statements: Vec<StmtId>,
+    },
+    If {
+        keyword: Token,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    },
+    // `fun name(params) { body }`
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        body: Vec<StmtId>,
+    },
+    Return {
+        keyword: Token,
+        value: Option<ExprId>,
+    },
+    // `while (condition) body`. A `for` loop becomes one of these, keeping its `for` keyword,
+    // inside a block holding its initializer; its increment runs after every pass through the
+    // body, `continue` included.
+    While {
+        keyword: Token,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    },
+    // `for (var name : iterable) body`, binding each element in turn in a scope of its own
+    ForEach {
+        keyword: Token,
+        name: Token,
+        iterable: ExprId,
+        body: StmtId,
+    },
+    Break {
+        keyword: Token,
+    },
+    Continue {
+        keyword: Token,
+    },
+}
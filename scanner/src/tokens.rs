@@ -1,11 +1,17 @@
-use std::collections::HashMap;
+use crate::interner::Symbol;
+use crate::source_map::FileId;
+pub use diagnostics::Span;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<String>,
     pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    // set for identifiers and string literals
+    pub symbol: Option<Symbol>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -15,6 +21,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -22,6 +30,9 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
+    Percent,
 
     // one or two character tokens.
     Bang,
@@ -32,15 +43,23 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    Arrow,
+    StarStar,
+    TildeSlash,
 
     // literals.
     Identifier,
     String,
     Number,
 
+    // comments kept for tooling.
+    DocComment,
+
     // keywords.
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -56,42 +75,185 @@ pub enum TokenType {
     Var,
     While,
 
+    // dialect keywords.
+    Custom(&'static str),
+    Reserved,
+
     Eof,
 }
 
+// Every token type except Custom. Only ever append: the position is a token type's code in
+// serialized trees.
+pub const TOKEN_TYPES: [TokenType; 51] = [
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::Comma,
+    TokenType::Dot,
+    TokenType::Minus,
+    TokenType::Plus,
+    TokenType::Semicolon,
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::Bang,
+    TokenType::BangEqual,
+    TokenType::Equal,
+    TokenType::EqualEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::Identifier,
+    TokenType::String,
+    TokenType::Number,
+    TokenType::DocComment,
+    TokenType::And,
+    TokenType::Class,
+    TokenType::Else,
+    TokenType::False,
+    TokenType::Fun,
+    TokenType::For,
+    TokenType::If,
+    TokenType::Nil,
+    TokenType::Or,
+    TokenType::Print,
+    TokenType::Return,
+    TokenType::Super,
+    TokenType::This,
+    TokenType::True,
+    TokenType::Var,
+    TokenType::While,
+    TokenType::Reserved,
+    TokenType::Eof,
+    TokenType::Question,
+    TokenType::Colon,
+    TokenType::Arrow,
+    TokenType::Break,
+    TokenType::Continue,
+    TokenType::LeftBracket,
+    TokenType::RightBracket,
+    TokenType::Percent,
+    TokenType::StarStar,
+    TokenType::TildeSlash,
+];
+
+impl TokenType {
+    // stable name, e.g. `LeftParen`; every Custom token is just `Custom`
+    pub fn name(&self) -> String {
+        match self {
+            TokenType::Custom(_) => "Custom".to_string(),
+            token_type => format!("{:?}", token_type),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TokenType> {
+        return TOKEN_TYPES
+            .iter()
+            .find(|token_type| token_type.name() == name)
+            .cloned();
+    }
+
+    pub fn code(&self) -> Option<u8> {
+        return TOKEN_TYPES
+            .iter()
+            .position(|token_type| token_type == self)
+            .map(|index| index as u8);
+    }
+
+    pub fn from_code(code: u8) -> Option<TokenType> {
+        return TOKEN_TYPES.get(code as usize).cloned();
+    }
+}
+
+// The standard Lox keywords, for listing them; lookups go through `lox_keyword`.
+pub const LOX_KEYWORDS: [&str; 18] = [
+    "and", "break", "class", "continue", "else", "false", "for", "fun", "if", "nil", "or", "print",
+    "return", "super", "this", "true", "var", "while",
+];
+
+pub fn lox_keyword(text: &str) -> Option<TokenType> {
+    let token_type = match text.as_bytes() {
+        b"and" => TokenType::And,
+        b"break" => TokenType::Break,
+        b"class" => TokenType::Class,
+        b"continue" => TokenType::Continue,
+        b"else" => TokenType::Else,
+        b"false" => TokenType::False,
+        b"for" => TokenType::For,
+        b"fun" => TokenType::Fun,
+        b"if" => TokenType::If,
+        b"nil" => TokenType::Nil,
+        b"or" => TokenType::Or,
+        b"print" => TokenType::Print,
+        b"return" => TokenType::Return,
+        b"super" => TokenType::Super,
+        b"this" => TokenType::This,
+        b"true" => TokenType::True,
+        b"var" => TokenType::Var,
+        b"while" => TokenType::While,
+        _ => return None,
+    };
+    return Some(token_type);
+}
+
+// The keyword set a scanner uses: standard Lox plus whatever words a dialect adds or reserves.
+// Dialect words are checked first, so they can also take over a standard keyword.
+#[derive(Debug, Clone, Default)]
 pub struct Keywords {
-    pub keywords: HashMap<String, TokenType>,
+    dialect: Vec<(&'static str, TokenType)>,
 }
 impl Keywords {
     pub fn new() -> Self {
-     let keywords: HashMap<String, TokenType> =  HashMap::from([
-         ("and".to_string(), TokenType::And),
-         ("class".to_string(), TokenType::Class),
-         ("else".to_string(), TokenType::Else),
-         ("false".to_string(), TokenType::False),
-         ("for".to_string(), TokenType::For),
-         ("fun".to_string(), TokenType::Fun),
-         ("if".to_string(), TokenType::If),
-         ("nil".to_string(), TokenType::Nil),
-         ("or".to_string(), TokenType::Or),
-         ("print".to_string(), TokenType::Print),
-         ("return".to_string(), TokenType::Return),
-         ("super".to_string(), TokenType::Super),
-         ("this".to_string(), TokenType::This),
-         ("true".to_string(), TokenType::True),
-         ("var".to_string(), TokenType::Var),
-         ("while".to_string(), TokenType::While),
-     ]);
-     return Self { keywords };
-    }
-
-    pub fn get(&self, key: &String) -> TokenType {
-        match self.keywords.get(key) {
-            Some(token_type) => token_type.clone(),
-            None => TokenType::Identifier,
+        return Self::default();
+    }
+
+    pub fn with_keyword(mut self, word: &'static str, token_type: TokenType) -> Self {
+        self.dialect.retain(|(existing, _)| *existing != word);
+        self.dialect.push((word, token_type));
+        self
+    }
+
+    // reserved words are not keywords yet, but can no longer be used as identifiers
+    pub fn reserve(self, word: &'static str) -> Self {
+        self.with_keyword(word, TokenType::Reserved)
+    }
+
+    pub fn get(&self, key: &str) -> TokenType {
+        for (word, token_type) in self.dialect.iter() {
+            if *word == key {
+                return token_type.clone();
+            }
         }
+        return lox_keyword(key).unwrap_or(TokenType::Identifier);
+    }
+
+    // the keyword `word` was most likely meant to be, if it is a near miss for one
+    pub fn suggest(&self, word: &str) -> Option<&'static str> {
+        return diagnostics::suggest::closest(word, self.words());
+    }
+
+    // the dialect's own spelling of the custom token `word`, if this dialect defines it
+    pub fn custom(&self, word: &str) -> Option<&'static str> {
+        return self.dialect.iter().find_map(|(_, token_type)| match token_type {
+            TokenType::Custom(custom) if *custom == word => Some(*custom),
+            _ => None,
+        });
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let standard = LOX_KEYWORDS
+            .into_iter()
+            .filter(|word| self.dialect.iter().all(|(dialect, _)| dialect != word));
+        let dialect = self
+            .dialect
+            .iter()
+            .filter(|(_, token_type)| *token_type != TokenType::Reserved)
+            .map(|(word, _)| *word);
+        return standard.chain(dialect);
     }
 }
+
 impl Token {
     pub fn new(token_type: TokenType, lexeme: String, literal: Option<String>, line: usize) -> Self {
         Self {
@@ -99,8 +261,15 @@ impl Token {
             lexeme,
             literal,
             line,
+            column: 0,
+            span: Span::default(),
+            symbol: None,
         }
     }
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
     pub fn into_string(self) -> String {
         format!("{:?} {}", self.token_type, self.lexeme)
     }
@@ -113,6 +282,9 @@ impl From<TokenType> for Token {
             lexeme: String::new(),
             literal: None,
             line: 0,
+            column: 0,
+            span: Span::default(),
+            symbol: None,
         }
     }
 }
@@ -124,6 +296,59 @@ impl Clone for Token {
             lexeme: self.lexeme.clone(),
             literal: self.literal.clone(),
             line: self.line,
+            column: self.column,
+            span: self.span,
+            symbol: self.symbol,
+        }
+    }
+}
+
+// Columns, spans and symbols are metadata: two tokens are equal when they spell the same thing on the same line.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        return self.token_type == other.token_type
+            && self.lexeme == other.lexeme
+            && self.literal == other.literal
+            && self.line == other.line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_table_complete() {
+        for word in LOX_KEYWORDS {
+            assert_ne!(lox_keyword(word), None, "{} is missing from lox_keyword", word);
         }
+        assert_eq!(lox_keyword("fn"), None);
+    }
+
+    #[test]
+    fn test_token_type_names() {
+        for (code, token_type) in TOKEN_TYPES.iter().enumerate() {
+            assert_eq!(TokenType::from_name(&token_type.name()).as_ref(), Some(token_type));
+            assert_eq!(token_type.code(), Some(code as u8));
+        }
+        assert_eq!(TokenType::Custom("import").code(), None);
+    }
+
+    #[test]
+    fn test_dialect_keywords() {
+        let keywords = Keywords::new()
+            .with_keyword("import", TokenType::Custom("import"))
+            .with_keyword("function", TokenType::Fun)
+            .reserve("async");
+
+        assert_eq!(keywords.get("import"), TokenType::Custom("import"));
+        assert_eq!(keywords.get("function"), TokenType::Fun);
+        assert_eq!(keywords.get("async"), TokenType::Reserved);
+        assert_eq!(keywords.get("while"), TokenType::While);
+        assert_eq!(keywords.get("whilst"), TokenType::Identifier);
+        assert!(keywords.words().any(|word| word == "import"));
+        assert!(!keywords.words().any(|word| word == "async"));
+        assert_eq!(keywords.custom("import"), Some("import"));
+        assert_eq!(keywords.custom("function"), None);
     }
 }
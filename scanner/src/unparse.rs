@@ -0,0 +1,755 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::statement::{Statement, StmtId};
+use crate::tokens::{Token, TokenType};
+use crate::visitor::{StatementVisitor, Visitor};
+
+// Binding strength of each level of the grammar in parser.rs, loosest first.
+const COMMA: u8 = 1;
+const ASSIGNMENT: u8 = 2;
+const CONDITIONAL: u8 = 3;
+const OR: u8 = 4;
+const AND: u8 = 5;
+const EQUALITY: u8 = 6;
+const COMPARISON: u8 = 7;
+const TERM: u8 = 8;
+const FACTOR: u8 = 9;
+const UNARY: u8 = 10;
+const POWER: u8 = 11;
+const CALL: u8 = 12;
+const PRIMARY: u8 = 13;
+
+fn binary_precedence(operator: &TokenType) -> u8 {
+    match operator {
+        TokenType::Comma => COMMA,
+        TokenType::Or => OR,
+        TokenType::And => AND,
+        TokenType::BangEqual | TokenType::EqualEqual => EQUALITY,
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => COMPARISON,
+        TokenType::Minus | TokenType::Plus => TERM,
+        TokenType::StarStar => POWER,
+        _ => FACTOR,
+    }
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => binary_precedence(&operator.token_type),
+        // a lambda's body runs as far right as it can, like the else branch of a conditional
+        Expression::Conditional { .. } | Expression::Lambda { .. } => CONDITIONAL,
+        Expression::SetIndex { .. } | Expression::Assign { .. } => ASSIGNMENT,
+        Expression::Unary { .. } => UNARY,
+        Expression::Index { .. } | Expression::Call { .. } => CALL,
+        // its body is braced, so nothing after it can be mistaken for part of it
+        Expression::Function { .. } => PRIMARY,
+        Expression::Grouping { .. } | Expression::Literal(_) | Expression::Variable { .. } | Expression::List { .. } | Expression::Map { .. } => PRIMARY,
+    }
+}
+
+// Writes Lox source for an expression, adding parentheses only where precedence or
+// associativity would otherwise change the tree. Grouping nodes keep their parentheses.
+pub fn unparse(ast: &AST, id: ExprId) -> String {
+    return Unparser::default().visit_expression(ast, id);
+}
+
+// Writes the program of `ast` as Lox source, one top-level statement per line and blocks
+// indented by four spaces.
+pub fn unparse_program(ast: &AST) -> String {
+    let mut source = String::new();
+    for statement in ast.program() {
+        source.push_str(&Unparser::default().visit_statement(ast, *statement));
+        source.push('\n');
+    }
+    return source;
+}
+
+// whether an `else` written after this statement would bind to an `if` inside it
+fn dangles(ast: &AST, id: StmtId) -> bool {
+    match &ast[id] {
+        Statement::If { else_branch: None, .. } => true,
+        Statement::If { else_branch: Some(else_branch), .. } => dangles(ast, *else_branch),
+        Statement::While { body, .. } | Statement::ForEach { body, .. } => dangles(ast, *body),
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Unparser {
+    // how many blocks enclose the statement being written
+    depth: usize,
+}
+
+impl Unparser {
+    fn indent(&self) -> String {
+        return "    ".repeat(self.depth);
+    }
+
+    // `{`, one statement per line one level deeper, then `}` back at this level
+    fn braced(&mut self, ast: &AST, statements: &[StmtId]) -> String {
+        if statements.is_empty() {
+            return "{}".to_string();
+        }
+        self.depth += 1;
+        let mut source = String::from("{\n");
+        for statement in statements {
+            source.push_str(&self.indent());
+            source.push_str(&self.visit_statement(ast, *statement));
+            source.push('\n');
+        }
+        self.depth -= 1;
+        source.push_str(&self.indent());
+        source.push('}');
+        return source;
+    }
+
+    fn operand(&mut self, ast: &AST, id: ExprId, min_precedence: u8) -> String {
+        let source = self.visit_expression(ast, id);
+        if precedence(&ast[id]) < min_precedence {
+            return format!("({})", source);
+        }
+        return source;
+    }
+}
+
+impl Visitor<String> for Unparser {
+    fn combine(&mut self, results: Vec<String>) -> String {
+        unreachable!("the unparser handles every node itself")
+    }
+    fn visit_unary(&mut self, ast: &AST, id: ExprId, operator: &Token, value: ExprId) -> String {
+        return format!("{}{}", operator.lexeme, self.operand(ast, value, UNARY));
+    }
+    fn visit_binary(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> String {
+        // the others are left-associative, so a right operand at the same level needs parentheses
+        let level = binary_precedence(&operator.token_type);
+        if level == POWER {
+            // right-associative, and a unary base needs parentheses: `(-2) ** 2`
+            let left = self.operand(ast, left, CALL);
+            return format!("{} ** {}", left, self.operand(ast, right, UNARY));
+        }
+        let left = self.operand(ast, left, level);
+        let right = self.operand(ast, right, level + 1);
+        if operator.token_type == TokenType::Comma {
+            return format!("{}, {}", left, right);
+        }
+        return format!("{} {} {}", left, operator.lexeme, right);
+    }
+    fn visit_logical(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> String {
+        // both are left-associative
+        let level = binary_precedence(&operator.token_type);
+        let left = self.operand(ast, left, level);
+        return format!("{} {} {}", left, operator.lexeme, self.operand(ast, right, level + 1));
+    }
+    fn visit_conditional(&mut self, ast: &AST, id: ExprId, condition: ExprId, question: &Token, then_branch: ExprId, else_branch: ExprId) -> String {
+        // the then branch sits between `?` and `:` so it never needs parentheses;
+        // the else branch nests to the right
+        let condition = self.operand(ast, condition, CONDITIONAL + 1);
+        let then_branch = self.visit_expression(ast, then_branch);
+        let else_branch = self.operand(ast, else_branch, CONDITIONAL);
+        return format!("{} ? {} : {}", condition, then_branch, else_branch);
+    }
+    fn visit_lambda(&mut self, ast: &AST, id: ExprId, params: &[Token], arrow: &Token, body: ExprId) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        return format!("({}) => {}", params.join(", "), self.operand(ast, body, ASSIGNMENT));
+    }
+    fn visit_function_expression(&mut self, ast: &AST, id: ExprId, keyword: &Token, params: &[Token], body: &[StmtId]) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        return format!("fun ({}) {}", params.join(", "), self.braced(ast, body));
+    }
+    fn visit_call(&mut self, ast: &AST, id: ExprId, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> String {
+        let callee = self.operand(ast, callee, CALL);
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| self.operand(ast, *argument, ASSIGNMENT))
+            .collect();
+        return format!("{}({})", callee, arguments.join(", "));
+    }
+    fn visit_list(&mut self, ast: &AST, id: ExprId, bracket: &Token, elements: &[ExprId]) -> String {
+        let elements: Vec<String> = elements
+            .iter()
+            .map(|element| self.operand(ast, *element, ASSIGNMENT))
+            .collect();
+        return format!("[{}]", elements.join(", "));
+    }
+    fn visit_map(&mut self, ast: &AST, id: ExprId, brace: &Token, entries: &[(ExprId, ExprId)]) -> String {
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|(key, value)| format!("{}: {}", self.operand(ast, *key, ASSIGNMENT), self.operand(ast, *value, ASSIGNMENT)))
+            .collect();
+        return format!("{{{}}}", entries.join(", "));
+    }
+    fn visit_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId) -> String {
+        let object = self.operand(ast, object, CALL);
+        return format!("{}[{}]", object, self.visit_expression(ast, index));
+    }
+    fn visit_set_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId, value: ExprId) -> String {
+        let object = self.operand(ast, object, CALL);
+        let index = self.visit_expression(ast, index);
+        return format!("{}[{}] = {}", object, index, self.operand(ast, value, ASSIGNMENT));
+    }
+    fn visit_assign(&mut self, ast: &AST, id: ExprId, name: &Token, value: ExprId) -> String {
+        return format!("{} = {}", name.lexeme, self.operand(ast, value, ASSIGNMENT));
+    }
+    fn visit_grouping(&mut self, ast: &AST, id: ExprId, interior: ExprId) -> String {
+        return format!("({})", self.visit_expression(ast, interior));
+    }
+    fn visit_literal(&mut self, ast: &AST, id: ExprId, literal: &Literal) -> String {
+        match literal {
+            Literal::NUMBER(token) | Literal::STRING(token) | Literal::KEYWORD(token) => token.lexeme.clone(),
+        }
+    }
+    fn visit_variable(&mut self, ast: &AST, id: ExprId, name: &Token) -> String {
+        return name.lexeme.clone();
+    }
+}
+
+// Statements come out with their first line unindented: the caller has already written the
+// indentation, or the statement follows `if (...)` or `else` on the same line.
+impl StatementVisitor<String> for Unparser {
+    fn visit_expression_statement(&mut self, ast: &AST, id: StmtId, expression: ExprId) -> String {
+        let source = self.visit_expression(ast, expression);
+        // a statement starting with `{` would be read back as a block
+        if source.starts_with('{') {
+            return format!("({});", source);
+        }
+        return format!("{};", source);
+    }
+    fn visit_print(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: ExprId) -> String {
+        return format!("print {};", self.visit_expression(ast, value));
+    }
+    fn visit_var(&mut self, ast: &AST, id: StmtId, name: &Token, initializer: Option<ExprId>) -> String {
+        match initializer {
+            Some(initializer) => return format!("var {} = {};", name.lexeme, self.visit_expression(ast, initializer)),
+            None => return format!("var {};", name.lexeme),
+        }
+    }
+    fn visit_block(&mut self, ast: &AST, id: StmtId, brace: &Token, statements: &[StmtId]) -> String {
+        return self.braced(ast, statements);
+    }
+    fn visit_if(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> String {
+        let condition = self.visit_expression(ast, condition);
+        let Some(else_branch) = else_branch else {
+            return format!("if ({}) {}", condition, self.visit_statement(ast, then_branch));
+        };
+        // `if (a) if (b) x; else y;` would hand the else to the inner if, so brace it
+        let then_source = if dangles(ast, then_branch) {
+            self.braced(ast, &[then_branch])
+        } else {
+            self.visit_statement(ast, then_branch)
+        };
+        let separator = if then_source.ends_with('}') { " ".to_string() } else { format!("\n{}", self.indent()) };
+        let else_source = self.visit_statement(ast, else_branch);
+        return format!("if ({}) {}{}else {}", condition, then_source, separator, else_source);
+    }
+    fn visit_function(&mut self, ast: &AST, id: StmtId, name: &Token, params: &[Token], body: &[StmtId]) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        return format!("fun {}({}) {}", name.lexeme, params.join(", "), self.braced(ast, body));
+    }
+    fn visit_return(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: Option<ExprId>) -> String {
+        match value {
+            Some(value) => return format!("return {};", self.visit_expression(ast, value)),
+            None => return "return;".to_string(),
+        }
+    }
+    // a `for` comes back without its initializer, which is the statement before it in the
+    // enclosing block
+    fn visit_while(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, body: StmtId, increment: Option<ExprId>) -> String {
+        let condition = self.visit_expression(ast, condition);
+        let body = self.visit_statement(ast, body);
+        if keyword.token_type != TokenType::For && increment.is_none() {
+            return format!("while ({}) {}", condition, body);
+        }
+        let increment = increment.map(|increment| format!(" {}", self.visit_expression(ast, increment)));
+        return format!("for (; {};{}) {}", condition, increment.unwrap_or_default(), body);
+    }
+    fn visit_for_each(&mut self, ast: &AST, id: StmtId, keyword: &Token, name: &Token, iterable: ExprId, body: StmtId) -> String {
+        let iterable = self.visit_expression(ast, iterable);
+        return format!("for (var {} : {}) {}", name.lexeme, iterable, self.visit_statement(ast, body));
+    }
+    fn visit_break(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> String {
+        return "break;".to_string();
+    }
+    fn visit_continue(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> String {
+        return "continue;".to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visitor::{fold_children, fold_program, Fold};
+    use crate::{lox_stmt, parser::Parser, Scanner};
+
+    fn parse(input: &str) -> (AST, ExprId) {
+        let mut parser = Parser::new(Scanner::new(input));
+        let root = parser.parse();
+        return (parser.into_ast(), root);
+    }
+
+    fn round_trip(input: &str) -> String {
+        let (ast, root) = parse(input);
+        return unparse(&ast, root);
+    }
+
+    #[test]
+    fn test_keeps_source_grouping() {
+        assert_eq!(round_trip("(1 + 2) * 3"), "(1 + 2) * 3");
+        assert_eq!(round_trip("1 - (2 - 3)"), "1 - (2 - 3)");
+        assert_eq!(round_trip("-(-x) == !true"), "-(-x) == !true");
+        assert_eq!(round_trip("\"a\"   <=nil"), "\"a\" <= nil");
+        assert_eq!(round_trip("a ? b , c : d ? e : f"), "a ? b, c : d ? e : f");
+        assert_eq!(round_trip("(a ? b : c) ? d : e"), "(a ? b : c) ? d : e");
+        assert_eq!(round_trip("((x) => x) + 1"), "((x) => x) + 1");
+        assert_eq!(round_trip("(a,b)=>(c)=>a, 1"), "(a, b) => (c) => a, 1");
+        assert_eq!(round_trip("xs[0][i, j] = ys[1] = [1, [ ], -x[2]]"), "xs[0][i, j] = ys[1] = [1, [], -x[2]]");
+        assert_eq!(round_trip("{\"a\":c ? 1 : 2,true:{}}[\"a\"]"), "{\"a\": c ? 1 : 2, true: {}}[\"a\"]");
+        assert_eq!(round_trip("-2**3**-x % (a ~/ b)"), "-2 ** 3 ** -x % (a ~/ b)");
+        assert_eq!(round_trip("(-2) ** 2"), "(-2) ** 2");
+        assert_eq!(round_trip("a = b = (c = 1) + xs[0]"), "a = b = (c = 1) + xs[0]");
+        assert_eq!(round_trip("a or b and !c or (d or e) ? f : g"), "a or b and !c or (d or e) ? f : g");
+        assert_eq!(round_trip("(a)=>b=a"), "(a) => b = a");
+        assert_eq!(round_trip("f(a,(b,c))(d)[0](-x)"), "f(a, (b, c))(d)[0](-x)");
+        assert_eq!(round_trip("fun(a,b){return a+b;}(1,2)"), "fun (a, b) {\n    return a + b;\n}(1, 2)");
+    }
+
+    fn token(token_type: TokenType, lexeme: &str, literal: Option<String>) -> Token {
+        Token::new(token_type, lexeme.to_string(), literal, 1)
+    }
+
+    #[test]
+    fn test_adds_needed_parentheses() {
+        let mut ast = AST::new();
+        let one = ast.add(Expression::Literal(Literal::NUMBER(token(TokenType::Number, "1", Some("1".to_string())))));
+        let two = ast.add(Expression::Literal(Literal::NUMBER(token(TokenType::Number, "2", Some("2".to_string())))));
+        let three = ast.add(Expression::Literal(Literal::NUMBER(token(TokenType::Number, "3", Some("3".to_string())))));
+        let difference = ast.add(Expression::Binary {
+            left: two,
+            operator: token(TokenType::Minus, "-", None),
+            right: three,
+        });
+        let right_nested = ast.add(Expression::Binary {
+            left: one,
+            operator: token(TokenType::Minus, "-", None),
+            right: difference,
+        });
+        let product = ast.add(Expression::Binary {
+            left: right_nested,
+            operator: token(TokenType::Star, "*", None),
+            right: one,
+        });
+        let negated = ast.add(Expression::Unary {
+            operator: token(TokenType::Minus, "-", None),
+            value: product,
+        });
+
+        assert_eq!(unparse(&ast, negated), "-((1 - (2 - 3)) * 1)");
+    }
+
+    // xorshift, so the property test is deterministic without pulling in a crate
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            return self.0 % n;
+        }
+    }
+
+    const BINARY: [(TokenType, &str); 14] = [
+        (TokenType::Comma, ","),
+        (TokenType::BangEqual, "!="),
+        (TokenType::EqualEqual, "=="),
+        (TokenType::Greater, ">"),
+        (TokenType::GreaterEqual, ">="),
+        (TokenType::Less, "<"),
+        (TokenType::LessEqual, "<="),
+        (TokenType::Minus, "-"),
+        (TokenType::Plus, "+"),
+        (TokenType::Slash, "/"),
+        (TokenType::Star, "*"),
+        (TokenType::Percent, "%"),
+        (TokenType::TildeSlash, "~/"),
+        (TokenType::StarStar, "**"),
+    ];
+
+    // an arbitrary tree, built the way the scanner would spell its tokens
+    fn generate(random: &mut Random, ast: &mut AST, depth: u32) -> ExprId {
+        let choice = if depth == 0 { random.below(4) } else { random.below(18) };
+        let expression = match choice {
+            0 => {
+                let lexeme = match random.below(2) {
+                    0 => random.below(100).to_string(),
+                    _ => format!("{}.{}", random.below(100), random.below(9) + 1),
+                };
+                let value: f64 = lexeme.parse().unwrap();
+                Expression::Literal(Literal::NUMBER(token(TokenType::Number, &lexeme, Some(value.to_string()))))
+            }
+            1 => {
+                let text = ["", "a", "hello world"][random.below(3) as usize];
+                let lexeme = format!("\"{}\"", text);
+                Expression::Literal(Literal::STRING(token(TokenType::String, &lexeme, Some(text.to_string()))))
+            }
+            2 => {
+                let (token_type, lexeme) = [(TokenType::True, "true"), (TokenType::False, "false"), (TokenType::Nil, "nil")]
+                    [random.below(3) as usize]
+                    .clone();
+                Expression::Literal(Literal::KEYWORD(token(token_type, lexeme, None)))
+            }
+            3 => {
+                let name = ["x", "count", "_tmp1"][random.below(3) as usize];
+                Expression::Variable {
+                    name: token(TokenType::Identifier, name, None),
+                }
+            }
+            4 => {
+                let (token_type, lexeme) = [(TokenType::Minus, "-"), (TokenType::Bang, "!")][random.below(2) as usize].clone();
+                let value = generate(random, ast, depth - 1);
+                Expression::Unary {
+                    operator: token(token_type, lexeme, None),
+                    value,
+                }
+            }
+            5 => Expression::Grouping {
+                interior: generate(random, ast, depth - 1),
+            },
+            7 => {
+                let params = match random.below(3) {
+                    0 => vec![],
+                    1 => vec!["x"],
+                    _ => vec!["x", "count"],
+                };
+                let body = generate(random, ast, depth - 1);
+                Expression::Lambda {
+                    params: params.into_iter().map(|name| token(TokenType::Identifier, name, None)).collect(),
+                    arrow: token(TokenType::Arrow, "=>", None),
+                    body,
+                }
+            }
+            8 => {
+                let elements = (0..random.below(3)).map(|_| generate(random, ast, depth - 1)).collect();
+                Expression::List {
+                    bracket: token(TokenType::LeftBracket, "[", None),
+                    elements,
+                }
+            }
+            9 => {
+                let object = generate(random, ast, depth - 1);
+                let index = generate(random, ast, depth - 1);
+                Expression::Index {
+                    object,
+                    bracket: token(TokenType::LeftBracket, "[", None),
+                    index,
+                }
+            }
+            10 => {
+                let object = generate(random, ast, depth - 1);
+                let index = generate(random, ast, depth - 1);
+                let value = generate(random, ast, depth - 1);
+                Expression::SetIndex {
+                    object,
+                    bracket: token(TokenType::LeftBracket, "[", None),
+                    index,
+                    value,
+                }
+            }
+            11 => {
+                let mut entries = Vec::new();
+                for _ in 0..random.below(3) {
+                    let key = generate(random, ast, depth - 1);
+                    entries.push((key, generate(random, ast, depth - 1)));
+                }
+                Expression::Map {
+                    brace: token(TokenType::LeftBrace, "{", None),
+                    entries,
+                }
+            }
+            13 => {
+                let (token_type, lexeme) = [(TokenType::And, "and"), (TokenType::Or, "or")][random.below(2) as usize].clone();
+                let left = generate(random, ast, depth - 1);
+                let right = generate(random, ast, depth - 1);
+                Expression::Logical {
+                    left,
+                    operator: token(token_type, lexeme, None),
+                    right,
+                }
+            }
+            14 => {
+                let callee = generate(random, ast, depth - 1);
+                let arguments = (0..random.below(3)).map(|_| generate(random, ast, depth - 1)).collect();
+                Expression::Call {
+                    callee,
+                    paren: token(TokenType::RightParen, ")", None),
+                    arguments,
+                }
+            }
+            15 => {
+                let params = match random.below(2) {
+                    0 => vec![],
+                    _ => vec!["x"],
+                };
+                let value = generate(random, ast, depth - 1);
+                let body = ast.add_statement(Statement::Return {
+                    keyword: token(TokenType::Return, "return", None),
+                    value: Some(value),
+                });
+                Expression::Function {
+                    keyword: token(TokenType::Fun, "fun", None),
+                    params: params.into_iter().map(|name| token(TokenType::Identifier, name, None)).collect(),
+                    body: vec![body],
+                }
+            }
+            12 => {
+                let name = ["x", "count", "_tmp1"][random.below(3) as usize];
+                Expression::Assign {
+                    name: token(TokenType::Identifier, name, None),
+                    value: generate(random, ast, depth - 1),
+                }
+            }
+            6 => {
+                let condition = generate(random, ast, depth - 1);
+                let then_branch = generate(random, ast, depth - 1);
+                let else_branch = generate(random, ast, depth - 1);
+                Expression::Conditional {
+                    condition,
+                    question: token(TokenType::Question, "?", None),
+                    then_branch,
+                    else_branch,
+                }
+            }
+            _ => {
+                let (token_type, lexeme) = BINARY[random.below(BINARY.len() as u64) as usize].clone();
+                let left = generate(random, ast, depth - 1);
+                let right = generate(random, ast, depth - 1);
+                Expression::Binary {
+                    left,
+                    operator: token(token_type, lexeme, None),
+                    right,
+                }
+            }
+        };
+        return ast.add(expression);
+    }
+
+    struct DropGroups;
+
+    impl Fold for DropGroups {
+        fn fold_expression(&mut self, from: &AST, id: ExprId, into: &mut AST) -> ExprId {
+            match &from[id] {
+                Expression::Grouping { interior } => self.fold_expression(from, *interior, into),
+                _ => fold_children(self, from, id, into),
+            }
+        }
+        // the unparser spreads a `fun` body over several lines; generated tokens are all on line 1
+        fn fold_token(&mut self, token: &Token) -> Token {
+            let mut token = token.clone();
+            token.line = 1;
+            return token;
+        }
+    }
+
+    fn without_groups(ast: &AST, root: ExprId) -> AST {
+        let mut stripped = AST::new();
+        let root = DropGroups.fold_expression(ast, root, &mut stripped);
+        stripped.add_root(root);
+        return stripped;
+    }
+
+    #[test]
+    fn test_parse_unparse_round_trip() {
+        let mut random = Random(0x9e3779b97f4a7c15);
+        for _ in 0..500 {
+            let mut ast = AST::new();
+            let root = generate(&mut random, &mut ast, 5);
+            ast.add_root(root);
+
+            // parentheses the unparser had to add come back as groupings; nothing else changes
+            let source = unparse(&ast, root);
+            let (parsed, parsed_root) = parse(&source);
+            assert_eq!(without_groups(&parsed, parsed_root), without_groups(&ast, root), "{}", source);
+
+            // a tree that came from the parser survives exactly
+            let (reparsed, _) = parse(&unparse(&parsed, parsed_root));
+            assert_eq!(reparsed, parsed, "{}", source);
+        }
+    }
+
+    // generated trees can hold things the parser reports but still builds, like `{nil: 1}`
+    fn parse_program(input: &str) -> AST {
+        let mut parser = Parser::new(Scanner::new(input)).with_sink(diagnostics::collector());
+        parser.parse_program();
+        return parser.into_ast();
+    }
+
+    #[test]
+    fn test_statements() {
+        let source = "var x=1;var y;fun add(a,b){if(a)print a;else if (b) {return b;}  else {}\nreturn;}{x=y;}";
+        assert_eq!(
+            unparse_program(&parse_program(source)),
+            "var x = 1;\n\
+             var y;\n\
+             fun add(a, b) {\n\
+             \x20   if (a) print a;\n\
+             \x20   else if (b) {\n\
+             \x20       return b;\n\
+             \x20   } else {}\n\
+             \x20   return;\n\
+             }\n\
+             {\n\
+             \x20   x = y;\n\
+             }\n"
+        );
+
+        // a statement may not start with a map, which would be read as a block
+        let ast = lox_stmt!((; (index (map "k" 1) "k")));
+        assert_eq!(unparse_program(&ast), "({\"k\": 1}[\"k\"]);\n");
+
+        // the else belongs to the outer if, so the inner one gets braces
+        let ast = lox_stmt!((if a (if b (print 1)) (print 2)));
+        assert_eq!(unparse_program(&ast), "if (a) {\n    if (b) print 1;\n} else print 2;\n");
+
+        // a `for` comes back as a block holding its initializer and the loop
+        let source = "while (x) break; for (var i = 0; i < 3; i = i + 1) continue; for (;;) {} for(var x:[1]){}";
+        assert_eq!(
+            unparse_program(&parse_program(source)),
+            "while (x) break;\n{\n    var i = 0;\n    for (; i < 3; i = i + 1) continue;\n}\nfor (; true;) {}\nfor (var x : [1]) {}\n"
+        );
+    }
+
+    // a statement; declarations only where the grammar allows them, `return` only in functions
+    // and `break` and `continue` only in loops
+    fn generate_statement(random: &mut Random, ast: &mut AST, depth: u32, declaration: bool, in_function: bool, in_loop: bool) -> StmtId {
+        let kinds = if depth == 0 { 2 } else { 10 };
+        let statement = match random.below(kinds) {
+            0 => Statement::Expression {
+                expression: generate(random, ast, 3),
+            },
+            1 => Statement::Print {
+                keyword: token(TokenType::Print, "print", None),
+                value: generate(random, ast, 3),
+            },
+            2 => {
+                let statements = (0..random.below(3))
+                    .map(|_| generate_statement(random, ast, depth - 1, true, in_function, in_loop))
+                    .collect();
+                Statement::Block {
+                    brace: token(TokenType::LeftBrace, "{", None),
+                    statements,
+                }
+            }
+            3 => {
+                let condition = generate(random, ast, 3);
+                let then_branch = generate_statement(random, ast, depth - 1, false, in_function, in_loop);
+                let else_branch = match random.below(2) {
+                    0 => None,
+                    _ => Some(generate_statement(random, ast, depth - 1, false, in_function, in_loop)),
+                };
+                Statement::If {
+                    keyword: token(TokenType::If, "if", None),
+                    condition,
+                    then_branch,
+                    else_branch,
+                }
+            }
+            4 if in_function => {
+                let value = match random.below(2) {
+                    0 => None,
+                    _ => Some(generate(random, ast, 3)),
+                };
+                Statement::Return {
+                    keyword: token(TokenType::Return, "return", None),
+                    value,
+                }
+            }
+            5 if declaration => {
+                let initializer = match random.below(2) {
+                    0 => None,
+                    _ => Some(generate(random, ast, 3)),
+                };
+                Statement::Var {
+                    name: token(TokenType::Identifier, "x", None),
+                    initializer,
+                }
+            }
+            6 if declaration => {
+                let body = (0..random.below(3))
+                    .map(|_| generate_statement(random, ast, depth - 1, true, true, false))
+                    .collect();
+                Statement::Function {
+                    name: token(TokenType::Identifier, "f", None),
+                    params: vec![token(TokenType::Identifier, "count", None)],
+                    body,
+                }
+            }
+            7 => {
+                let condition = generate(random, ast, 3);
+                let body = generate_statement(random, ast, depth - 1, false, in_function, true);
+                let (keyword, increment) = match random.below(3) {
+                    0 => (token(TokenType::While, "while", None), None),
+                    1 => (token(TokenType::For, "for", None), None),
+                    _ => (token(TokenType::For, "for", None), Some(generate(random, ast, 3))),
+                };
+                Statement::While {
+                    keyword,
+                    condition,
+                    body,
+                    increment,
+                }
+            }
+            9 => {
+                let iterable = generate(random, ast, 3);
+                let body = generate_statement(random, ast, depth - 1, false, in_function, true);
+                Statement::ForEach {
+                    keyword: token(TokenType::For, "for", None),
+                    name: token(TokenType::Identifier, "x", None),
+                    iterable,
+                    body,
+                }
+            }
+            8 if in_loop => match random.below(2) {
+                0 => Statement::Break {
+                    keyword: token(TokenType::Break, "break", None),
+                },
+                _ => Statement::Continue {
+                    keyword: token(TokenType::Continue, "continue", None),
+                },
+            },
+            _ => Statement::Expression {
+                expression: generate(random, ast, 2),
+            },
+        };
+        return ast.add_statement(statement);
+    }
+
+    fn program_without_groups(ast: &AST) -> AST {
+        let mut stripped = AST::new();
+        fold_program(&mut DropGroups, ast, &mut stripped);
+        return stripped;
+    }
+
+    // braces added for a dangling else come back as a block
+    fn has_dangling_else(ast: &AST) -> bool {
+        return ast.statement_ids().any(|id| match &ast[id] {
+            Statement::If { then_branch, else_branch: Some(_), .. } => dangles(ast, *then_branch),
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_program_round_trip() {
+        let mut random = Random(0x2545f4914f6cdd1d);
+        for _ in 0..300 {
+            let mut ast = AST::new();
+            for _ in 0..3 {
+                let statement = generate_statement(&mut random, &mut ast, 3, true, false, false);
+                ast.add_to_program(statement);
+            }
+
+            let source = unparse_program(&ast);
+            let parsed = parse_program(&source);
+            if !has_dangling_else(&ast) {
+                assert!(program_without_groups(&parsed).same_shape(&program_without_groups(&ast)), "{}", source);
+            }
+            assert_eq!(parse_program(&unparse_program(&parsed)), parsed, "{}", source);
+        }
+    }
+}
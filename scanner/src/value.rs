@@ -0,0 +1,228 @@
+use crate::interpreter::Function;
+use crate::native::Native;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// What an expression evaluates to.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    // shared, so `push(xs, 1)` is seen through every variable holding `xs`
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    Function(Rc<Function>),
+    Native(&'static Native),
+}
+
+impl Value {
+    pub fn list(elements: Vec<Value>) -> Value {
+        return Value::List(Rc::new(RefCell::new(elements)));
+    }
+
+    pub fn map(map: Map) -> Value {
+        return Value::Map(Rc::new(RefCell::new(map)));
+    }
+
+    // nil and false are false, everything else is true
+    pub fn is_truthy(&self) -> bool {
+        return !matches!(self, Value::Nil | Value::Bool(false));
+    }
+
+    // for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Function(_) | Value::Native(_) => "function",
+        }
+    }
+
+    // `open` holds the lists and maps being written, so one that contains itself shows as
+    // `[...]` or `{...}` rather than recursing forever. Strings inside them keep their quotes.
+    fn write(&self, f: &mut fmt::Formatter<'_>, open: &mut Vec<*const ()>, nested: bool) -> fmt::Result {
+        let pointer = match self {
+            Value::String(string) if nested => return write!(f, "\"{}\"", string),
+            Value::List(list) => Rc::as_ptr(list) as *const (),
+            Value::Map(map) => Rc::as_ptr(map) as *const (),
+            value => return write!(f, "{}", value),
+        };
+        if open.contains(&pointer) {
+            return write!(f, "{}", if let Value::List(_) = self { "[...]" } else { "{...}" });
+        }
+        open.push(pointer);
+        match self {
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (position, element) in list.borrow().iter().enumerate() {
+                    if position > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write(f, open, true)?;
+                }
+                write!(f, "]")?;
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (position, (key, value)) in map.borrow().entries().enumerate() {
+                    if position > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.to_value().write(f, open, true)?;
+                    write!(f, ": ")?;
+                    value.write(f, open, true)?;
+                }
+                write!(f, "}}")?;
+            }
+            _ => {}
+        }
+        open.pop();
+        return Ok(());
+    }
+}
+
+// Lists, maps and functions are only equal to themselves; everything else compares by value.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => return true,
+            (Value::Bool(left), Value::Bool(right)) => return left == right,
+            (Value::Number(left), Value::Number(right)) => return left == right,
+            (Value::String(left), Value::String(right)) => return left == right,
+            (Value::List(left), Value::List(right)) => return Rc::ptr_eq(left, right),
+            (Value::Map(left), Value::Map(right)) => return Rc::ptr_eq(left, right),
+            (Value::Function(left), Value::Function(right)) => return Rc::ptr_eq(left, right),
+            (Value::Native(left), Value::Native(right)) => return std::ptr::eq(*left, *right),
+            _ => return false,
+        }
+    }
+}
+
+// How `print` shows a value: strings without quotes, whole numbers without a fraction.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::List(_) | Value::Map(_) => self.write(f, &mut Vec::new(), false),
+            Value::Function(function) => write!(f, "{:?}", function),
+            Value::Native(native) => write!(f, "{:?}", native),
+        }
+    }
+}
+
+// What a map can be keyed by. Numbers are keyed by their bits, with -0 folded into 0 so the
+// two keys that compare equal are one key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Bool(bool),
+    Number(u64),
+    String(Rc<str>),
+}
+
+impl Key {
+    // None for nil, lists, maps and functions
+    pub fn from_value(value: &Value) -> Option<Key> {
+        match value {
+            Value::Bool(value) => return Some(Key::Bool(*value)),
+            Value::Number(value) if *value == 0.0 => return Some(Key::Number(0f64.to_bits())),
+            Value::Number(value) => return Some(Key::Number(value.to_bits())),
+            Value::String(value) => return Some(Key::String(value.clone())),
+            _ => return None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Bool(value) => return Value::Bool(*value),
+            Key::Number(bits) => return Value::Number(f64::from_bits(*bits)),
+            Key::String(value) => return Value::String(value.clone()),
+        }
+    }
+}
+
+// A map that remembers the order its keys were first inserted in, which is the order
+// `keys`, `values`, `print` and `for` see them in.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Key, Value)>,
+    positions: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        return self.positions.get(key).map(|position| &self.entries[*position].1);
+    }
+
+    // a key that is already present keeps its place
+    pub fn insert(&mut self, key: Key, value: Value) {
+        match self.positions.get(&key) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+        for (key, _) in &self.entries[position..] {
+            *self.positions.get_mut(key).unwrap() -= 1;
+        }
+        return Some(value);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        return self.entries.iter().map(|(key, value)| (key, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        return Key::String(text.into());
+    }
+
+    #[test]
+    fn test_map_keeps_insertion_order() {
+        let mut map = Map::default();
+        for (name, value) in [("c", 1.0), ("a", 2.0), ("b", 3.0)] {
+            map.insert(key(name), Value::Number(value));
+        }
+        map.insert(key("c"), Value::Number(4.0));
+        assert_eq!(map.remove(&key("a")), Some(Value::Number(2.0)));
+        assert_eq!(map.remove(&key("a")), None);
+        map.insert(key("a"), Value::Nil);
+
+        let keys: Vec<Key> = map.entries().map(|(key, _)| key.clone()).collect();
+        assert_eq!(keys, [key("c"), key("b"), key("a")]);
+        assert_eq!(map.get(&key("b")), Some(&Value::Number(3.0)));
+        assert_eq!(map.get(&key("c")), Some(&Value::Number(4.0)));
+
+        // 0 and -0 are equal, so they are one key
+        assert_eq!(Key::from_value(&Value::Number(-0.0)), Key::from_value(&Value::Number(0.0)));
+        assert_eq!(Key::from_value(&Value::Nil), None);
+    }
+}
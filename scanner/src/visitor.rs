@@ -0,0 +1,714 @@
+use crate::expression::{ExprId, Expression, Literal, AST};
+use crate::statement::{Statement, StmtId};
+use crate::tokens::Token;
+
+// Read-only pass over an expression tree that computes an `R` per node.
+// `visit_expression` dispatches on the variant. Every `visit_*` defaults to visiting the
+// node's children and handing their results to `combine`, so a pass only overrides the
+// nodes it cares about.
+pub trait Visitor<R> {
+    // what a node the pass does not handle itself comes to, given its children's results
+    fn combine(&mut self, results: Vec<R>) -> R;
+    fn visit_expression(&mut self, ast: &AST, id: ExprId) -> R {
+        match &ast[id] {
+            Expression::Unary { operator, value } => self.visit_unary(ast, id, operator, *value),
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => self.visit_binary(ast, id, *left, operator, *right),
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => self.visit_logical(ast, id, *left, operator, *right),
+            Expression::Grouping { interior } => self.visit_grouping(ast, id, *interior),
+            Expression::Conditional {
+                condition,
+                question,
+                then_branch,
+                else_branch,
+            } => self.visit_conditional(ast, id, *condition, question, *then_branch, *else_branch),
+            Expression::Literal(literal) => self.visit_literal(ast, id, literal),
+            Expression::Variable { name } => self.visit_variable(ast, id, name),
+            Expression::Assign { name, value } => self.visit_assign(ast, id, name, *value),
+            Expression::Lambda { params, arrow, body } => self.visit_lambda(ast, id, params, arrow, *body),
+            Expression::Function { keyword, params, body } => self.visit_function_expression(ast, id, keyword, params, body),
+            Expression::Call {
+                callee,
+                paren,
+                arguments,
+            } => self.visit_call(ast, id, *callee, paren, arguments),
+            Expression::List { bracket, elements } => self.visit_list(ast, id, bracket, elements),
+            Expression::Map { brace, entries } => self.visit_map(ast, id, brace, entries),
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => self.visit_index(ast, id, *object, bracket, *index),
+            Expression::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => self.visit_set_index(ast, id, *object, bracket, *index, *value),
+        }
+    }
+    fn visit_unary(&mut self, ast: &AST, id: ExprId, operator: &Token, value: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_binary(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_logical(&mut self, ast: &AST, id: ExprId, left: ExprId, operator: &Token, right: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_grouping(&mut self, ast: &AST, id: ExprId, interior: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_conditional(&mut self, ast: &AST, id: ExprId, condition: ExprId, question: &Token, then_branch: ExprId, else_branch: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_literal(&mut self, ast: &AST, id: ExprId, literal: &Literal) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_variable(&mut self, ast: &AST, id: ExprId, name: &Token) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_assign(&mut self, ast: &AST, id: ExprId, name: &Token, value: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_lambda(&mut self, ast: &AST, id: ExprId, params: &[Token], arrow: &Token, body: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_function_expression(&mut self, ast: &AST, id: ExprId, keyword: &Token, params: &[Token], body: &[StmtId]) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_call(&mut self, ast: &AST, id: ExprId, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_list(&mut self, ast: &AST, id: ExprId, bracket: &Token, elements: &[ExprId]) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_map(&mut self, ast: &AST, id: ExprId, brace: &Token, entries: &[(ExprId, ExprId)]) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_set_index(&mut self, ast: &AST, id: ExprId, object: ExprId, bracket: &Token, index: ExprId, value: ExprId) -> R {
+        let results = walk_expression(self, ast, id);
+        return self.combine(results);
+    }
+}
+
+// The expressions directly inside `expression`. The body of a `fun` expression is statements,
+// so it has none; a pass that needs the body visits it as statements.
+pub fn children(expression: &Expression) -> Vec<ExprId> {
+    match expression {
+        Expression::Unary { value, .. } | Expression::Assign { value, .. } => vec![*value],
+        Expression::Binary { left, right, .. } | Expression::Logical { left, right, .. } => vec![*left, *right],
+        Expression::Grouping { interior } => vec![*interior],
+        Expression::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => vec![*condition, *then_branch, *else_branch],
+        Expression::Lambda { body, .. } => vec![*body],
+        Expression::Call { callee, arguments, .. } => {
+            let mut children = vec![*callee];
+            children.extend_from_slice(arguments);
+            children
+        }
+        Expression::List { elements, .. } => elements.clone(),
+        // key, value, key, value, ...
+        Expression::Map { entries, .. } => entries.iter().flat_map(|(key, value)| [*key, *value]).collect(),
+        Expression::Index { object, index, .. } => vec![*object, *index],
+        Expression::SetIndex {
+            object,
+            index,
+            value,
+            ..
+        } => vec![*object, *index, *value],
+        Expression::Literal(_) | Expression::Variable { .. } | Expression::Function { .. } => vec![],
+    }
+}
+
+// visits the direct children of `id`, left to right
+pub fn walk_expression<R, V: Visitor<R> + ?Sized>(visitor: &mut V, ast: &AST, id: ExprId) -> Vec<R> {
+    return children(&ast[id])
+        .into_iter()
+        .map(|child| visitor.visit_expression(ast, child))
+        .collect();
+}
+
+// Read-only pass over statements as well as expressions. Expression-only passes need not
+// implement it; `visit_statement` dispatches like `visit_expression` does, and its hooks
+// default to walking the statement's children in the same way.
+pub trait StatementVisitor<R>: Visitor<R> {
+    fn visit_statement(&mut self, ast: &AST, id: StmtId) -> R {
+        match &ast[id] {
+            Statement::Expression { expression } => self.visit_expression_statement(ast, id, *expression),
+            Statement::Print { keyword, value } => self.visit_print(ast, id, keyword, *value),
+            Statement::Var { name, initializer } => self.visit_var(ast, id, name, *initializer),
+            Statement::Block { brace, statements } => self.visit_block(ast, id, brace, statements),
+            Statement::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => self.visit_if(ast, id, keyword, *condition, *then_branch, *else_branch),
+            Statement::Function { name, params, body } => self.visit_function(ast, id, name, params, body),
+            Statement::Return { keyword, value } => self.visit_return(ast, id, keyword, *value),
+            Statement::While {
+                keyword,
+                condition,
+                body,
+                increment,
+            } => self.visit_while(ast, id, keyword, *condition, *body, *increment),
+            Statement::ForEach {
+                keyword,
+                name,
+                iterable,
+                body,
+            } => self.visit_for_each(ast, id, keyword, name, *iterable, *body),
+            Statement::Break { keyword } => self.visit_break(ast, id, keyword),
+            Statement::Continue { keyword } => self.visit_continue(ast, id, keyword),
+        }
+    }
+    fn visit_expression_statement(&mut self, ast: &AST, id: StmtId, expression: ExprId) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_print(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: ExprId) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_var(&mut self, ast: &AST, id: StmtId, name: &Token, initializer: Option<ExprId>) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_block(&mut self, ast: &AST, id: StmtId, brace: &Token, statements: &[StmtId]) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_if(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_function(&mut self, ast: &AST, id: StmtId, name: &Token, params: &[Token], body: &[StmtId]) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_return(&mut self, ast: &AST, id: StmtId, keyword: &Token, value: Option<ExprId>) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_while(&mut self, ast: &AST, id: StmtId, keyword: &Token, condition: ExprId, body: StmtId, increment: Option<ExprId>) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_for_each(&mut self, ast: &AST, id: StmtId, keyword: &Token, name: &Token, iterable: ExprId, body: StmtId) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_break(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+    fn visit_continue(&mut self, ast: &AST, id: StmtId, keyword: &Token) -> R {
+        let results = walk_statement(self, ast, id);
+        return self.combine(results);
+    }
+}
+
+// the expressions directly inside a statement, then its nested statements, each left to right
+pub fn statement_children(statement: &Statement) -> (Vec<ExprId>, Vec<StmtId>) {
+    match statement {
+        Statement::Expression { expression } => (vec![*expression], vec![]),
+        Statement::Print { value, .. } => (vec![*value], vec![]),
+        Statement::Var { initializer, .. } => (initializer.iter().copied().collect(), vec![]),
+        Statement::Block { statements, .. } => (vec![], statements.clone()),
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            let mut branches = vec![*then_branch];
+            branches.extend(*else_branch);
+            (vec![*condition], branches)
+        }
+        Statement::Function { body, .. } => (vec![], body.clone()),
+        Statement::Return { value, .. } => (value.iter().copied().collect(), vec![]),
+        Statement::While {
+            condition,
+            body,
+            increment,
+            ..
+        } => {
+            let mut expressions = vec![*condition];
+            expressions.extend(*increment);
+            (expressions, vec![*body])
+        }
+        Statement::ForEach { iterable, body, .. } => (vec![*iterable], vec![*body]),
+        Statement::Break { .. } | Statement::Continue { .. } => (vec![], vec![]),
+    }
+}
+
+// visits the direct children of statement `id`: its expressions, then its statements
+pub fn walk_statement<R, V: StatementVisitor<R> + ?Sized>(visitor: &mut V, ast: &AST, id: StmtId) -> Vec<R> {
+    let (expressions, statements) = statement_children(&ast[id]);
+    let mut results: Vec<R> = expressions
+        .into_iter()
+        .map(|child| visitor.visit_expression(ast, child))
+        .collect();
+    results.extend(statements.into_iter().map(|child| visitor.visit_statement(ast, child)));
+    return results;
+}
+
+// In-place pass; every method defaults to walking the children, so only override what matters.
+pub trait VisitorMut {
+    fn visit_expression_mut(&mut self, ast: &mut AST, id: ExprId) {
+        walk_expression_mut(self, ast, id);
+    }
+    fn visit_statement_mut(&mut self, ast: &mut AST, id: StmtId) {
+        walk_statement_mut(self, ast, id);
+    }
+    fn visit_token_mut(&mut self, token: &mut Token) {}
+    fn visit_literal_mut(&mut self, literal: &mut Literal) {
+        match literal {
+            Literal::NUMBER(token) | Literal::STRING(token) | Literal::KEYWORD(token) => {
+                self.visit_token_mut(token)
+            }
+        }
+    }
+}
+
+// visits the node's own tokens and then its children
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut AST, id: ExprId) {
+    match &mut ast[id] {
+        Expression::Unary { operator, .. } | Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => {
+            visitor.visit_token_mut(operator)
+        }
+        Expression::Conditional { question, .. } => visitor.visit_token_mut(question),
+        Expression::Grouping { .. } => {}
+        Expression::Literal(literal) => visitor.visit_literal_mut(literal),
+        Expression::Variable { name } | Expression::Assign { name, .. } => visitor.visit_token_mut(name),
+        Expression::Lambda { params, arrow, .. } => {
+            for param in params.iter_mut() {
+                visitor.visit_token_mut(param);
+            }
+            visitor.visit_token_mut(arrow);
+        }
+        Expression::Function { keyword, params, .. } => {
+            visitor.visit_token_mut(keyword);
+            for param in params.iter_mut() {
+                visitor.visit_token_mut(param);
+            }
+        }
+        Expression::Call { paren, .. } => visitor.visit_token_mut(paren),
+        Expression::List { bracket, .. } | Expression::Index { bracket, .. } | Expression::SetIndex { bracket, .. } => {
+            visitor.visit_token_mut(bracket)
+        }
+        Expression::Map { brace, .. } => visitor.visit_token_mut(brace),
+    }
+    for child in children(&ast[id]) {
+        visitor.visit_expression_mut(ast, child);
+    }
+    if let Expression::Function { body, .. } = &ast[id] {
+        for statement in body.clone() {
+            visitor.visit_statement_mut(ast, statement);
+        }
+    }
+}
+
+// visits the statement's own tokens and then its children
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut AST, id: StmtId) {
+    match &mut ast[id] {
+        Statement::Expression { .. } => {}
+        Statement::Print { keyword, .. }
+        | Statement::If { keyword, .. }
+        | Statement::Return { keyword, .. }
+        | Statement::While { keyword, .. }
+        | Statement::Break { keyword }
+        | Statement::Continue { keyword } => visitor.visit_token_mut(keyword),
+        Statement::Var { name, .. } => visitor.visit_token_mut(name),
+        Statement::ForEach { keyword, name, .. } => {
+            visitor.visit_token_mut(keyword);
+            visitor.visit_token_mut(name);
+        }
+        Statement::Block { brace, .. } => visitor.visit_token_mut(brace),
+        Statement::Function { name, params, .. } => {
+            visitor.visit_token_mut(name);
+            for param in params.iter_mut() {
+                visitor.visit_token_mut(param);
+            }
+        }
+    }
+    let (expressions, statements) = statement_children(&ast[id]);
+    for child in expressions {
+        visitor.visit_expression_mut(ast, child);
+    }
+    for child in statements {
+        visitor.visit_statement_mut(ast, child);
+    }
+}
+
+// Rewriting pass that copies a tree into another AST; the defaults copy it unchanged.
+pub trait Fold {
+    fn fold_expression(&mut self, from: &AST, id: ExprId, into: &mut AST) -> ExprId {
+        fold_children(self, from, id, into)
+    }
+    fn fold_statement(&mut self, from: &AST, id: StmtId, into: &mut AST) -> StmtId {
+        fold_statement_children(self, from, id, into)
+    }
+    fn fold_token(&mut self, token: &Token) -> Token {
+        token.clone()
+    }
+    fn fold_literal(&mut self, literal: &Literal) -> Literal {
+        match literal {
+            Literal::NUMBER(token) => Literal::NUMBER(self.fold_token(token)),
+            Literal::STRING(token) => Literal::STRING(self.fold_token(token)),
+            Literal::KEYWORD(token) => Literal::KEYWORD(self.fold_token(token)),
+        }
+    }
+}
+
+// folds the children of `id` and adds the rebuilt node to `into`
+pub fn fold_children<F: Fold + ?Sized>(folder: &mut F, from: &AST, id: ExprId, into: &mut AST) -> ExprId {
+    let expression = match &from[id] {
+        Expression::Unary { operator, value } => Expression::Unary {
+            operator: folder.fold_token(operator),
+            value: folder.fold_expression(from, *value, into),
+        },
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        } => Expression::Binary {
+            left: folder.fold_expression(from, *left, into),
+            operator: folder.fold_token(operator),
+            right: folder.fold_expression(from, *right, into),
+        },
+        Expression::Logical {
+            left,
+            operator,
+            right,
+        } => Expression::Logical {
+            left: folder.fold_expression(from, *left, into),
+            operator: folder.fold_token(operator),
+            right: folder.fold_expression(from, *right, into),
+        },
+        Expression::Grouping { interior } => Expression::Grouping {
+            interior: folder.fold_expression(from, *interior, into),
+        },
+        Expression::Conditional {
+            condition,
+            question,
+            then_branch,
+            else_branch,
+        } => Expression::Conditional {
+            condition: folder.fold_expression(from, *condition, into),
+            question: folder.fold_token(question),
+            then_branch: folder.fold_expression(from, *then_branch, into),
+            else_branch: folder.fold_expression(from, *else_branch, into),
+        },
+        Expression::Literal(literal) => Expression::Literal(folder.fold_literal(literal)),
+        Expression::Variable { name } => Expression::Variable {
+            name: folder.fold_token(name),
+        },
+        Expression::Assign { name, value } => Expression::Assign {
+            name: folder.fold_token(name),
+            value: folder.fold_expression(from, *value, into),
+        },
+        Expression::Lambda { params, arrow, body } => Expression::Lambda {
+            params: params.iter().map(|param| folder.fold_token(param)).collect(),
+            arrow: folder.fold_token(arrow),
+            body: folder.fold_expression(from, *body, into),
+        },
+        Expression::Function { keyword, params, body } => Expression::Function {
+            keyword: folder.fold_token(keyword),
+            params: params.iter().map(|param| folder.fold_token(param)).collect(),
+            body: body
+                .iter()
+                .map(|statement| folder.fold_statement(from, *statement, into))
+                .collect(),
+        },
+        Expression::Call {
+            callee,
+            paren,
+            arguments,
+        } => Expression::Call {
+            callee: folder.fold_expression(from, *callee, into),
+            paren: folder.fold_token(paren),
+            arguments: arguments
+                .iter()
+                .map(|argument| folder.fold_expression(from, *argument, into))
+                .collect(),
+        },
+        Expression::List { bracket, elements } => Expression::List {
+            bracket: folder.fold_token(bracket),
+            elements: elements
+                .iter()
+                .map(|element| folder.fold_expression(from, *element, into))
+                .collect(),
+        },
+        Expression::Map { brace, entries } => Expression::Map {
+            brace: folder.fold_token(brace),
+            entries: entries
+                .iter()
+                .map(|(key, value)| {
+                    let key = folder.fold_expression(from, *key, into);
+                    (key, folder.fold_expression(from, *value, into))
+                })
+                .collect(),
+        },
+        Expression::Index {
+            object,
+            bracket,
+            index,
+        } => Expression::Index {
+            object: folder.fold_expression(from, *object, into),
+            bracket: folder.fold_token(bracket),
+            index: folder.fold_expression(from, *index, into),
+        },
+        Expression::SetIndex {
+            object,
+            bracket,
+            index,
+            value,
+        } => Expression::SetIndex {
+            object: folder.fold_expression(from, *object, into),
+            bracket: folder.fold_token(bracket),
+            index: folder.fold_expression(from, *index, into),
+            value: folder.fold_expression(from, *value, into),
+        },
+    };
+    return into.add(expression);
+}
+
+// folds the children of statement `id` and adds the rebuilt statement to `into`
+pub fn fold_statement_children<F: Fold + ?Sized>(folder: &mut F, from: &AST, id: StmtId, into: &mut AST) -> StmtId {
+    let statement = match &from[id] {
+        Statement::Expression { expression } => Statement::Expression {
+            expression: folder.fold_expression(from, *expression, into),
+        },
+        Statement::Print { keyword, value } => Statement::Print {
+            keyword: folder.fold_token(keyword),
+            value: folder.fold_expression(from, *value, into),
+        },
+        Statement::Var { name, initializer } => Statement::Var {
+            name: folder.fold_token(name),
+            initializer: initializer.map(|initializer| folder.fold_expression(from, initializer, into)),
+        },
+        Statement::Block { brace, statements } => Statement::Block {
+            brace: folder.fold_token(brace),
+            statements: statements
+                .iter()
+                .map(|statement| folder.fold_statement(from, *statement, into))
+                .collect(),
+        },
+        Statement::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        } => Statement::If {
+            keyword: folder.fold_token(keyword),
+            condition: folder.fold_expression(from, *condition, into),
+            then_branch: folder.fold_statement(from, *then_branch, into),
+            else_branch: else_branch.map(|else_branch| folder.fold_statement(from, else_branch, into)),
+        },
+        Statement::Function { name, params, body } => Statement::Function {
+            name: folder.fold_token(name),
+            params: params.iter().map(|param| folder.fold_token(param)).collect(),
+            body: body
+                .iter()
+                .map(|statement| folder.fold_statement(from, *statement, into))
+                .collect(),
+        },
+        Statement::Return { keyword, value } => Statement::Return {
+            keyword: folder.fold_token(keyword),
+            value: value.map(|value| folder.fold_expression(from, value, into)),
+        },
+        Statement::While {
+            keyword,
+            condition,
+            body,
+            increment,
+        } => Statement::While {
+            keyword: folder.fold_token(keyword),
+            condition: folder.fold_expression(from, *condition, into),
+            body: folder.fold_statement(from, *body, into),
+            increment: increment.map(|increment| folder.fold_expression(from, increment, into)),
+        },
+        Statement::ForEach {
+            keyword,
+            name,
+            iterable,
+            body,
+        } => Statement::ForEach {
+            keyword: folder.fold_token(keyword),
+            name: folder.fold_token(name),
+            iterable: folder.fold_expression(from, *iterable, into),
+            body: folder.fold_statement(from, *body, into),
+        },
+        Statement::Break { keyword } => Statement::Break {
+            keyword: folder.fold_token(keyword),
+        },
+        Statement::Continue { keyword } => Statement::Continue {
+            keyword: folder.fold_token(keyword),
+        },
+    };
+    let docs = from.docs(id).iter().map(|doc| folder.fold_token(doc)).collect();
+    let folded = into.add_statement(statement);
+    into.add_docs(folded, docs);
+    return folded;
+}
+
+// Folds a whole program into `into`, which gets the rebuilt statements as its program.
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, from: &AST, into: &mut AST) {
+    for statement in from.program() {
+        let statement = folder.fold_statement(from, *statement, into);
+        into.add_to_program(statement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, tokens::TokenType, Scanner};
+
+    fn parse(input: &str) -> (AST, ExprId) {
+        let mut parser = Parser::new(Scanner::new(input));
+        let root = parser.parse();
+        return (parser.into_ast(), root);
+    }
+
+    struct Depth;
+
+    impl Visitor<usize> for Depth {
+        fn combine(&mut self, results: Vec<usize>) -> usize {
+            return 1 + results.into_iter().max().unwrap_or(0);
+        }
+        // parentheses add no depth
+        fn visit_grouping(&mut self, ast: &AST, id: ExprId, interior: ExprId) -> usize {
+            return self.visit_expression(ast, interior);
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let (ast, root) = parse("1 + -(2 * 3)");
+        assert_eq!(Depth.visit_expression(&ast, root), 4);
+    }
+
+    struct Count;
+
+    impl Visitor<usize> for Count {
+        fn combine(&mut self, results: Vec<usize>) -> usize {
+            return 1 + results.into_iter().sum::<usize>();
+        }
+    }
+
+    impl StatementVisitor<usize> for Count {}
+
+    #[test]
+    fn test_walk() {
+        let (ast, root) = parse("1 + -(2 * x)");
+        assert_eq!(Count.visit_expression(&ast, root), 7);
+    }
+
+    fn parse_program(input: &str) -> AST {
+        let mut parser = Parser::new(Scanner::new(input));
+        parser.parse_program();
+        return parser.into_ast();
+    }
+
+    #[test]
+    fn test_walk_statements() {
+        // fun, if, block, print, return and their expressions a, a, b
+        let ast = parse_program("fun f(a) { if (a) { print a; } else return b; }");
+        assert_eq!(Count.visit_statement(&ast, ast.program()[0]), 8);
+    }
+
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn visit_token_mut(&mut self, token: &mut Token) {
+            if token.token_type == TokenType::Identifier && token.lexeme == "a" {
+                token.lexeme = "b".to_string();
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        let (mut ast, root) = parse("a * (a + c)");
+        Rename.visit_expression_mut(&mut ast, root);
+        assert_eq!(ast.print(root), "(* b (group (+ b c)))");
+    }
+
+    #[test]
+    fn test_visitor_mut_statements() {
+        let mut ast = parse_program("fun a(a) { var c = (a); return a; }");
+        let function = ast.program()[0];
+        Rename.visit_statement_mut(&mut ast, function);
+        assert_eq!(ast.print(function), "(fun b (b) (var c (group b)) (return b))");
+
+        // passes reach into the body of a `fun` expression too
+        let (mut ast, root) = parse("a(fun (a) { return a; })");
+        Rename.visit_expression_mut(&mut ast, root);
+        assert_eq!(ast.print(root), "(call b (fun (b) (return b)))");
+        let mut folded = AST::new();
+        let folded_root = DropGroups.fold_expression(&ast, root, &mut folded);
+        assert!(folded.same_tree(folded_root, &ast, root));
+    }
+
+    struct DropGroups;
+
+    impl Fold for DropGroups {
+        fn fold_expression(&mut self, from: &AST, id: ExprId, into: &mut AST) -> ExprId {
+            match &from[id] {
+                Expression::Grouping { interior } => self.fold_expression(from, *interior, into),
+                _ => fold_children(self, from, id, into),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let (ast, root) = parse("((1)) - (2 + (x))");
+        let mut folded = AST::new();
+        let folded_root = DropGroups.fold_expression(&ast, root, &mut folded);
+        assert_eq!(folded.print(folded_root), "(- 1 (+ 2 x))");
+        assert_eq!(ast.print(root), "(- (group (group 1)) (group (+ 2 (group x))))");
+    }
+
+    #[test]
+    fn test_fold_program() {
+        let ast = parse_program("/// one\nvar x = (1); if ((x)) print ((x) + 2); else { x = (3); }");
+        let mut folded = AST::new();
+        fold_program(&mut DropGroups, &ast, &mut folded);
+        let printed: Vec<String> = folded.program().iter().map(|statement| folded.print(*statement)).collect();
+        assert_eq!(printed, ["(var x 1)", "(if x (print (+ x 2)) (block (; (= x 3))))"]);
+        assert_eq!(folded.docs(folded.program()[0]), ast.docs(ast.program()[0]));
+        assert_eq!(folded.docs(folded.program()[0]).len(), 1);
+    }
+}
@@ -1,36 +1,232 @@
-mod errors;
-use errors::error;
+#![allow(clippy::needless_return)]
+use diagnostics::{codes, collector, Diagnostic, Renderer};
+use scanner::dot::program_to_dot;
+use scanner::expression::AST;
+use scanner::interpreter::Interpreter;
+use scanner::json::{program_json, Json, ToJson};
+use scanner::parser::Parser;
+use scanner::resolver::Resolver;
+use scanner::statement::StmtId;
+use scanner::source_map::{SharedSourceMap, SourceMap};
 use scanner::*;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::rc::Rc;
+
+const USAGE: &str = "Usage: cargo run <filename> OR cargo run to enter interactive mode
+       defunkter tokens <filename> [--format text|json]
+       defunkter ast <filename> [--format text|json|dot]
+       defunkter --explain <code>";
+
+#[derive(Clone, Copy, PartialEq)]
+enum TokenFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum AstFormat {
+    Text,
+    Json,
+    Dot,
+}
+
+// exit statuses, as in BSD's sysexits.h
+const EXIT_USAGE: i32 = 64;
+const EXIT_DATA_ERROR: i32 = 65;
+const EXIT_NO_INPUT: i32 = 66;
+const EXIT_SOFTWARE: i32 = 70;
 
 fn run_file(filename: &str) {
-    let file = std::fs::read_to_string(filename).expect("Error reading file");
-    let source = file.as_str();
-    run(source);
+    let (scanner, source_map) = open(filename);
+    let diagnostics = collector();
+    let mut interpreter = Interpreter::new();
+    let scanner = scanner.with_sink(diagnostics.clone()).with_interner(interpreter.interner());
+    let mut parser = Parser::new(scanner).with_sink(diagnostics.clone());
+    let program = parser.parse_program();
+    resolve(&interpreter, parser.ast(), &program, &diagnostics);
+    report(&source_map, &diagnostics.borrow());
+    exit_on_errors(&diagnostics.borrow());
+    if let Err(error) = interpreter.interpret(parser.ast(), &program) {
+        report(&source_map, &[*error]);
+        std::process::exit(EXIT_SOFTWARE);
+    }
 }
 fn run_prompt() {
     println!("Welcome to the Lox(rs) interpreter");
+    // One interpreter, AST and source map for the session: variables and functions outlive the
+    // line declaring them, and an error inside an earlier line's function can still show it.
+    let mut interpreter = Interpreter::new();
+    let mut ast = AST::new();
+    let source_map = SourceMap::shared();
     loop {
         print!("> ");
+        let _ = std::io::stdout().flush();
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        run(input.as_str());
+        if std::io::stdin().read_line(&mut input).unwrap() == 0 {
+            break;
+        }
+        let file = source_map.borrow_mut().add("<input>", &input);
+        ast = run(&mut interpreter, ast, Scanner::from_source_map(source_map.clone(), file));
+    }
+}
+// runs one line, returning the AST grown by it
+fn run(interpreter: &mut Interpreter, ast: AST, scanner: Scanner) -> AST {
+    let diagnostics = collector();
+    let source_map = scanner.source_map();
+    let scanner = scanner.with_sink(diagnostics.clone()).with_interner(interpreter.interner());
+    let mut parser = Parser::new(scanner).with_sink(diagnostics.clone()).with_ast(ast);
+    let program = parser.parse_program();
+    resolve(interpreter, parser.ast(), &program, &diagnostics);
+    report(&source_map, &diagnostics.borrow());
+    if !diagnostics.borrow().iter().any(Diagnostic::is_error) {
+        if let Err(error) = interpreter.interpret(parser.ast(), &program) {
+            report(&source_map, &[*error]);
+        }
+    }
+    return parser.into_ast();
+}
+
+// Warns about undeclared variables, against the globals the interpreter already has. A program
+// with syntax errors is not resolved, since its tree is missing pieces.
+fn resolve(interpreter: &Interpreter, ast: &AST, program: &[StmtId], diagnostics: &Rc<RefCell<Vec<Diagnostic>>>) {
+    if diagnostics.borrow().iter().any(Diagnostic::is_error) {
+        return;
+    }
+    Resolver::new()
+        .with_sink(diagnostics.clone())
+        .with_interner(interpreter.interner())
+        .with_globals(interpreter.globals())
+        .resolve(ast, program);
+}
+
+// the library only collects diagnostics; printing them is the binary's job
+fn report(source_map: &SharedSourceMap, diagnostics: &[Diagnostic]) {
+    let renderer = Renderer::for_stderr();
+    for diagnostic in diagnostics {
+        let file = diagnostic.primary_span.file;
+        if source_map.borrow().file(file).contents.is_empty() {
+            // a streamed file; if it cannot be read again the diagnostic has no snippet
+            let _ = source_map.borrow_mut().read_contents(file);
+        }
+        eprintln!("{}\n", renderer.render(diagnostic, &*source_map.borrow()));
+    }
+}
+
+// Opens `filename` to be scanned as a stream, so the file is never held in memory whole. It is
+// registered without its contents; `report` reads them in if there is something to show.
+fn open(filename: &str) -> (Scanner<BufReader<File>>, SharedSourceMap) {
+    let file = match File::open(filename) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", filename, err);
+            std::process::exit(EXIT_NO_INPUT);
+        }
+    };
+    let source_map = SourceMap::shared();
+    let id = source_map.borrow_mut().add(filename, "");
+    let scanner = Scanner::from_reader(BufReader::new(file)).in_file(source_map.clone(), id);
+    return (scanner, source_map);
+}
+
+fn dump_tokens(filename: &str, format: TokenFormat) {
+    let (scanner, source_map) = open(filename);
+    let diagnostics = collector();
+    let scanner = scanner.with_sink(diagnostics.clone());
+    match format {
+        TokenFormat::Text => {
+            for token in scanner {
+                let (line, column) = (token.line, token.column);
+                println!("{}:{} {}", line, column, token.into_string());
+            }
+        }
+        TokenFormat::Json => {
+            let tokens = scanner.map(|token| token.to_json()).collect();
+            println!("{}", Json::Array(tokens));
+        }
+    }
+    report(&source_map, &diagnostics.borrow());
+    exit_on_errors(&diagnostics.borrow());
+}
+
+fn dump_ast(filename: &str, format: AstFormat) {
+    let (scanner, source_map) = open(filename);
+    let diagnostics = collector();
+    let scanner = scanner.with_sink(diagnostics.clone());
+    let mut parser = Parser::new(scanner).with_sink(diagnostics.clone());
+    let program = parser.parse_program();
+    report(&source_map, &diagnostics.borrow());
+    let ast = parser.ast();
+    match format {
+        AstFormat::Text => {
+            for statement in program {
+                println!("{}", ast.print(statement));
+            }
+        }
+        AstFormat::Json => println!("{}", program_json(ast)),
+        AstFormat::Dot => print!("{}", program_to_dot(ast)),
+    }
+    exit_on_errors(&diagnostics.borrow());
+}
+
+// The dumps print what they could make of a broken file, but tools reading them still need to
+// know it was broken.
+fn exit_on_errors(diagnostics: &[Diagnostic]) {
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        std::process::exit(EXIT_DATA_ERROR);
+    }
+}
+
+fn explain(code: &str) {
+    match codes::lookup(code) {
+        Some(entry) => println!("{}: {}\n\n{}", entry.code, entry.title, entry.explanation),
+        None => {
+            eprintln!("error: {} is not a defunkter error code", code);
+            std::process::exit(1);
+        }
     }
 }
-fn run(source: &str) {
-    let mut scanner = Scanner::new(source);
-    scanner.scan_tokens();
-    for token in scanner.tokens.iter() {
-        println!("{:?}", token);
+
+// the value of a trailing `--format <value>`, or `text` without one
+fn format_flag(args: &[String]) -> Option<&str> {
+    match args {
+        [] => Some("text"),
+        [flag, value] if flag == "--format" => Some(value),
+        _ => None,
     }
 }
 
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(EXIT_USAGE);
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() == 2 {
-        run_file(&args[1]);
-    } else if args.len() == 1 {
-        run_prompt();
-    } else {
-        println!("Usage: cargo run <filename> OR cargo run to enter interactive mode");
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("tokens") => {
+            let [_, _, filename, flags @ ..] = args.as_slice() else { usage() };
+            let format = match format_flag(flags) {
+                Some("text") => TokenFormat::Text,
+                Some("json") => TokenFormat::Json,
+                _ => usage(),
+            };
+            dump_tokens(filename, format);
+        }
+        Some("ast") => {
+            let [_, _, filename, flags @ ..] = args.as_slice() else { usage() };
+            let format = match format_flag(flags) {
+                Some("text") => AstFormat::Text,
+                Some("json") => AstFormat::Json,
+                Some("dot") => AstFormat::Dot,
+                _ => usage(),
+            };
+            dump_ast(filename, format);
+        }
+        Some("--explain") if args.len() == 3 => explain(&args[2]),
+        Some(filename) if args.len() == 2 => run_file(filename),
+        None => run_prompt(),
+        _ => usage(),
     }
 }